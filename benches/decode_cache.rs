@@ -0,0 +1,46 @@
+//! Cycles per second with and without the decoded instruction cache.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use chip8::{Chip8, Emulator};
+
+/// Instructions executed per benchmark iteration
+const CYCLES: u64 = 10_000;
+
+/// Draws every font glyph across the screen, forever
+const ROM: [u8; 18] = [
+    0x60, 0x00, // 200: LD V0, 00
+    0x61, 0x00, // 202: LD V1, 00
+    0xF0, 0x29, // 204: LD F, V0
+    0xD0, 0x15, // 206: DRW V0, V1, 5
+    0x70, 0x01, // 208: ADD V0, 01
+    0x81, 0x04, // 20A: ADD V1, V0
+    0x30, 0x40, // 20C: SE V0, 40
+    0x12, 0x04, // 20E: JP 204
+    0x12, 0x00, // 210: JP 200
+];
+
+fn step(c: &mut Criterion) {
+    let mut group = c.benchmark_group("step");
+    group.throughput(Throughput::Elements(CYCLES));
+
+    for cached in [false, true] {
+        let mut chip8 = Chip8::new();
+        chip8.set_decode_cache(cached);
+        chip8.load_rom_bytes(&ROM).unwrap();
+
+        let name = if cached { "cached" } else { "uncached" };
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| {
+                for _ in 0..CYCLES {
+                    chip8.step().unwrap();
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, step);
+criterion_main!(benches);
@@ -0,0 +1,330 @@
+//! Chip-8 sound.
+//!
+//! Chip-8 provides a single tone, which sounds for as long as the sound timer (`ST`) is non-zero.
+//! The frequency of the tone is decided by the interpreter, here it is a square wave.
+//!
+//! [`Audio`] generates one frame (1/60th of a second) of samples at a time and hands them to an
+//! [`AudioSink`]. There is no sink playing to a sound device, the [`WavWriter`] sink records the
+//! samples to a WAV file.
+//!
+//! [`Audio`]: struct.Audio.html
+//! [`AudioSink`]: trait.AudioSink.html
+//! [`WavWriter`]: struct.WavWriter.html
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::{error::Result, Chip8};
+
+/// Number of frames per second, the rate the sound timer counts down at
+const FRAMES_PER_SECOND: u32 = 60;
+
+/// Settings for the generated tone
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AudioConfig {
+    /// Frequency of the tone, in Hz
+    pub frequency: f32,
+    /// Volume of the tone, from `0.0` to `1.0`
+    pub volume: f32,
+    /// Samples per second
+    pub sample_rate: u32,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            frequency: 440.0,
+            volume: 0.25,
+            sample_rate: 44100,
+        }
+    }
+}
+
+/// Destination for generated samples
+pub trait AudioSink {
+    /// Queue mono, signed 16 bit `samples` for playback
+    fn write(&mut self, samples: &[i16]) -> Result<()>;
+
+    /// Flush any buffered samples, called once no more samples will be written
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Sink that discards all samples
+#[derive(Debug, Default)]
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn write(&mut self, _samples: &[i16]) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Square wave generator
+#[derive(Debug)]
+pub struct Beeper {
+    config: AudioConfig,
+    /// Position within the current period, from `0.0` to `1.0`
+    phase: f32,
+    /// Fractional samples carried over between frames
+    remainder: f32,
+}
+
+impl Beeper {
+    /// Create a new `Beeper`
+    pub fn new(config: AudioConfig) -> Self {
+        Self {
+            config,
+            phase: 0.0,
+            remainder: 0.0,
+        }
+    }
+
+    /// Get the current [`AudioConfig`]
+    ///
+    /// [`AudioConfig`]: struct.AudioConfig.html
+    pub fn config(&self) -> AudioConfig {
+        self.config
+    }
+
+    /// Append one frame of samples to `out`, a tone if `active`, otherwise silence
+    pub fn frame(&mut self, active: bool, out: &mut Vec<i16>) {
+        let exact = self.config.sample_rate as f32 / FRAMES_PER_SECOND as f32 + self.remainder;
+        let count = exact as usize;
+        self.remainder = exact - count as f32;
+
+        if !active {
+            // restart the wave so every beep starts the same way
+            self.phase = 0.0;
+            out.extend(std::iter::repeat_n(0, count));
+            return;
+        }
+
+        let amplitude = (self.config.volume.clamp(0.0, 1.0) * i16::MAX as f32) as i16;
+        let step = self.config.frequency / self.config.sample_rate as f32;
+        for _ in 0..count {
+            out.push(if self.phase < 0.5 {
+                amplitude
+            } else {
+                -amplitude
+            });
+            self.phase = (self.phase + step).fract();
+        }
+    }
+}
+
+/// Drives an [`AudioSink`] from the sound timer of a [`Chip8`]
+///
+/// [`AudioSink`]: trait.AudioSink.html
+/// [`Chip8`]: ../struct.Chip8.html
+pub struct Audio {
+    beeper: Beeper,
+    sink: Box<dyn AudioSink>,
+    buf: Vec<i16>,
+}
+
+impl Audio {
+    /// Create a new `Audio` writing to `sink`
+    pub fn new(config: AudioConfig, sink: Box<dyn AudioSink>) -> Self {
+        Self {
+            beeper: Beeper::new(config),
+            sink,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Generate the audio of the frame `chip8` just ran, call once after each frame
+    pub fn frame(&mut self, chip8: &Chip8) -> Result<()> {
+        self.buf.clear();
+        self.beeper.frame(chip8.sounded(), &mut self.buf);
+        self.sink.write(&self.buf)
+    }
+
+    /// Flush the sink, called once no more frames will be generated
+    pub fn finish(&mut self) -> Result<()> {
+        self.sink.finish()
+    }
+}
+
+impl fmt::Debug for Audio {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Audio")
+            .field("beeper", &self.beeper)
+            .finish()
+    }
+}
+
+/// Sink writing 16 bit mono PCM WAV
+#[derive(Debug)]
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    /// Number of bytes of sample data written
+    data_len: u32,
+}
+
+impl WavWriter<BufWriter<File>> {
+    /// Create a WAV file at `path`
+    pub fn create(path: impl AsRef<Path>, sample_rate: u32) -> Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Size of the header, up to the start of the sample data
+    const HEADER_LEN: u32 = 44;
+
+    /// Create a new `WavWriter`, writing the header to `writer`
+    ///
+    /// The lengths in the header are filled in by [`finish`].
+    ///
+    /// [`finish`]: trait.AudioSink.html#method.finish
+    pub fn new(mut writer: W, sample_rate: u32) -> Result<Self> {
+        let channels: u16 = 1;
+        let bits: u16 = 16;
+        let block_align = channels * bits / 8;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(Self::HEADER_LEN - 8).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&bits.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            data_len: 0,
+        })
+    }
+
+    /// Consume the `WavWriter`, returning the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Seek> AudioSink for WavWriter<W> {
+    fn write(&mut self, samples: &[i16]) -> Result<()> {
+        for sample in samples {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_len += (samples.len() * 2) as u32;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_all(&(Self::HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_len.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Emulator;
+    use std::io;
+
+    #[test]
+    fn square_wave() {
+        let config = AudioConfig {
+            frequency: 600.0,
+            volume: 1.0,
+            sample_rate: 4800,
+        };
+        let mut beeper = Beeper::new(config);
+        let mut out = Vec::new();
+
+        beeper.frame(true, &mut out);
+        assert_eq!(out.len(), 80);
+        // 8 samples per period
+        assert_eq!(
+            &out[..8],
+            &[
+                i16::MAX,
+                i16::MAX,
+                i16::MAX,
+                i16::MAX,
+                -i16::MAX,
+                -i16::MAX,
+                -i16::MAX,
+                -i16::MAX
+            ]
+        );
+
+        out.clear();
+        beeper.frame(false, &mut out);
+        assert!(out.iter().all(|s| *s == 0));
+    }
+
+    #[test]
+    fn wav_follows_sound_timer() {
+        let mut chip8 = Chip8::new();
+        chip8.st = 2;
+
+        let config = AudioConfig::default();
+        let cursor = io::Cursor::new(Vec::new());
+        let mut wav = WavWriter::new(cursor, config.sample_rate).unwrap();
+        let mut beeper = Beeper::new(config);
+        let mut buf = Vec::new();
+        for _ in 0..3 {
+            beeper.frame(chip8.sound_active(), &mut buf);
+            chip8.tick_timers();
+        }
+        wav.write(&buf).unwrap();
+        wav.finish().unwrap();
+
+        let bytes = wav.into_inner().into_inner();
+        let data = &bytes[44..];
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(&bytes[40..44], &(735u32 * 3 * 2).to_le_bytes());
+        assert!(data[..735 * 2 * 2].iter().any(|b| *b != 0));
+        assert!(data[735 * 2 * 2..].iter().all(|b| *b == 0));
+    }
+
+    #[derive(Clone, Default)]
+    struct Samples(std::rc::Rc<std::cell::RefCell<Vec<i16>>>);
+
+    impl AudioSink for Samples {
+        fn write(&mut self, samples: &[i16]) -> Result<()> {
+            self.0.borrow_mut().extend_from_slice(samples);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sound_timer_of_one_sounds_for_a_frame() {
+        let mut chip8 = Chip8::new();
+        // 6101: LD V1, 01, F118: LD ST, V1, 1204: JP 204
+        chip8
+            .load_rom_bytes(&[0x61, 0x01, 0xF1, 0x18, 0x12, 0x04])
+            .unwrap();
+
+        let samples = Samples::default();
+        let mut audio = Audio::new(AudioConfig::default(), Box::new(samples.clone()));
+        for _ in 0..2 {
+            chip8.run_frame().unwrap();
+            audio.frame(&chip8).unwrap();
+        }
+
+        let samples = samples.0.borrow();
+        assert_eq!(samples.len(), 735 * 2);
+        assert!(samples[..735].iter().any(|s| *s != 0));
+        assert!(samples[735..].iter().all(|s| *s == 0));
+    }
+}
@@ -0,0 +1,193 @@
+//! Builder for a fully configured [`Chip8`].
+//!
+//! [`Chip8`]: ../struct.Chip8.html
+
+use std::sync::Arc;
+
+use super::{
+    database::Database,
+    error::{Chip8Error, Result},
+    font,
+    memory::{AccessMode, Ram},
+    quirks::{Platform, Quirks},
+    timing::Timing,
+    Chip8, Emulator,
+};
+
+/// Builder for a [`Chip8`], created by [`Chip8::builder`]
+///
+/// [`Chip8`]: ../struct.Chip8.html
+/// [`Chip8::builder`]: ../struct.Chip8.html#method.builder
+#[derive(Debug, Default)]
+pub struct Chip8Builder {
+    rom: Option<Vec<u8>>,
+    platform: Option<Platform>,
+    quirks: Option<Quirks>,
+    clock_hz: Option<f32>,
+    timing: Option<Timing>,
+    font: Option<Vec<u8>>,
+    load_addr: Option<u16>,
+    entry: Option<u16>,
+    access_mode: Option<AccessMode>,
+    protect: bool,
+    database: Option<Arc<Database>>,
+}
+
+impl Chip8Builder {
+    /// Create a new `Chip8Builder`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// ROM to load
+    pub fn rom(mut self, rom: impl Into<Vec<u8>>) -> Self {
+        self.rom = Some(rom.into());
+        self
+    }
+
+    /// ROM database to pick quirks and clock speed from
+    ///
+    /// Quirks and clock speed given to the builder take precedence over the database.
+    pub fn database(mut self, db: Arc<Database>) -> Self {
+        self.database = Some(db);
+        self
+    }
+
+    /// Platform whose instructions are decoded
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.platform = Some(platform);
+        self
+    }
+
+    /// Interpreter quirks to emulate
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    /// CPU clock speed, in instructions per second
+    pub fn clock_hz(mut self, hz: f32) -> Self {
+        self.clock_hz = Some(hz);
+        self
+    }
+
+    /// How long instructions take to execute
+    pub fn timing(mut self, timing: Timing) -> Self {
+        self.timing = Some(timing);
+        self
+    }
+
+    /// Font to load in place of the builtin [`FONT`]
+    ///
+    /// [`FONT`]: ../font/constant.FONT.html
+    pub fn font(mut self, font: impl Into<Vec<u8>>) -> Self {
+        self.font = Some(font.into());
+        self
+    }
+
+    /// Address to load the ROM at
+    pub fn load_addr(mut self, addr: u16) -> Self {
+        self.load_addr = Some(addr);
+        self
+    }
+
+    /// Address to start execution from
+    pub fn entry(mut self, addr: u16) -> Self {
+        self.entry = Some(addr);
+        self
+    }
+
+    /// How out-of-range memory accesses are handled
+    pub fn access_mode(mut self, mode: AccessMode) -> Self {
+        self.access_mode = Some(mode);
+        self
+    }
+
+    /// Enable memory protection, see [`Chip8::set_protection`]
+    ///
+    /// [`Chip8::set_protection`]: ../struct.Chip8.html#method.set_protection
+    pub fn protection(mut self, enabled: bool) -> Self {
+        self.protect = enabled;
+        self
+    }
+
+    /// Build the `Chip8`, loading the font and ROM into memory
+    pub fn build(self) -> Result<Chip8> {
+        let mut chip8 = Chip8::new();
+
+        if let Some(db) = self.database {
+            chip8.set_database(db);
+        }
+        if let Some(mode) = self.access_mode {
+            chip8.ram.set_mode(mode);
+        }
+        if let Some(font) = self.font {
+            // the font has to stay inside the reserved region, clear of the ROM
+            let available = Ram::RESERVED_SIZE - font::FONT_ADDR as usize;
+            if font.len() > available {
+                return Err(Chip8Error::TooLarge {
+                    size: font.len(),
+                    available,
+                });
+            }
+            chip8.load_blob(font::FONT_ADDR, &font)?;
+        }
+        if let Some(addr) = self.load_addr {
+            chip8.set_load_addr(addr);
+        }
+        if let Some(addr) = self.entry {
+            chip8.set_entry(addr);
+        }
+        if let Some(rom) = self.rom {
+            chip8.load_rom_bytes(&rom)?;
+        }
+        // applied after the ROM so they override the database
+        if let Some(platform) = self.platform {
+            chip8.set_platform(platform);
+        }
+        if let Some(quirks) = self.quirks {
+            chip8.set_quirks(quirks);
+        }
+        if let Some(hz) = self.clock_hz {
+            chip8.set_clock_hz(hz);
+        }
+        if let Some(timing) = self.timing {
+            chip8.set_timing(timing);
+        }
+        chip8.set_protection(self.protect);
+
+        Ok(chip8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_from_bytes() {
+        let chip8 = Chip8::builder()
+            .rom(&[0x00, 0xE0][..])
+            .quirks(Quirks::schip())
+            .clock_hz(1000.0)
+            .load_addr(0x600)
+            .build()
+            .unwrap();
+
+        assert_eq!(chip8.ram[0x600], 0x00);
+        assert_eq!(chip8.ram[0x601], 0xE0);
+        assert_eq!(chip8.pc, 0x600);
+        assert_eq!(chip8.quirks(), Quirks::schip());
+        assert_eq!(chip8.clock_hz(), 1000.0);
+        assert_eq!(chip8.ram[font::FONT_ADDR as usize], font::FONT[0]);
+    }
+
+    #[test]
+    fn custom_font() {
+        let chip8 = Chip8::builder().font(vec![0xAA; 10]).build().unwrap();
+        assert_eq!(chip8.ram[font::FONT_ADDR as usize + 9], 0xAA);
+        assert_eq!(chip8.ram[font::FONT_ADDR as usize + 10], font::FONT[10]);
+
+        assert!(Chip8::builder().font(vec![0xAA; 0x200]).build().is_err());
+    }
+}
@@ -0,0 +1,91 @@
+//! Decoded instruction cache.
+//!
+//! Decoding an [`OpCode`] on every cycle is wasted work, as most programs execute the same few
+//! loops over and over. The `DecodeCache` keeps the decoded [`Instruction`] for each address it
+//! has executed. Entries are invalidated whenever either byte of the instruction is written, so
+//! self-modifying code (e.g. through `Fx33` or `Fx55`) still behaves.
+//!
+//! [`OpCode`]: ../opcode/struct.OpCode.html
+//! [`Instruction`]: ../instruction/struct.Instruction.html
+
+use std::fmt;
+
+use super::{instruction::Instruction, memory::Ram};
+
+/// Decoded instructions, by address
+pub struct DecodeCache {
+    entries: Vec<Option<Instruction>>,
+}
+
+impl DecodeCache {
+    /// Create a new, empty `DecodeCache`
+    pub fn new() -> Self {
+        Self {
+            entries: vec![None; Ram::RAM_SIZE],
+        }
+    }
+
+    /// Get the instruction decoded at `addr`, if it is cached
+    pub fn get(&self, addr: u16) -> Option<Instruction> {
+        self.entries.get(addr as usize).copied().flatten()
+    }
+
+    /// Cache `inst`, decoded at `addr`
+    pub fn insert(&mut self, addr: u16, inst: Instruction) {
+        if let Some(entry) = self.entries.get_mut(addr as usize) {
+            *entry = Some(inst);
+        }
+    }
+
+    /// Invalidate the instructions containing the byte at `addr`
+    ///
+    /// Instructions are 2 bytes, so this is the instruction at `addr` and the one before it.
+    pub fn invalidate(&mut self, addr: usize) {
+        let prev = addr.wrapping_sub(1) % Ram::RAM_SIZE;
+        for addr in [addr % Ram::RAM_SIZE, prev] {
+            self.entries[addr] = None;
+        }
+    }
+
+    /// Invalidate every instruction
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+    }
+}
+
+impl fmt::Debug for DecodeCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cached = self.entries.iter().filter(|entry| entry.is_some()).count();
+        f.debug_struct("DecodeCache")
+            .field("cached", &cached)
+            .finish()
+    }
+}
+
+impl Default for DecodeCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcode::OpCode;
+
+    #[test]
+    fn invalidate_both_bytes() {
+        let mut cache = DecodeCache::new();
+        let inst = OpCode::from((0x12, 0x00)).decode();
+        for addr in [0x200, 0x202, 0xFFF] {
+            cache.insert(addr, inst);
+        }
+
+        cache.invalidate(0x201);
+        assert!(cache.get(0x200).is_none());
+        assert!(cache.get(0x202).is_some());
+        cache.invalidate(0x000);
+        assert!(cache.get(0xFFF).is_none());
+        assert!(cache.get(0x1000).is_none());
+    }
+}
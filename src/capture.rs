@@ -0,0 +1,271 @@
+//! Screenshots and recordings of the Chip-8 display.
+//!
+//! Screenshots are written as PNG, or as 1 bit PBM which needs no palette. Recordings are written
+//! as animated GIFs, one frame per call to [`GifRecorder::frame`], which should be made once per
+//! emulated frame (60 per second).
+//!
+//! [`GifRecorder::frame`]: struct.GifRecorder.html#method.frame
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use super::{
+    display::Display,
+    error::{Chip8Error, Result},
+    render::{self, Palette},
+};
+
+/// Image formats screenshots can be saved in
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Format {
+    /// Portable Network Graphics
+    Png,
+    /// Portable bitmap, black and white
+    Pbm,
+}
+
+impl Format {
+    /// Pick the format from the extension of `path`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "png" => Some(Self::Png),
+            "pbm" => Some(Self::Pbm),
+            _ => None,
+        }
+    }
+}
+
+/// Save a screenshot of `display` to `path`, in the format given by its extension
+pub fn screenshot(
+    display: &Display,
+    palette: &Palette,
+    scale: usize,
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    let path = path.as_ref();
+    let format = Format::from_path(path).ok_or_else(|| {
+        Chip8Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("unknown screenshot format `{}`", path.display()),
+        ))
+    })?;
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+        Format::Png => write_png(display, palette, scale, &mut writer)?,
+        Format::Pbm => write_pbm(display, scale, &mut writer)?,
+    }
+    writer.flush()?;
+
+    log::debug!("Saved screenshot to {}", path.display());
+    Ok(())
+}
+
+/// Write `display` as a PNG
+pub fn write_png(
+    display: &Display,
+    palette: &Palette,
+    scale: usize,
+    writer: impl Write,
+) -> Result<()> {
+    let image = render::render(display, palette, scale);
+
+    let mut encoder = png::Encoder::new(writer, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.rgb)?;
+
+    Ok(())
+}
+
+/// Write `display` as a binary PBM, pixels that are on are black
+pub fn write_pbm(display: &Display, scale: usize, mut writer: impl Write) -> Result<()> {
+    let (width, height, pixels) = render::scale(display, scale);
+
+    write!(writer, "P4\n{} {}\n", width, height)?;
+    for row in pixels.chunks(width) {
+        // rows are packed 8 pixels to a byte, padded to a whole byte
+        let packed: Vec<u8> = row
+            .chunks(8)
+            .map(|bits| {
+                bits.iter()
+                    .enumerate()
+                    .filter(|(_, p)| **p != 0)
+                    .fold(0, |byte, (i, _)| byte | (0x80 >> i))
+            })
+            .collect();
+        writer.write_all(&packed)?;
+    }
+
+    Ok(())
+}
+
+/// Records the display as an animated GIF
+///
+/// Identical consecutive frames are merged into one longer frame, to keep recordings small.
+pub struct GifRecorder<W: Write> {
+    encoder: gif::Encoder<W>,
+    width: u16,
+    height: u16,
+    scale: usize,
+    /// Scaled pixels of the frame waiting to be written
+    pending: Option<Vec<u8>>,
+    /// Frames recorded so far
+    frames: u64,
+    /// Hundredths of a second written so far
+    written: u64,
+}
+
+impl GifRecorder<BufWriter<File>> {
+    /// Create a GIF file at `path`, recording a display of `width`x`height` pixels
+    pub fn create(
+        path: impl AsRef<Path>,
+        width: usize,
+        height: usize,
+        palette: &Palette,
+        scale: usize,
+    ) -> Result<Self> {
+        Self::new(
+            BufWriter::new(File::create(path)?),
+            width,
+            height,
+            palette,
+            scale,
+        )
+    }
+}
+
+impl<W: Write> GifRecorder<W> {
+    /// Create a new `GifRecorder` writing to `writer`, recording a display of `width`x`height`
+    /// pixels
+    ///
+    /// Fails if the scaled up display is larger than a GIF can be, 65535 pixels a side.
+    pub fn new(
+        writer: W,
+        width: usize,
+        height: usize,
+        palette: &Palette,
+        scale: usize,
+    ) -> Result<Self> {
+        let scale = scale.max(1);
+        let side = |pixels: usize| {
+            pixels
+                .checked_mul(scale)
+                .and_then(|scaled| u16::try_from(scaled).ok())
+                .ok_or_else(|| {
+                    Chip8Error::Io(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("scale {} is too large for a GIF", scale),
+                    ))
+                })
+        };
+        let (width, height) = (side(width)?, side(height)?);
+        let global_palette: Vec<u8> = palette.colors().into_iter().flatten().collect();
+
+        let mut encoder = gif::Encoder::new(writer, width, height, &global_palette)?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        Ok(Self {
+            encoder,
+            width,
+            height,
+            scale,
+            pending: None,
+            frames: 0,
+            written: 0,
+        })
+    }
+
+    /// Record the current contents of `display` as the next frame
+    pub fn frame(&mut self, display: &Display) -> Result<()> {
+        let (_, _, pixels) = render::scale(display, self.scale);
+
+        if self.pending.as_ref() != Some(&pixels) {
+            self.flush()?;
+            self.pending = Some(pixels);
+        }
+        self.frames += 1;
+
+        Ok(())
+    }
+
+    /// Write the last frame and the end of the GIF, returning the underlying writer
+    pub fn finish(mut self) -> Result<W> {
+        self.flush()?;
+        log::debug!("Recorded {} frames", self.frames);
+        Ok(self.encoder.into_inner()?)
+    }
+
+    /// Write the pending frame, which lasts until the current frame
+    fn flush(&mut self) -> Result<()> {
+        let pixels = match self.pending.take() {
+            Some(pixels) => pixels,
+            None => return Ok(()),
+        };
+
+        // GIF delays are in hundredths of a second, so round against the total time elapsed to
+        // keep the recording from drifting
+        let end = self.frames * 100 / 60;
+        let delay = end.saturating_sub(self.written).max(1);
+        self.written += delay;
+
+        let mut frame = gif::Frame::from_indexed_pixels(self.width, self.height, pixels, None);
+        frame.delay = delay as u16;
+        self.encoder.write_frame(&frame)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pbm() {
+        let mut display = Display::new();
+        display.draw_sprite(0, 0, &[0xA0], false);
+
+        let mut out = Vec::new();
+        write_pbm(&display, 2, &mut out).unwrap();
+
+        let header = b"P4\n128 64\n";
+        assert_eq!(&out[..header.len()], header);
+        let data = &out[header.len()..];
+        assert_eq!(data.len(), 16 * 64);
+        assert_eq!(&data[..2], &[0xCC, 0x00]);
+        assert_eq!(&data[16..18], &[0xCC, 0x00]);
+        assert!(data[32..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn gif_merges_frames() {
+        let mut display = Display::new();
+        let mut gif = GifRecorder::new(Vec::new(), 64, 32, &Palette::default(), 1).unwrap();
+        for _ in 0..60 {
+            gif.frame(&display).unwrap();
+        }
+        display.draw_sprite(0, 0, &[0xFF], false);
+        gif.frame(&display).unwrap();
+        let bytes = gif.finish().unwrap();
+
+        let mut decoder = gif::DecodeOptions::new().read_info(&bytes[..]).unwrap();
+        let first = decoder.read_next_frame().unwrap().unwrap().delay;
+        assert_eq!(first, 100);
+        let second = decoder.read_next_frame().unwrap().unwrap().delay;
+        assert_eq!(second, 1);
+        assert!(decoder.read_next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn gif_too_large() {
+        let palette = Palette::default();
+        assert!(GifRecorder::new(Vec::new(), 64, 32, &palette, 1023).is_ok());
+        assert!(GifRecorder::new(Vec::new(), 64, 32, &palette, 1024).is_err());
+        assert!(GifRecorder::new(Vec::new(), 64, 32, &palette, usize::MAX).is_err());
+    }
+}
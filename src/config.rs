@@ -0,0 +1,384 @@
+//! Emulator configuration file.
+//!
+//! Settings are read from a TOML file, by default `chip8/config.toml` in the user's config
+//! directory (`$XDG_CONFIG_HOME` on Linux). Every setting is optional, and settings for a single
+//! ROM can be given in a `[rom."<name>"]` table, keyed by the file name or SHA-1 of the ROM:
+//!
+//! ```toml
+//! platform = "schip"
+//! clock_hz = 700
+//! timing = "fixed"
+//! palette = "amber"
+//! scale = 8
+//!
+//! [quirks]
+//! vf_reset = false
+//!
+//! [keymap]
+//! 5 = ["W", "Up"]
+//!
+//! [machine]
+//! protect = true
+//!
+//! [audio]
+//! frequency = 660
+//! volume = 0.1
+//!
+//! [rom."BLINKY.ch8"]
+//! clock_hz = 1000
+//! timing = "vip"
+//! palette = ["#000000", "#00FF00"]
+//! ```
+//!
+//! Settings are applied from least to most specific: the top level settings, then the ROM
+//! database, then the settings for the ROM, and finally command line flags.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer};
+
+use super::{
+    audio::AudioConfig,
+    error::Result,
+    quirks::{Platform, Quirks},
+    render::Palette,
+    timing::Timing,
+    Chip8,
+};
+
+/// Contents of a configuration file
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Settings for all ROMs
+    #[serde(flatten)]
+    pub settings: Settings,
+    /// Emulator settings
+    #[serde(default)]
+    pub machine: MachineSettings,
+    /// Tone settings
+    #[serde(default)]
+    pub audio: AudioSettings,
+    /// Settings for single ROMs, by file name or SHA-1
+    #[serde(default)]
+    pub rom: BTreeMap<String, Settings>,
+}
+
+/// Settings which can be given for all ROMs, or for a single ROM
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Platform to emulate, which also gives the default quirks
+    #[serde(deserialize_with = "parse")]
+    pub platform: Option<Platform>,
+    /// CPU clock speed, in instructions per second
+    pub clock_hz: Option<f32>,
+    /// Timing model, `fixed` or `vip`
+    #[serde(deserialize_with = "parse")]
+    pub timing: Option<Timing>,
+    /// Individual quirks, overriding the platform
+    pub quirks: QuirkSettings,
+    /// Host keys for each keypad key, e.g. `5 = ["W", "Up"]`
+    pub keymap: BTreeMap<String, Vec<String>>,
+    /// Display colours
+    pub palette: Option<PaletteSetting>,
+    /// Screen pixels per Chip-8 pixel
+    pub scale: Option<usize>,
+}
+
+/// Quirks to override, any not given are left as they are
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuirkSettings {
+    pub shift_uses_vy: Option<bool>,
+    pub load_store_increments_i: Option<bool>,
+    pub jump_uses_vx: Option<bool>,
+    pub vf_reset: Option<bool>,
+    pub clip_sprites: Option<bool>,
+    pub display_wait: Option<bool>,
+}
+
+/// Emulator settings, which apply to all ROMs
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MachineSettings {
+    /// Trap writes to reserved memory and execution outside of the loaded code
+    pub protect: Option<bool>,
+}
+
+/// Tone settings to override, any not given are left as they are
+#[derive(Debug, Default, Copy, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioSettings {
+    pub frequency: Option<f32>,
+    pub volume: Option<f32>,
+    pub sample_rate: Option<u32>,
+}
+
+/// A palette, as a theme name, a comma separated string of colours, or a list of colours
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum PaletteSetting {
+    Named(String),
+    Colors(Vec<String>),
+}
+
+impl Config {
+    /// Path of the default configuration file, if there is a config directory
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("chip8").join("config.toml"))
+    }
+
+    /// Parse a configuration file from TOML
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        Ok(toml::from_str(toml)?)
+    }
+
+    /// Read and parse the configuration file at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let config = Self::from_toml(&fs::read_to_string(path.as_ref())?)?;
+        log::debug!("Loaded config from {}", path.as_ref().display());
+        Ok(config)
+    }
+
+    /// Read the default configuration file, or use an empty `Config` if there isn't one
+    pub fn load_default() -> Result<Self> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::open(path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Get the settings for the ROM with the file `name` and hash `sha1`, if there are any
+    ///
+    /// Settings for the file name are merged over settings for the hash.
+    pub fn rom_settings(&self, name: &str, sha1: &str) -> Settings {
+        let by_hash = self
+            .rom
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(sha1))
+            .map(|(_, settings)| settings.clone())
+            .unwrap_or_default();
+
+        match self.rom.get(name) {
+            Some(by_name) => by_hash.merge(by_name),
+            None => by_hash,
+        }
+    }
+
+    /// Get the [`AudioConfig`] with the tone settings applied
+    ///
+    /// [`AudioConfig`]: ../audio/struct.AudioConfig.html
+    pub fn audio(&self) -> AudioConfig {
+        let defaults = AudioConfig::default();
+        AudioConfig {
+            frequency: self.audio.frequency.unwrap_or(defaults.frequency),
+            volume: self.audio.volume.unwrap_or(defaults.volume),
+            sample_rate: self.audio.sample_rate.unwrap_or(defaults.sample_rate),
+        }
+    }
+}
+
+impl Settings {
+    /// Combine with `other`, whose settings take precedence
+    pub fn merge(&self, other: &Settings) -> Settings {
+        let mut keymap = self.keymap.clone();
+        keymap.extend(other.keymap.clone());
+
+        Settings {
+            platform: other.platform.or(self.platform),
+            clock_hz: other.clock_hz.or(self.clock_hz),
+            timing: other.timing.or(self.timing),
+            quirks: self.quirks.merge(&other.quirks),
+            keymap,
+            palette: other.palette.clone().or_else(|| self.palette.clone()),
+            scale: other.scale.or(self.scale),
+        }
+    }
+
+    /// Apply the platform, quirks, clock speed and timing to `chip8`, leaving any not given as
+    /// they are
+    pub fn apply(&self, chip8: &mut Chip8) {
+        if let Some(platform) = self.platform {
+            chip8.set_platform(platform);
+        }
+        let mut quirks = chip8.quirks();
+        self.apply_quirks(&mut quirks);
+        chip8.set_quirks(quirks);
+        if let Some(hz) = self.clock_hz {
+            chip8.set_clock_hz(hz);
+        }
+        if let Some(timing) = self.timing {
+            chip8.set_timing(timing);
+        }
+    }
+
+    /// Apply the platform and quirks to `quirks`
+    pub fn apply_quirks(&self, quirks: &mut Quirks) {
+        if let Some(platform) = self.platform {
+            *quirks = platform.quirks();
+        }
+        self.quirks.apply(quirks);
+    }
+
+    /// Parse the palette, if one was given
+    pub fn palette(&self) -> Option<std::result::Result<Palette, String>> {
+        self.palette.as_ref().map(|palette| match palette {
+            PaletteSetting::Named(s) => s.parse(),
+            PaletteSetting::Colors(colors) => Palette::from_hex(colors),
+        })
+    }
+}
+
+impl QuirkSettings {
+    /// Combine with `other`, whose settings take precedence
+    pub fn merge(&self, other: &QuirkSettings) -> QuirkSettings {
+        QuirkSettings {
+            shift_uses_vy: other.shift_uses_vy.or(self.shift_uses_vy),
+            load_store_increments_i: other
+                .load_store_increments_i
+                .or(self.load_store_increments_i),
+            jump_uses_vx: other.jump_uses_vx.or(self.jump_uses_vx),
+            vf_reset: other.vf_reset.or(self.vf_reset),
+            clip_sprites: other.clip_sprites.or(self.clip_sprites),
+            display_wait: other.display_wait.or(self.display_wait),
+        }
+    }
+
+    /// Override the quirks that were given in `quirks`
+    pub fn apply(&self, quirks: &mut Quirks) {
+        let settings = [
+            (self.shift_uses_vy, &mut quirks.shift_uses_vy),
+            (
+                self.load_store_increments_i,
+                &mut quirks.load_store_increments_i,
+            ),
+            (self.jump_uses_vx, &mut quirks.jump_uses_vx),
+            (self.vf_reset, &mut quirks.vf_reset),
+            (self.clip_sprites, &mut quirks.clip_sprites),
+            (self.display_wait, &mut quirks.display_wait),
+        ];
+        for (setting, quirk) in settings {
+            if let Some(enabled) = setting {
+                *quirk = enabled;
+            }
+        }
+    }
+}
+
+/// Deserialize an optional value using its `FromStr` implementation
+fn parse<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| s.parse().map_err(de::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Emulator;
+
+    const CONFIG: &str = r##"
+        platform = "schip"
+        clock_hz = 700
+        palette = "amber"
+
+        [quirks]
+        vf_reset = true
+
+        [keymap]
+        5 = ["W", "Up"]
+
+        [machine]
+        protect = true
+
+        [audio]
+        volume = 0.1
+
+        [rom."BLINKY.ch8"]
+        clock_hz = 1000
+        timing = "vip"
+        palette = ["#000000", "#00FF00"]
+        keymap = { 5 = ["K"] }
+
+        [rom.DA39A3EE5E6B4B0D3255BFEF95601890AFD80709]
+        platform = "chip8"
+        clock_hz = 500
+    "##;
+
+    #[test]
+    fn parse_config() {
+        let config = Config::from_toml(CONFIG).unwrap();
+        assert_eq!(config.settings.platform, Some(Platform::Schip));
+        assert_eq!(config.settings.clock_hz, Some(700.0));
+        assert_eq!(config.settings.keymap["5"], vec!["W", "Up"]);
+        assert_eq!(config.machine.protect, Some(true));
+        assert_eq!(config.audio().volume, 0.1);
+        assert_eq!(config.audio().frequency, AudioConfig::default().frequency);
+
+        let mut quirks = Quirks::chip8();
+        config.settings.apply_quirks(&mut quirks);
+        assert_eq!(
+            quirks,
+            Quirks {
+                vf_reset: true,
+                ..Quirks::schip()
+            }
+        );
+
+        assert!(Config::from_toml("platform = \"nes\"").is_err());
+        assert!(Config::from_toml("clock = 700").is_err());
+        assert!(Config::from_toml("[machine]\nprotect = 1").is_err());
+    }
+
+    #[test]
+    fn rom_overrides() {
+        let config = Config::from_toml(CONFIG).unwrap();
+        let sha1 = "da39a3ee5e6b4b0d3255bfef95601890afd80709";
+
+        let settings = config
+            .settings
+            .merge(&config.rom_settings("BLINKY.ch8", sha1));
+        assert_eq!(settings.platform, Some(Platform::Chip8));
+        assert_eq!(settings.clock_hz, Some(1000.0));
+        assert_eq!(settings.timing, Some(Timing::Vip));
+        assert_eq!(settings.keymap["5"], vec!["K"]);
+        assert_eq!(
+            settings.palette().unwrap(),
+            Ok(Palette::new([0, 0, 0], [0, 0xFF, 0]))
+        );
+
+        let settings = config.settings.merge(&config.rom_settings("PONG", "00"));
+        assert_eq!(settings, config.settings);
+    }
+
+    #[test]
+    fn flags_take_precedence() {
+        let config = Config::from_toml(CONFIG).unwrap();
+        let mut chip8 = Chip8::new();
+        config.settings.apply(&mut chip8);
+        chip8.load_rom_bytes(&[0x12, 0x00]).unwrap();
+        config.rom_settings("BLINKY.ch8", "").apply(&mut chip8);
+        assert_eq!(chip8.clock_hz(), 1000.0);
+        assert_eq!(chip8.timing(), Timing::Vip);
+
+        let flags = Settings {
+            platform: Some(Platform::Chip8),
+            clock_hz: Some(600.0),
+            ..Settings::default()
+        };
+        flags.apply(&mut chip8);
+        assert_eq!(chip8.quirks(), Quirks::chip8());
+        assert_eq!(chip8.clock_hz(), 600.0);
+        assert_eq!(chip8.timing(), Timing::Vip);
+    }
+}
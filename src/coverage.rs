@@ -0,0 +1,298 @@
+//! Code coverage of ROM execution.
+//!
+//! While coverage is enabled (see [`Chip8::set_coverage`]), every executed address is counted,
+//! and for skip instructions (`SE`, `SNE`, `SKP` and `SKNP`) how often the skip was taken and
+//! not taken. The result can be written as:
+//!
+//! * an annotated disassembly, one line per instruction or word of data of the ROM, in the style
+//!   of `gcov`: the execution count, `#####` for instructions which are reachable but were never executed, or
+//!   `-` for words which aren't code
+//! * an lcov tracefile, whose line numbers are the lines of the annotated disassembly, so tools
+//!   like `genhtml` can render it
+//!
+//! Words count as code if they were executed, or if [`scan`] finds them reachable.
+//!
+//! [`Chip8::set_coverage`]: ../struct.Chip8.html#method.set_coverage
+//! [`scan`]: ../scan/index.html
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+
+use super::{error::Result, opcode::OpCode, optable, scan};
+
+/// Execution counts gathered while coverage is enabled
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Coverage {
+    /// Times each address was executed
+    executed: BTreeMap<u16, u64>,
+    /// Times each skip was taken and not taken, by address
+    branches: BTreeMap<u16, (u64, u64)>,
+}
+
+/// An instruction or a word of data of the ROM, as annotated
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Line {
+    /// Address of the word, or of the first word of the instruction
+    pub addr: u16,
+    /// The word, as an opcode
+    pub opcode: OpCode,
+    /// Times the word was executed, `None` if it isn't code
+    pub count: Option<u64>,
+    /// Times the skip at the word was taken and not taken, if it is a skip
+    pub branch: Option<(u64, u64)>,
+}
+
+/// Totals of a coverage report
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct Summary {
+    /// Instructions
+    pub lines: usize,
+    /// Instructions executed at least once
+    pub lines_hit: usize,
+    /// Branches, two per skip instruction
+    pub branches: usize,
+    /// Branches followed at least once
+    pub branches_hit: usize,
+}
+
+impl Coverage {
+    /// Create a new, empty `Coverage`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count an execution of the instruction at `addr`
+    pub fn record(&mut self, addr: u16) {
+        *self.executed.entry(addr).or_insert(0) += 1;
+    }
+
+    /// Count the skip at `addr` being `taken` or not
+    pub fn record_branch(&mut self, addr: u16, taken: bool) {
+        let branch = self.branches.entry(addr).or_insert((0, 0));
+        if taken {
+            branch.0 += 1;
+        } else {
+            branch.1 += 1;
+        }
+    }
+
+    /// Times the instruction at `addr` was executed
+    pub fn executed(&self, addr: u16) -> u64 {
+        self.executed.get(&addr).copied().unwrap_or(0)
+    }
+
+    /// Annotate each instruction and word of data of `rom`, loaded at `load_addr`
+    pub fn lines(&self, rom: &[u8], load_addr: u16) -> Vec<Line> {
+        let reachable = scan::scan(rom, load_addr).reachable;
+        let mut lines = Vec::new();
+        let mut words = rom.chunks_exact(2).enumerate();
+        while let Some((i, word)) = words.next() {
+            let addr = load_addr + 2 * i as u16;
+            let opcode = OpCode::from((word[0], word[1]));
+            let count = match self.executed.get(&addr) {
+                Some(count) => Some(*count),
+                None if reachable.contains(&addr) => Some(0),
+                None => None,
+            };
+            let branch = if count.is_some() && is_skip(opcode) {
+                Some(self.branches.get(&addr).copied().unwrap_or((0, 0)))
+            } else {
+                None
+            };
+            if count.is_some() {
+                // the operand words of a longer instruction are part of its line
+                let len = optable::lookup(opcode).map_or(2, |def| def.len);
+                for _ in 1..len / 2 {
+                    words.next();
+                }
+            }
+            lines.push(Line {
+                addr,
+                opcode,
+                count,
+                branch,
+            });
+        }
+        lines
+    }
+
+    /// Totals of the report for `rom`, loaded at `load_addr`
+    pub fn summary(&self, rom: &[u8], load_addr: u16) -> Summary {
+        let mut summary = Summary::default();
+        for line in self.lines(rom, load_addr) {
+            if let Some(count) = line.count {
+                summary.lines += 1;
+                summary.lines_hit += (count > 0) as usize;
+            }
+            if let Some((taken, not_taken)) = line.branch {
+                summary.branches += 2;
+                summary.branches_hit += (taken > 0) as usize + (not_taken > 0) as usize;
+            }
+        }
+        summary
+    }
+
+    /// Write the annotated disassembly of `rom`, loaded at `load_addr`
+    pub fn write_annotated(
+        &self,
+        rom: &[u8],
+        load_addr: u16,
+        mut writer: impl Write,
+    ) -> Result<()> {
+        for line in self.lines(rom, load_addr) {
+            let count = match line.count {
+                Some(0) => "#####".to_string(),
+                Some(count) => count.to_string(),
+                None => "-".to_string(),
+            };
+            write!(writer, "{:>9}: {:03X}: ", count, line.addr)?;
+            if line.count.is_some() {
+                write!(writer, "{}", line.opcode.decode())?;
+            } else {
+                write!(writer, "({:04X})", line.opcode)?;
+            }
+            if let Some((taken, not_taken)) = line.branch {
+                write!(writer, "  [taken {}, not taken {}]", taken, not_taken)?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Write an lcov tracefile for `rom`, loaded at `load_addr`, whose annotated disassembly is
+    /// at `source`
+    pub fn write_lcov(
+        &self,
+        source: &str,
+        rom: &[u8],
+        load_addr: u16,
+        mut writer: impl Write,
+    ) -> Result<()> {
+        writeln!(writer, "TN:")?;
+        writeln!(writer, "SF:{}", source)?;
+
+        let lines = self.lines(rom, load_addr);
+        for (line_no, line) in (1..).zip(&lines) {
+            if let Some((taken, not_taken)) = line.branch {
+                let hit = line.count.unwrap_or(0) > 0;
+                for (idx, count) in [taken, not_taken].iter().enumerate() {
+                    let count = if hit {
+                        count.to_string()
+                    } else {
+                        "-".to_string()
+                    };
+                    writeln!(writer, "BRDA:{},0,{},{}", line_no, idx, count)?;
+                }
+            }
+        }
+        for (line_no, line) in (1..).zip(&lines) {
+            if let Some(count) = line.count {
+                writeln!(writer, "DA:{},{}", line_no, count)?;
+            }
+        }
+
+        let summary = self.summary(rom, load_addr);
+        writeln!(writer, "BRF:{}", summary.branches)?;
+        writeln!(writer, "BRH:{}", summary.branches_hit)?;
+        writeln!(writer, "LF:{}", summary.lines)?;
+        writeln!(writer, "LH:{}", summary.lines_hit)?;
+        writeln!(writer, "end_of_record")?;
+        Ok(())
+    }
+}
+
+/// Whether `opcode` is a conditional skip
+pub fn is_skip(opcode: OpCode) -> bool {
+    matches!(
+        opcode.to_match_tuple(),
+        (0x3, ..)
+            | (0x4, ..)
+            | (0x5, _, _, 0x0)
+            | (0x9, _, _, 0x0)
+            | (0xE, _, 0x9, 0xE)
+            | (0xE, _, 0xA, 0x1)
+    )
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = |hit: usize, total: usize| {
+            if total == 0 {
+                100.0
+            } else {
+                hit as f64 * 100.0 / total as f64
+            }
+        };
+        write!(
+            f,
+            "instructions {}/{} ({:.1}%), branches {}/{} ({:.1}%)",
+            self.lines_hit,
+            self.lines,
+            percent(self.lines_hit, self.lines),
+            self.branches_hit,
+            self.branches,
+            percent(self.branches_hit, self.branches)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chip8, Emulator};
+
+    const ROM: [u8; 10] = [
+        0x70, 0x01, // 200: ADD V0, 01
+        0x30, 0x03, // 202: SE V0, 03
+        0x12, 0x00, // 204: JP 200
+        0x12, 0x06, // 206: JP 206
+        0xAB, 0xCD, // 208: data
+    ];
+
+    #[test]
+    fn skips_and_lines() {
+        let mut chip8 = Chip8::new();
+        chip8.set_coverage(true);
+        chip8.load_rom_bytes(&ROM).unwrap();
+        for _ in 0..9 {
+            chip8.step().unwrap();
+        }
+
+        let coverage = chip8.coverage().unwrap();
+        let lines = coverage.lines(&ROM, 0x200);
+        assert_eq!(lines[0].count, Some(3));
+        assert_eq!(lines[1].branch, Some((1, 2)));
+        assert_eq!(lines[3].count, Some(1));
+        assert_eq!(lines[4].count, None);
+        assert_eq!(
+            coverage.summary(&ROM, 0x200),
+            Summary {
+                lines: 4,
+                lines_hit: 4,
+                branches: 2,
+                branches_hit: 2
+            }
+        );
+
+        let mut lcov = Vec::new();
+        coverage
+            .write_lcov("rom.asm", &ROM, 0x200, &mut lcov)
+            .unwrap();
+        let lcov = String::from_utf8(lcov).unwrap();
+        assert!(lcov.contains("BRDA:2,0,0,1\nBRDA:2,0,1,2\n"));
+        assert!(lcov.contains("DA:4,1\nBRF:2\nBRH:2\nLF:4\nLH:4\n"));
+    }
+
+    #[test]
+    fn long_instruction_line() {
+        let rom = [
+            0xF0, 0x00, 0x03, 0x00, // 200: LD I, LONG 0300
+            0x12, 0x04, // 204: JP 204
+        ];
+        let lines = Coverage::new().lines(&rom, 0x200);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].count, Some(0));
+        assert_eq!(lines[1].addr, 0x204);
+    }
+}
@@ -0,0 +1,965 @@
+//! Debug Adapter Protocol server.
+//!
+//! [`DapServer`] lets editors which speak the Debug Adapter Protocol (e.g. VS Code) debug a ROM.
+//! Editors usually start the adapter themselves and talk to it over stdin and stdout:
+//!
+//! ```text
+//! chip8 dap
+//! ```
+//!
+//! The `launch` request takes the following arguments, all but `program` optional:
+//!
+//! * `program` - path of the ROM
+//! * `symbols` - path of a [symbol file], to name addresses, and to show and set breakpoints in
+//!   assembler source
+//! * `platform` - platform to take the quirks from, e.g. `schip`
+//! * `clockHz` - CPU clock speed, in instructions per second
+//! * `timing` - `fixed` or `vip`
+//! * `loadAddress` - address to load the ROM at
+//! * `stopOnEntry` - stop before the first instruction
+//! * `protect` - stop on writes to the reserved interpreter memory and execution outside of the
+//!   ROM
+//!
+//! Source and instruction breakpoints, stepping over, into and out of subroutines, pausing, the
+//! call stack, variables (registers, the stack and memory at `I`), memory reads and disassembly
+//! are supported. Memory can be written, and viewed and edited from the debug console with the
+//! [`Monitor`] commands, e.g. `x 200`. While running, frames are paced to real time. The last
+//! [`history::DEFAULT_CAPACITY`] instructions can be stepped back through, one at a time or back
+//! to the previous breakpoint.
+//!
+//! [`DapServer`]: struct.DapServer.html
+//! [`history::DEFAULT_CAPACITY`]: ../history/constant.DEFAULT_CAPACITY.html
+//! [`Monitor`]: ../hexdump/struct.Monitor.html
+//! [symbol file]: ../symbols/index.html
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use super::{
+    error::{Chip8Error, Result},
+    hexdump::Monitor,
+    history,
+    memory::Ram,
+    opcode::OpCode,
+    quirks::Platform,
+    symbols::{LineMap, Symbols},
+    timing::Timing,
+    Chip8, Emulator,
+};
+
+/// The only thread
+const THREAD_ID: i64 = 1;
+/// Real time between frames while running
+const FRAME_TIME: Duration = Duration::from_micros(16_667);
+/// Bytes of memory at `I` shown as variables
+const MEMORY_VIEW: u16 = 0x40;
+
+/// `variablesReference` of each scope
+const REGISTERS_REF: i64 = 1;
+const STACK_REF: i64 = 2;
+const MEMORY_REF: i64 = 3;
+
+/// How a running program stops by itself
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Resume {
+    /// Only at breakpoints
+    Continue,
+    /// At `addr` (the instruction after a `CALL`, or a return address), reported as a step
+    Until(u16),
+}
+
+/// Debug adapter serving a single debug session, see the [module documentation](index.html)
+#[derive(Debug, Default)]
+pub struct DapServer {
+    chip8: Option<Chip8>,
+    /// Breakpoints set by source file
+    source_breakpoints: BTreeMap<PathBuf, Vec<u16>>,
+    /// Breakpoints set by address
+    instruction_breakpoints: BTreeSet<u16>,
+    /// Whether to stop before the first instruction
+    stop_on_entry: bool,
+    /// Set while the program is running
+    running: Option<Resume>,
+    /// Address execution was resumed from, cleared once the first instruction is reached, so a
+    /// breakpoint there doesn't stop the program straight away
+    resumed_from: Option<u16>,
+    /// Interpreter of debug console commands
+    monitor: Monitor,
+    /// Sequence number of the next message sent
+    seq: i64,
+}
+
+impl DapServer {
+    /// Create a new `DapServer`, waiting for a `launch` request
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve requests read from `reader` until the session ends, writing to `writer`
+    pub fn serve<R, W>(&mut self, reader: R, mut writer: W) -> Result<()>
+    where
+        R: BufRead + Send + 'static,
+        W: Write,
+    {
+        let requests = spawn_reader(reader);
+        let mut next_frame = Instant::now();
+        loop {
+            let request = if self.running.is_some() {
+                match requests.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => break,
+                }
+            } else {
+                next_frame = Instant::now();
+                match requests.recv() {
+                    Ok(request) => Some(request),
+                    Err(_) => break,
+                }
+            };
+            if let Some(request) = request {
+                if !self.handle(&request?, &mut writer)? {
+                    break;
+                }
+            }
+
+            if let Some(resume) = self.running {
+                self.run_frame(resume, &mut writer)?;
+                next_frame += FRAME_TIME;
+                let now = Instant::now();
+                if next_frame > now {
+                    thread::sleep(next_frame - now);
+                } else {
+                    next_frame = now;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a request, returning whether the session carries on
+    fn handle(&mut self, request: &Value, out: &mut impl Write) -> Result<bool> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let args = &request["arguments"];
+        log::debug!("DAP: {}", command);
+
+        if self.chip8.is_none() && !matches!(command, "initialize" | "launch" | "disconnect") {
+            return self.fail(out, request, "no program launched").map(|_| true);
+        }
+
+        let body = match command {
+            "initialize" => {
+                self.respond(out, request, capabilities())?;
+                self.event(out, "initialized", json!({}))?;
+                return Ok(true);
+            }
+            "launch" => match self.launch(args) {
+                Ok(()) => json!({}),
+                Err(e) => return self.fail(out, request, &e.to_string()).map(|_| true),
+            },
+            "configurationDone" => {
+                self.respond(out, request, json!({}))?;
+                if self.stop_on_entry {
+                    self.stopped(out, "entry", None)?;
+                } else {
+                    self.resume(Resume::Continue);
+                }
+                return Ok(true);
+            }
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => json!({ "breakpoints": [] }),
+            "threads" => json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] }),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => scopes(),
+            "variables" => self.variables(args),
+            "readMemory" => self.read_memory(args),
+            "writeMemory" => match self.write_memory(args) {
+                Ok(body) => body,
+                Err(e) => return self.fail(out, request, &e).map(|_| true),
+            },
+            "evaluate" => match self.evaluate(args) {
+                Ok(body) => body,
+                Err(e) => return self.fail(out, request, &e).map(|_| true),
+            },
+            "disassemble" => self.disassemble(args),
+            "continue" => {
+                self.resume(Resume::Continue);
+                json!({ "allThreadsContinued": true })
+            }
+            "next" => {
+                self.step_over(out)?;
+                json!({})
+            }
+            "stepIn" => {
+                self.respond(out, request, json!({}))?;
+                self.step_in(out)?;
+                return Ok(true);
+            }
+            "stepBack" => {
+                self.respond(out, request, json!({}))?;
+                let reason = if self.chip8_mut().step_back() {
+                    "step"
+                } else {
+                    "entry"
+                };
+                self.stopped(out, reason, None)?;
+                return Ok(true);
+            }
+            "reverseContinue" => {
+                self.respond(out, request, json!({}))?;
+                let breakpoints = self.breakpoints();
+                let reason = if self
+                    .chip8_mut()
+                    .run_back_until(|pc| breakpoints.contains(&pc))
+                {
+                    "breakpoint"
+                } else {
+                    "entry"
+                };
+                self.stopped(out, reason, None)?;
+                return Ok(true);
+            }
+            "stepOut" => {
+                let chip8 = self.chip8();
+                if chip8.sp == 0 {
+                    return self.fail(out, request, "not in a subroutine").map(|_| true);
+                }
+                let ret = chip8.stack[chip8.sp as usize - 1];
+                self.resume(Resume::Until(ret));
+                json!({})
+            }
+            "pause" => {
+                self.respond(out, request, json!({}))?;
+                if self.running.take().is_some() {
+                    self.stopped(out, "pause", None)?;
+                }
+                return Ok(true);
+            }
+            "disconnect" | "terminate" => {
+                self.respond(out, request, json!({}))?;
+                self.event(out, "terminated", json!({}))?;
+                return Ok(false);
+            }
+            _ => {
+                return self
+                    .fail(out, request, &format!("unsupported request `{}`", command))
+                    .map(|_| true)
+            }
+        };
+        self.respond(out, request, body)?;
+        Ok(true)
+    }
+
+    /// Load the program given by the `launch` arguments
+    fn launch(&mut self, args: &Value) -> Result<()> {
+        let invalid = |reason: &str| {
+            Chip8Error::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                reason.to_string(),
+            ))
+        };
+
+        let program = args["program"]
+            .as_str()
+            .ok_or_else(|| invalid("missing `program`"))?;
+        let mut chip8 = Chip8::new();
+        if let Some(addr) = args["loadAddress"].as_u64() {
+            chip8.set_load_addr(addr as u16);
+        }
+        match args["symbols"].as_str() {
+            Some(symbols) => chip8.load_rom_with_symbols(&program, &symbols)?,
+            None => chip8.load_rom(&program)?,
+        }
+
+        if let Some(platform) = args["platform"].as_str() {
+            let platform: Platform = platform.parse().map_err(|e: String| invalid(&e))?;
+            chip8.set_platform(platform);
+            chip8.set_quirks(platform.quirks());
+        }
+        if let Some(hz) = args["clockHz"].as_f64() {
+            chip8.set_clock_hz(hz as f32);
+        }
+        if let Some(timing) = args["timing"].as_str() {
+            let timing: Timing = timing.parse().map_err(|e: String| invalid(&e))?;
+            chip8.set_timing(timing);
+        }
+
+        chip8.set_protection(args["protect"].as_bool().unwrap_or(false));
+
+        chip8.set_history(history::DEFAULT_CAPACITY);
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        self.chip8 = Some(chip8);
+        Ok(())
+    }
+
+    /// `setBreakpoints`: replace the breakpoints of a source file
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        let path = PathBuf::from(args["source"]["path"].as_str().unwrap_or_default());
+        let mut addrs = Vec::new();
+        let breakpoints: Vec<_> = args["breakpoints"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .map(|bp| {
+                let line = bp["line"].as_u64().unwrap_or(0) as u32;
+                match self.lines().and_then(|lines| lines.addr(&path, line)) {
+                    Some(addr) => {
+                        addrs.push(addr);
+                        json!({ "verified": true, "line": line, "instructionReference": reference(addr) })
+                    }
+                    None => json!({
+                        "verified": false,
+                        "line": line,
+                        "message": "no instruction at this line",
+                    }),
+                }
+            })
+            .collect();
+        self.source_breakpoints.insert(path, addrs);
+        json!({ "breakpoints": breakpoints })
+    }
+
+    /// `setInstructionBreakpoints`: replace the breakpoints set by address
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Value {
+        self.instruction_breakpoints.clear();
+        let breakpoints: Vec<_> = args["breakpoints"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .map(|bp| {
+                let addr = bp["instructionReference"]
+                    .as_str()
+                    .and_then(parse_reference)
+                    .map(|addr| addr.wrapping_add(bp["offset"].as_i64().unwrap_or(0) as u16));
+                match addr {
+                    Some(addr) => {
+                        self.instruction_breakpoints.insert(addr);
+                        json!({ "verified": true, "instructionReference": reference(addr) })
+                    }
+                    None => json!({ "verified": false, "message": "invalid address" }),
+                }
+            })
+            .collect();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    /// `stackTrace`: the current instruction, then the `CALL` of each subroutine being executed
+    fn stack_trace(&self) -> Value {
+        let chip8 = self.chip8();
+        let calls = chip8.stack[..chip8.sp as usize]
+            .iter()
+            .rev()
+            .map(|ret| ret.wrapping_sub(2));
+        let addrs: Vec<_> = std::iter::once(chip8.pc).chain(calls).collect();
+
+        let frames: Vec<_> = addrs
+            .iter()
+            .enumerate()
+            .map(|(depth, addr)| {
+                // each frame is named after the subroutine the next `CALL` out entered
+                let name = match addrs.get(depth + 1) {
+                    Some(call) => {
+                        let sub = self.opcode(*call).addr();
+                        match self.symbols().and_then(|symbols| symbols.label(sub)) {
+                            Some(label) => label.to_string(),
+                            None => format!("sub_{:03X}", sub),
+                        }
+                    }
+                    None => "main".to_string(),
+                };
+                let mut frame = json!({
+                    "id": depth,
+                    "name": name,
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": reference(*addr),
+                });
+                if let Some(source) = self.lines().and_then(|lines| lines.line(*addr)) {
+                    frame["source"] = source_json(&source.path);
+                    frame["line"] = json!(source.line);
+                    frame["column"] = json!(1);
+                }
+                frame
+            })
+            .collect();
+        json!({ "stackFrames": frames, "totalFrames": addrs.len() })
+    }
+
+    /// `variables`: the contents of a scope
+    fn variables(&self, args: &Value) -> Value {
+        let chip8 = self.chip8();
+        let var = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+
+        let vars: Vec<_> = match args["variablesReference"].as_i64() {
+            Some(REGISTERS_REF) => (0..16u8)
+                .map(|x| var(format!("V{:X}", x), format!("{:#04X}", chip8.regs[x])))
+                .chain(vec![
+                    var("I".to_string(), format!("{:#05X}", chip8.i)),
+                    var("PC".to_string(), format!("{:#05X}", chip8.pc)),
+                    var("SP".to_string(), chip8.sp.to_string()),
+                    var("DT".to_string(), chip8.dt.to_string()),
+                    var("ST".to_string(), chip8.st.to_string()),
+                ])
+                .collect(),
+            Some(STACK_REF) => chip8.stack[..chip8.sp as usize]
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(idx, ret)| var(format!("[{}]", idx), format!("{:#05X}", ret)))
+                .collect(),
+            Some(MEMORY_REF) => (0..MEMORY_VIEW)
+                .step_by(8)
+                .map(|off| {
+                    let addr = (chip8.i as usize + off as usize) % Ram::RAM_SIZE;
+                    let bytes: Vec<_> = (addr..addr + 8)
+                        .map(|a| format!("{:02X}", chip8.ram[a % Ram::RAM_SIZE]))
+                        .collect();
+                    var(format!("{:03X}", addr), bytes.join(" "))
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        json!({ "variables": vars })
+    }
+
+    /// `readMemory`: bytes of RAM, base64 encoded
+    fn read_memory(&self, args: &Value) -> Value {
+        let start = args["memoryReference"]
+            .as_str()
+            .and_then(parse_reference)
+            .unwrap_or(0) as i64
+            + args["offset"].as_i64().unwrap_or(0);
+        let start = start.clamp(0, Ram::RAM_SIZE as i64) as usize;
+        let count = args["count"].as_u64().unwrap_or(0) as usize;
+        let end = (start + count).min(Ram::RAM_SIZE);
+
+        let chip8 = self.chip8();
+        json!({
+            "address": reference(start as u16),
+            "data": base64(&chip8.ram[start..end]),
+            "unreadableBytes": count - (end - start),
+        })
+    }
+
+    /// `writeMemory`: write base64 encoded bytes to RAM
+    fn write_memory(&mut self, args: &Value) -> std::result::Result<Value, String> {
+        let start = args["memoryReference"]
+            .as_str()
+            .and_then(parse_reference)
+            .ok_or("invalid memory reference")? as i64
+            + args["offset"].as_i64().unwrap_or(0);
+        let data = args["data"]
+            .as_str()
+            .and_then(unbase64)
+            .ok_or("invalid base64 data")?;
+        if start < 0 || start as usize + data.len() > Ram::RAM_SIZE {
+            return Err(format!(
+                "{} bytes at {:#X} don't fit in RAM",
+                data.len(),
+                start
+            ));
+        }
+
+        self.chip8_mut()
+            .write_mem(start as u16, &data)
+            .map_err(|e| e.to_string())?;
+        Ok(json!({ "bytesWritten": data.len() }))
+    }
+
+    /// `evaluate`: run a [`Monitor`] command typed in the debug console
+    ///
+    /// [`Monitor`]: ../hexdump/struct.Monitor.html
+    fn evaluate(&mut self, args: &Value) -> std::result::Result<Value, String> {
+        let expression = args["expression"].as_str().unwrap_or_default();
+        let chip8 = self.chip8.as_mut().ok_or("no program launched")?;
+        let output = self.monitor.run(chip8, expression)?;
+        Ok(json!({ "result": output.trim_end(), "variablesReference": 0 }))
+    }
+
+    /// `disassemble`: instructions around an address
+    fn disassemble(&self, args: &Value) -> Value {
+        let base = args["memoryReference"]
+            .as_str()
+            .and_then(parse_reference)
+            .unwrap_or(0) as i64
+            + args["offset"].as_i64().unwrap_or(0);
+        let first = args["instructionOffset"].as_i64().unwrap_or(0);
+        let count = args["instructionCount"].as_i64().unwrap_or(0);
+
+        // instructions are decoded forwards from the base, before it they are taken to be 2
+        // bytes long
+        let mut addr = base + 2 * first;
+        let mut instructions = Vec::new();
+        for _ in 0..count {
+            if addr < 0 || addr + 2 > Ram::RAM_SIZE as i64 {
+                instructions.push(json!({
+                    "address": format!("{:#05X}", addr.max(0)),
+                    "instruction": "",
+                    "presentationHint": "invalid",
+                }));
+                addr += 2;
+                continue;
+            }
+            let decoded = self.opcode(addr as u16).decode_for(self.chip8().platform());
+            let len = if addr < base { 2 } else { decoded.def().len };
+            let bytes: Vec<_> = (0..len / 2)
+                .map(|word| format!("{:04X}", self.opcode(addr as u16 + 2 * word)))
+                .collect();
+            let mut inst = json!({
+                "address": reference(addr as u16),
+                "instructionBytes": bytes.join(" "),
+                "instruction": decoded.disassemble_with(self.symbols()),
+            });
+            if let Some(label) = self
+                .symbols()
+                .and_then(|symbols| symbols.label(addr as u16))
+            {
+                inst["symbol"] = json!(label);
+            }
+            if let Some(source) = self.lines().and_then(|lines| lines.line(addr as u16)) {
+                inst["location"] = source_json(&source.path);
+                inst["line"] = json!(source.line);
+            }
+            instructions.push(inst);
+            addr += len as i64;
+        }
+        json!({ "instructions": instructions })
+    }
+
+    /// `next`: execute an instruction, running a whole subroutine if it is a `CALL`
+    fn step_over(&mut self, out: &mut impl Write) -> Result<()> {
+        let pc = self.chip8().pc;
+        if self.opcode(pc).to_match_tuple().0 == 0x2 {
+            self.resume(Resume::Until(pc.wrapping_add(2)));
+            Ok(())
+        } else {
+            self.step_in(out)
+        }
+    }
+
+    /// `stepIn`: execute a single instruction
+    fn step_in(&mut self, out: &mut impl Write) -> Result<()> {
+        let mut executed = false;
+        let res = self
+            .chip8_mut()
+            .run_frame_until(|_| std::mem::replace(&mut executed, true));
+        match res {
+            Ok(_) => self.stopped(out, "step", None),
+            Err(e) => self.stopped(out, "exception", Some(e.to_string())),
+        }
+    }
+
+    /// Start running the program from the current instruction, until `resume` stops it
+    fn resume(&mut self, resume: Resume) {
+        self.running = Some(resume);
+        self.resumed_from = Some(self.chip8().pc);
+    }
+
+    /// Run a frame of the running program, reporting if it stopped
+    fn run_frame(&mut self, resume: Resume, out: &mut impl Write) -> Result<()> {
+        let breakpoints = self.breakpoints();
+        let mut resumed_from = self.resumed_from;
+        let mut reason = "breakpoint";
+
+        let res = self.chip8_mut().run_frame_until(|pc| {
+            if resumed_from.take() == Some(pc) {
+                return false;
+            }
+            if resume == Resume::Until(pc) {
+                reason = "step";
+                return true;
+            }
+            breakpoints.contains(&pc)
+        });
+        self.resumed_from = resumed_from;
+        match res {
+            Ok(true) => {
+                self.running = None;
+                self.stopped(out, reason, None)
+            }
+            Ok(false) => Ok(()),
+            Err(e) => {
+                self.running = None;
+                self.stopped(out, "exception", Some(e.to_string()))
+            }
+        }
+    }
+
+    /// Addresses of all breakpoints, by source and by address
+    fn breakpoints(&self) -> BTreeSet<u16> {
+        self.source_breakpoints
+            .values()
+            .flatten()
+            .chain(&self.instruction_breakpoints)
+            .copied()
+            .collect()
+    }
+
+    /// Send a `stopped` event
+    fn stopped(&mut self, out: &mut impl Write, reason: &str, text: Option<String>) -> Result<()> {
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(text) = text {
+            body["text"] = json!(text);
+        }
+        self.event(out, "stopped", body)
+    }
+
+    fn chip8(&self) -> &Chip8 {
+        self.chip8.as_ref().expect("no program launched")
+    }
+
+    fn symbols(&self) -> Option<&Symbols> {
+        self.chip8().symbols()
+    }
+
+    fn lines(&self) -> Option<&LineMap> {
+        self.symbols().map(Symbols::lines)
+    }
+
+    fn chip8_mut(&mut self) -> &mut Chip8 {
+        self.chip8.as_mut().expect("no program launched")
+    }
+
+    /// The word at `addr`, wrapping at the end of RAM
+    fn opcode(&self, addr: u16) -> OpCode {
+        let ram = &self.chip8().ram;
+        let addr = addr as usize % Ram::RAM_SIZE;
+        OpCode::from((ram[addr], ram[(addr + 1) % Ram::RAM_SIZE]))
+    }
+
+    fn respond(&mut self, out: &mut impl Write, request: &Value, body: Value) -> Result<()> {
+        let response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "success": true,
+            "command": request["command"],
+            "body": body,
+        });
+        self.send(out, response)
+    }
+
+    fn fail(&mut self, out: &mut impl Write, request: &Value, message: &str) -> Result<()> {
+        let response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "success": false,
+            "command": request["command"],
+            "message": message,
+        });
+        self.send(out, response)
+    }
+
+    fn event(&mut self, out: &mut impl Write, event: &str, body: Value) -> Result<()> {
+        self.send(
+            out,
+            json!({ "type": "event", "event": event, "body": body }),
+        )
+    }
+
+    /// Send `message` with the next sequence number
+    fn send(&mut self, out: &mut impl Write, mut message: Value) -> Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = serde_json::to_string(&message)?;
+        write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// Read messages on a separate thread, so requests (e.g. `pause`) arrive while running
+fn spawn_reader<R: BufRead + Send + 'static>(mut reader: R) -> Receiver<Result<Value>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || loop {
+        let message = match read_message(&mut reader) {
+            Ok(Some(message)) => Ok(message),
+            Ok(None) => break,
+            Err(e) => Err(e),
+        };
+        let failed = message.is_err();
+        if tx.send(message).is_err() || failed {
+            break;
+        }
+    });
+    rx
+}
+
+/// Read a single message, or `None` at the end of the input
+fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                len = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; len.unwrap_or(0)];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+/// Capabilities reported in reply to `initialize`
+fn capabilities() -> Value {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsInstructionBreakpoints": true,
+        "supportsDisassembleRequest": true,
+        "supportsReadMemoryRequest": true,
+        "supportsWriteMemoryRequest": true,
+        "supportsSteppingGranularity": false,
+        "supportsTerminateRequest": true,
+        "supportsStepBack": true,
+    })
+}
+
+/// Reply to `scopes`, the same for every frame
+fn scopes() -> Value {
+    json!({ "scopes": [
+        { "name": "Registers", "variablesReference": REGISTERS_REF, "expensive": false },
+        { "name": "Stack", "variablesReference": STACK_REF, "expensive": false },
+        { "name": "Memory at I", "variablesReference": MEMORY_REF, "expensive": false },
+    ]})
+}
+
+fn source_json(path: &Path) -> Value {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    json!({ "name": name, "path": path })
+}
+
+/// Memory or instruction reference of `addr`
+fn reference(addr: u16) -> String {
+    format!("{:#05X}", addr)
+}
+
+/// Parse a memory or instruction reference, in hex with a `0x` prefix or decimal
+fn parse_reference(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Encode `bytes` as base64, with padding
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decode base64 `text`, with or without padding
+fn unbase64(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let (mut n, mut bits) = (0u32, 0);
+    for c in text.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        n = n << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frame `requests` as the editor would
+    fn input(requests: &[Value]) -> Vec<u8> {
+        let mut input = Vec::new();
+        for request in requests {
+            let body = request.to_string();
+            write!(input, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        }
+        input
+    }
+
+    /// Split the server's output into messages
+    fn output(bytes: &[u8]) -> Vec<Value> {
+        let mut reader = bytes;
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut reader).unwrap() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    #[test]
+    fn encode_base64() {
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"M"), "TQ==");
+        assert_eq!(unbase64("TWFu"), Some(b"Man".to_vec()));
+        assert_eq!(unbase64("TWE="), Some(b"Ma".to_vec()));
+        assert_eq!(unbase64("TQ"), Some(b"M".to_vec()));
+        assert_eq!(unbase64("T!=="), None);
+    }
+
+    #[test]
+    fn session() {
+        let dir = std::env::temp_dir().join(format!("chip8-dap-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rom = [
+            0x22, 0x04, // 200: CALL 204
+            0x12, 0x02, // 202: JP 202
+            0x70, 0x01, // 204: ADD V0, 01
+            0x00, 0xEE, // 206: RET
+        ];
+        std::fs::write(dir.join("test.ch8"), rom).unwrap();
+        let symbols = "200 test.8o:1\n204 test.8o:5\n204 update\n";
+        std::fs::write(dir.join("test.sym"), symbols).unwrap();
+
+        let requests = input(&[
+            json!({ "seq": 1, "type": "request", "command": "initialize", "arguments": {} }),
+            json!({ "seq": 2, "type": "request", "command": "launch", "arguments": {
+                "program": dir.join("test.ch8"),
+                "symbols": dir.join("test.sym"),
+                "stopOnEntry": true,
+            }}),
+            json!({ "seq": 3, "type": "request", "command": "setBreakpoints", "arguments": {
+                "source": { "path": dir.join("test.8o") },
+                "breakpoints": [{ "line": 5 }, { "line": 6 }],
+            }}),
+            json!({ "seq": 4, "type": "request", "command": "configurationDone" }),
+            json!({ "seq": 5, "type": "request", "command": "continue" }),
+            json!({ "seq": 6, "type": "request", "command": "stackTrace" }),
+            json!({ "seq": 7, "type": "request", "command": "stepIn" }),
+            json!({ "seq": 8, "type": "request", "command": "variables", "arguments": {
+                "variablesReference": REGISTERS_REF,
+            }}),
+            json!({ "seq": 9, "type": "request", "command": "disassemble", "arguments": {
+                "memoryReference": "0x200", "instructionCount": 2,
+            }}),
+            json!({ "seq": 10, "type": "request", "command": "stepBack" }),
+            json!({ "seq": 11, "type": "request", "command": "variables", "arguments": {
+                "variablesReference": REGISTERS_REF,
+            }}),
+            json!({ "seq": 12, "type": "request", "command": "reverseContinue" }),
+            json!({ "seq": 13, "type": "request", "command": "writeMemory", "arguments": {
+                "memoryReference": "0x300", "offset": 1, "data": "q80=",
+            }}),
+            json!({ "seq": 14, "type": "request", "command": "evaluate", "arguments": {
+                "expression": "x 300 4", "context": "repl",
+            }}),
+            json!({ "seq": 15, "type": "request", "command": "evaluate", "arguments": {
+                "expression": "poke 300", "context": "repl",
+            }}),
+            json!({ "seq": 16, "type": "request", "command": "disconnect" }),
+        ]);
+
+        let mut out = Vec::new();
+        DapServer::new()
+            .serve(std::io::Cursor::new(requests), &mut out)
+            .unwrap();
+        let messages = output(&out);
+        std::fs::remove_dir_all(&dir).ok();
+
+        let response = |seq: i64| {
+            messages
+                .iter()
+                .find(|m| m["type"] == "response" && m["request_seq"] == seq)
+                .unwrap()
+        };
+        let stops: Vec<_> = messages
+            .iter()
+            .filter(|m| m["event"] == "stopped")
+            .map(|m| m["body"]["reason"].as_str().unwrap())
+            .collect();
+        assert_eq!(stops, vec!["entry", "breakpoint", "step", "step", "entry"]);
+
+        let breakpoints = &response(3)["body"]["breakpoints"];
+        assert_eq!(breakpoints[0]["verified"], true);
+        assert_eq!(breakpoints[1]["verified"], false);
+
+        let frames = &response(6)["body"]["stackFrames"];
+        assert_eq!(frames[0]["name"], "update");
+        assert_eq!(frames[0]["line"], 5);
+        assert_eq!(frames[1]["instructionPointerReference"], "0x200");
+
+        assert_eq!(response(8)["body"]["variables"][0]["value"], "0x01");
+        assert_eq!(response(11)["body"]["variables"][0]["value"], "0x00");
+        let instructions = &response(9)["body"]["instructions"];
+        assert_eq!(instructions[0]["instruction"], "CALL update");
+        assert_eq!(instructions[1]["instruction"], "JP 0x202");
+
+        assert_eq!(response(13)["body"]["bytesWritten"], 2);
+        let dump = response(14)["body"]["result"].as_str().unwrap();
+        assert!(dump.starts_with("300:   00  AB  CD  00"));
+        assert_eq!(response(15)["success"], false);
+        assert_eq!(messages.last().unwrap()["event"], "terminated");
+    }
+
+    #[test]
+    fn breakpoint_at_frame_start() {
+        // 10 instructions run each frame, so the second frame starts at 214
+        let mut rom = [0x70, 0x01].repeat(10); // 200-212: ADD V0, 01
+        rom.extend(&[0x12, 0x14]); // 214: JP 214
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&rom).unwrap();
+
+        let mut server = DapServer::new();
+        server.chip8 = Some(chip8);
+        server.instruction_breakpoints.insert(0x214);
+        server.resume(Resume::Continue);
+        let mut out = Vec::new();
+        server.run_frame(Resume::Continue, &mut out).unwrap();
+        assert!(server.running.is_some());
+        server.run_frame(Resume::Continue, &mut out).unwrap();
+        assert!(server.running.is_none());
+        assert_eq!(server.chip8().pc, 0x214);
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains(r#""reason":"breakpoint""#));
+    }
+}
@@ -0,0 +1,291 @@
+//! ROM metadata database.
+//!
+//! Reads the `programs.json` file of the community CHIP-8 database, which records the platform,
+//! quirks, speed, key mappings and colours each known ROM expects, keyed by the SHA-1 hash of the
+//! ROM image. Without a database given, `chip8/programs.json` in the user's data directory
+//! (`$XDG_DATA_HOME` on Linux) is used, if it exists.
+//!
+//! ```text
+//! [
+//!   {
+//!     "title": "Pong",
+//!     "roms": {
+//!       "<sha1>": {
+//!         "platforms": ["originalChip8"],
+//!         "tickrate": 15,
+//!         "quirkyPlatforms": { "originalChip8": { "shift": true } },
+//!         "keys": { "up": 1, "down": 4 },
+//!         "colors": { "pixels": ["#000000", "#ffffff"] }
+//!       }
+//!     }
+//!   }
+//! ]
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+
+use super::{
+    error::Result,
+    quirks::{Platform, Quirks},
+};
+
+/// Number of timer ticks per second, the database measures speed in instructions per tick
+const TICKS_PER_SECOND: f32 = 60.0;
+
+/// Settings recorded for a single ROM
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomInfo {
+    /// Title of the program the ROM belongs to
+    pub title: String,
+    /// Platform the ROM was written for
+    pub platform: Platform,
+    /// Quirks the ROM expects
+    pub quirks: Quirks,
+    /// CPU clock speed the ROM expects, in instructions per second
+    pub clock_hz: Option<f32>,
+    /// Keypad keys the ROM uses, by their purpose, e.g. `"up" => 0x5`
+    pub keys: HashMap<String, u8>,
+    /// Colours, as `#RRGGBB` strings, for each pixel value
+    pub colors: Vec<String>,
+}
+
+/// ROM metadata, keyed by the SHA-1 hash of the ROM
+#[derive(Debug, Default)]
+pub struct Database {
+    roms: HashMap<String, RomInfo>,
+}
+
+impl Database {
+    /// Parse a database in the format of the community `programs.json`
+    pub fn from_json(json: &str) -> Result<Self> {
+        let programs: Vec<Program> = serde_json::from_str(json)?;
+
+        let mut roms = HashMap::new();
+        for program in programs {
+            for (hash, rom) in program.roms {
+                let info = rom.into_info(&program.title);
+                roms.insert(hash.to_ascii_lowercase(), info);
+            }
+        }
+
+        log::debug!("Loaded ROM database with {} entries", roms.len());
+        Ok(Self { roms })
+    }
+
+    /// Read a database from a `programs.json` file
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Path of the default database, if there is a data directory
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("chip8").join("programs.json"))
+    }
+
+    /// Read the default database, if there is one
+    pub fn load_default() -> Result<Option<Self>> {
+        match Self::default_path() {
+            Some(path) if path.exists() => {
+                log::debug!("Using ROM database {}", path.display());
+                Self::open(path).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Look up the settings for a ROM image
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomInfo> {
+        self.get(&sha1_hex(rom))
+    }
+
+    /// Look up the settings for a ROM by its hex encoded SHA-1 hash
+    pub fn get(&self, sha1: &str) -> Option<&RomInfo> {
+        self.roms.get(&sha1.to_ascii_lowercase())
+    }
+
+    /// Number of ROMs in the database
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    /// Whether the database has no ROMs
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+}
+
+/// Hex encoded SHA-1 hash of `data`
+pub fn sha1_hex(data: &[u8]) -> String {
+    Sha1::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Program entry of `programs.json`
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    roms: HashMap<String, Rom>,
+}
+
+/// ROM entry of a program
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    #[serde(default)]
+    platforms: Vec<String>,
+    tickrate: Option<f32>,
+    #[serde(default)]
+    quirky_platforms: HashMap<String, QuirkOverrides>,
+    #[serde(default)]
+    keys: HashMap<String, u8>,
+    colors: Option<Colors>,
+}
+
+/// Quirks a ROM needs that differ from its platform's defaults
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QuirkOverrides {
+    shift: Option<bool>,
+    memory_increment_by_x: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    vblank: Option<bool>,
+    logic: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+impl Rom {
+    fn into_info(self, title: &str) -> RomInfo {
+        // the first platform we can emulate is the preferred one
+        let (id, platform) = self
+            .platforms
+            .iter()
+            .find_map(|id| platform_from_id(id).map(|p| (id.as_str(), p)))
+            .unwrap_or(("originalChip8", Platform::Chip8));
+
+        let mut quirks = match id {
+            "modernChip8" => Quirks::modern(),
+            _ => platform.quirks(),
+        };
+        if let Some(overrides) = self.quirky_platforms.get(id) {
+            overrides.apply(&mut quirks);
+        }
+
+        RomInfo {
+            title: title.to_owned(),
+            platform,
+            quirks,
+            clock_hz: self.tickrate.map(|t| t * TICKS_PER_SECOND),
+            keys: self.keys,
+            colors: self.colors.map(|c| c.pixels).unwrap_or_default(),
+        }
+    }
+}
+
+impl QuirkOverrides {
+    fn apply(&self, quirks: &mut Quirks) {
+        // the database names quirks after the non-VIP behaviour
+        if let Some(shift) = self.shift {
+            quirks.shift_uses_vy = !shift;
+        }
+        if let Some(unchanged) = self.memory_leave_i_unchanged {
+            quirks.load_store_increments_i = !unchanged;
+        }
+        // incrementing by `x` rather than `x + 1` is close enough to incrementing at all
+        if let Some(true) = self.memory_increment_by_x {
+            quirks.load_store_increments_i = true;
+        }
+        if let Some(wrap) = self.wrap {
+            quirks.clip_sprites = !wrap;
+        }
+        if let Some(jump) = self.jump {
+            quirks.jump_uses_vx = jump;
+        }
+        if let Some(vblank) = self.vblank {
+            quirks.display_wait = vblank;
+        }
+        if let Some(logic) = self.logic {
+            quirks.vf_reset = logic;
+        }
+    }
+}
+
+/// Map a platform id used by the database onto a [`Platform`]
+///
+/// [`Platform`]: ../quirks/enum.Platform.html
+fn platform_from_id(id: &str) -> Option<Platform> {
+    match id {
+        "originalChip8" | "hybridVIP" | "modernChip8" => Some(Platform::Chip8),
+        "chip48" | "superchip1" | "superchip" => Some(Platform::Schip),
+        "xochip" => Some(Platform::XoChip),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JSON: &str = r##"[
+        {
+            "title": "Test",
+            "roms": {
+                "A9993E364706816ABA3E25717850C26C9CD0D89D": {
+                    "platforms": ["megachip8", "superchip"],
+                    "tickrate": 30,
+                    "quirkyPlatforms": { "superchip": { "jump": false, "shift": false } },
+                    "keys": { "up": 5 },
+                    "colors": { "pixels": ["#000000", "#ffffff"] }
+                }
+            }
+        }
+    ]"##;
+
+    #[test]
+    fn sha1() {
+        assert_eq!(sha1_hex(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+
+    #[test]
+    fn lookup() {
+        let db = Database::from_json(JSON).unwrap();
+        let info = db.lookup(b"abc").unwrap();
+
+        let mut quirks = Quirks::schip();
+        quirks.jump_uses_vx = false;
+        quirks.shift_uses_vy = true;
+
+        assert_eq!(info.title, "Test");
+        assert_eq!(info.platform, Platform::Schip);
+        assert_eq!(info.quirks, quirks);
+        assert_eq!(info.clock_hz, Some(1800.0));
+        assert_eq!(info.keys["up"], 5);
+        assert_eq!(info.colors.len(), 2);
+        assert!(db.lookup(b"abd").is_none());
+    }
+
+    #[test]
+    fn modern_chip8() {
+        let json = r#"[{ "title": "New", "roms": { "abc": { "platforms": ["modernChip8"] } } }]"#;
+        let db = Database::from_json(json).unwrap();
+        let info = db.get("ABC").unwrap();
+        assert_eq!(info.platform, Platform::Chip8);
+        assert_eq!(info.quirks, Quirks::modern());
+        assert!(!info.quirks.shift_uses_vy && !info.quirks.vf_reset);
+        assert!(!info.quirks.display_wait);
+    }
+}
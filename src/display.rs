@@ -0,0 +1,132 @@
+//! Chip-8 display.
+//!
+//! The original implementation of the Chip-8 language used a 64x32-pixel monochrome display with
+//! this format:
+//!
+//! ```text
+//! +----------------------+
+//! | (0,0)         (63,0) |
+//! |                      |
+//! | (0,31)       (63,31) |
+//! +----------------------+
+//! ```
+//!
+//! Chip-8 draws graphics on screen through the use of sprites. A sprite is a group of bytes which
+//! are a binary representation of the desired picture. Chip-8 sprites may be up to 15 bytes, for
+//! a possible sprite size of 8x15.
+
+/// Framebuffer of the Chip-8 display
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Display {
+    width: usize,
+    height: usize,
+    /// One byte per pixel, row by row
+    pixels: Vec<u8>,
+}
+
+impl Display {
+    /// Width of the display, in pixels
+    pub const WIDTH: usize = 64;
+    /// Height of the display, in pixels
+    pub const HEIGHT: usize = 32;
+
+    /// Create a new, blank `Display`
+    pub fn new() -> Self {
+        Self {
+            width: Self::WIDTH,
+            height: Self::HEIGHT,
+            pixels: vec![0; Self::WIDTH * Self::HEIGHT],
+        }
+    }
+
+    /// Get the width of the display, in pixels
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Get the height of the display, in pixels
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Get the pixels of the display, one byte per pixel, row by row
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Get the value of the pixel at `(x, y)`
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Clear the display
+    pub fn clear(&mut self) {
+        self.pixels.iter_mut().for_each(|p| *p = 0);
+    }
+
+    /// XOR `sprite` onto the display with its top left corner at `(x, y)`
+    ///
+    /// The starting position always wraps around the display, the rest of the sprite is either
+    /// clipped at the edges, or wraps around too. Returns whether any pixel was erased.
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8], clip: bool) -> bool {
+        let (x, y) = (x % self.width, y % self.height);
+        let mut collision = false;
+
+        for (row, byte) in sprite.iter().enumerate() {
+            let py = y + row;
+            if clip && py >= self.height {
+                break;
+            }
+            for col in 0..8 {
+                let px = x + col;
+                if clip && px >= self.width {
+                    break;
+                }
+                if byte & (0x80 >> col) == 0 {
+                    continue;
+                }
+
+                let idx = (py % self.height) * self.width + px % self.width;
+                collision |= self.pixels[idx] != 0;
+                self.pixels[idx] ^= 1;
+            }
+        }
+
+        collision
+    }
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw_collision() {
+        let mut display = Display::new();
+        assert!(!display.draw_sprite(0, 0, &[0xC0], false));
+        assert_eq!(display.pixel(0, 0), 1);
+        assert_eq!(display.pixel(1, 0), 1);
+        assert!(display.draw_sprite(1, 0, &[0x80], false));
+        assert_eq!(display.pixel(1, 0), 0);
+    }
+
+    #[test]
+    fn wrap_and_clip() {
+        let mut display = Display::new();
+        display.draw_sprite(62, 31, &[0xF0, 0xF0], false);
+        assert_eq!(display.pixel(63, 31), 1);
+        assert_eq!(display.pixel(0, 31), 1);
+        assert_eq!(display.pixel(0, 0), 1);
+
+        let mut display = Display::new();
+        display.draw_sprite(62 + 64, 31, &[0xF0, 0xF0], true);
+        assert_eq!(display.pixel(63, 31), 1);
+        assert_eq!(display.pixels().iter().filter(|p| **p != 0).count(), 2);
+    }
+}
@@ -7,6 +7,41 @@ pub type Result<T> = std::result::Result<T, Chip8Error>;
 #[derive(Debug)]
 pub enum Chip8Error {
     Io(io::Error),
+    Json(serde_json::Error),
+    Toml(toml::de::Error),
+    Png(png::EncodingError),
+    Gif(gif::EncodingError),
+    /// A memory access fell outside of RAM
+    OutOfBounds(usize),
+    /// The instruction at `pc` wrote to the reserved interpreter region
+    ProtectedWrite {
+        pc: u16,
+        addr: usize,
+    },
+    /// `pc` left the bounds of the loaded ROM
+    ExecOutsideRom {
+        pc: u16,
+    },
+    /// `CALL` at `pc` nested deeper than the stack allows
+    StackOverflow {
+        pc: u16,
+    },
+    /// `RET` at `pc` with nothing on the stack
+    StackUnderflow {
+        pc: u16,
+    },
+    /// Data being loaded doesn't fit in the RAM available after its load address
+    TooLarge {
+        size: usize,
+        available: usize,
+    },
+    /// Line `line` of a symbol file couldn't be parsed
+    Symbols {
+        line: usize,
+        reason: String,
+    },
+    /// A script failed to compile or raised an error
+    Script(String),
 }
 
 impl fmt::Display for Chip8Error {
@@ -14,6 +49,32 @@ impl fmt::Display for Chip8Error {
         match *self {
             // this is a wrapper, so defer to the underlying types impl of `fmt`
             Self::Io(ref e) => e.fmt(f),
+            Self::Json(ref e) => e.fmt(f),
+            Self::Toml(ref e) => e.fmt(f),
+            Self::Png(ref e) => e.fmt(f),
+            Self::Gif(ref e) => e.fmt(f),
+            Self::OutOfBounds(addr) => write!(f, "memory access out of bounds: {:#06X}", addr),
+            Self::ProtectedWrite { pc, addr } => write!(
+                f,
+                "write to reserved memory {:#06X} by instruction at {:#06X}",
+                addr, pc
+            ),
+            Self::ExecOutsideRom { pc } => {
+                write!(f, "execution outside of loaded ROM at {:#06X}", pc)
+            }
+            Self::StackOverflow { pc } => write!(f, "stack overflow at {:#06X}", pc),
+            Self::StackUnderflow { pc } => {
+                write!(f, "return with an empty stack at {:#06X}", pc)
+            }
+            Self::TooLarge { size, available } => write!(
+                f,
+                "{} bytes is larger than the {} bytes of RAM available",
+                size, available
+            ),
+            Self::Symbols { line, ref reason } => {
+                write!(f, "invalid symbol file, line {}: {}", line, reason)
+            }
+            Self::Script(ref reason) => write!(f, "script error: {}", reason),
         }
     }
 }
@@ -22,6 +83,18 @@ impl error::Error for Chip8Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
             Self::Io(ref e) => Some(e),
+            Self::Json(ref e) => Some(e),
+            Self::Toml(ref e) => Some(e),
+            Self::Png(ref e) => Some(e),
+            Self::Gif(ref e) => Some(e),
+            Self::OutOfBounds(_)
+            | Self::ProtectedWrite { .. }
+            | Self::ExecOutsideRom { .. }
+            | Self::StackOverflow { .. }
+            | Self::StackUnderflow { .. }
+            | Self::TooLarge { .. }
+            | Self::Symbols { .. }
+            | Self::Script(_) => None,
         }
     }
 }
@@ -31,3 +104,27 @@ impl From<io::Error> for Chip8Error {
         Self::Io(err)
     }
 }
+
+impl From<serde_json::Error> for Chip8Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl From<toml::de::Error> for Chip8Error {
+    fn from(err: toml::de::Error) -> Self {
+        Self::Toml(err)
+    }
+}
+
+impl From<png::EncodingError> for Chip8Error {
+    fn from(err: png::EncodingError) -> Self {
+        Self::Png(err)
+    }
+}
+
+impl From<gif::EncodingError> for Chip8Error {
+    fn from(err: gif::EncodingError) -> Self {
+        Self::Gif(err)
+    }
+}
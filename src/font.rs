@@ -0,0 +1,39 @@
+//! Chip-8 builtin font.
+//!
+//! Chip-8 programs may refer to a group of sprites representing the hexadecimal digits `0..F`.
+//! These sprites are 5 bytes long, or 8x5 pixels. The data is stored in the interpreter area of
+//! Chip-8 memory (`0x000..0x1FF`).
+//!
+//! ```text
+//! "0"     Binary    Hex
+//! ****    11110000  0xF0
+//! *  *    10010000  0x90
+//! *  *    10010000  0x90
+//! *  *    10010000  0x90
+//! ****    11110000  0xF0
+//! ```
+
+/// Memory address the font is loaded at
+pub const FONT_ADDR: u16 = 0x050;
+/// Size of a single glyph, in bytes
+pub const GLYPH_SIZE: usize = 5;
+
+/// Sprites for the hexadecimal digits `0..F`
+pub const FONT: [u8; 16 * GLYPH_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
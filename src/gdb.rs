@@ -0,0 +1,600 @@
+//! GDB remote serial protocol stub.
+//!
+//! [`GdbStub`] lets gdb, or an IDE speaking the same protocol, attach to a [`Chip8`] over TCP:
+//!
+//! ```text
+//! chip8 --gdb 127.0.0.1:2159 game.ch8
+//! (gdb) target remote 127.0.0.1:2159
+//! ```
+//!
+//! The registers are described to the debugger by a target description, in this order:
+//! `V0..VF`, `I`, `PC`, `SP`, `DT` and `ST`. `I` and `PC` are 16 bit, little endian, the rest are
+//! 8 bit. Memory is the 4KiB of RAM. Breakpoints, single-stepping and continuing are supported,
+//! and a running program can be interrupted. The last [`history::DEFAULT_CAPACITY`] instructions
+//! can be stepped back through with `reverse-stepi` and `reverse-continue`. Memory can be viewed
+//! and edited with the [`Monitor`] commands, e.g. `monitor x 200`.
+//!
+//! While running, frames are paced to real time, so timers and sound behave as usual.
+//!
+//! [`GdbStub`]: struct.GdbStub.html
+//! [`Chip8`]: ../struct.Chip8.html
+//! [`history::DEFAULT_CAPACITY`]: ../history/constant.DEFAULT_CAPACITY.html
+//! [`Monitor`]: ../hexdump/struct.Monitor.html
+
+use std::collections::BTreeSet;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{error::Result, hexdump::Monitor, history, memory::Ram, Chip8};
+
+/// Target description sent to the debugger
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+/// Number of registers described by `TARGET_XML`
+const REG_COUNT: usize = 21;
+/// Largest packet the debugger may send
+const PACKET_SIZE: usize = 0x1000;
+/// Real time between frames while running
+const FRAME_TIME: Duration = Duration::from_micros(16_667);
+/// Byte the debugger sends to interrupt a running program
+const INTERRUPT: u8 = 0x03;
+
+/// Stop reply for a trap, i.e. a breakpoint, single-step or interrupt
+const STOP_TRAP: &str = "S05";
+/// Stop reply for a fault raised by an instruction
+const STOP_FAULT: &str = "S0b";
+/// Stop reply for running backwards out of history
+const STOP_HISTORY_BEGIN: &str = "T05replaylog:begin;";
+
+/// What to do after handling a packet
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Action {
+    /// Send a reply
+    Reply(String),
+    /// Run until a breakpoint or interrupt
+    Continue,
+    /// Execute a single instruction
+    Step,
+    /// Close the connection, replying first if given
+    Close(Option<String>),
+}
+
+/// Debugger stub serving a [`Chip8`], see the [module documentation](index.html)
+///
+/// [`Chip8`]: ../struct.Chip8.html
+#[derive(Debug)]
+pub struct GdbStub<'a> {
+    chip8: &'a mut Chip8,
+    breakpoints: BTreeSet<u16>,
+    /// Whether packets are no longer acknowledged, after `QStartNoAckMode`
+    no_ack: bool,
+    /// Interpreter of `monitor` commands
+    monitor: Monitor,
+}
+
+impl<'a> GdbStub<'a> {
+    /// Create a `GdbStub` for `chip8`, stopped at its current instruction
+    ///
+    /// History is enabled on `chip8`, so execution can be stepped backwards.
+    pub fn new(chip8: &'a mut Chip8) -> Self {
+        if chip8.history().is_none() {
+            chip8.set_history(history::DEFAULT_CAPACITY);
+        }
+        Self {
+            chip8,
+            breakpoints: BTreeSet::new(),
+            no_ack: false,
+            monitor: Monitor::new(),
+        }
+    }
+
+    /// Listen on `addr` and serve the first debugger to connect, until it detaches
+    pub fn serve(&mut self, addr: impl ToSocketAddrs) -> Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        log::info!("Waiting for GDB on {}", listener.local_addr()?);
+        let (stream, peer) = listener.accept()?;
+        log::info!("GDB connected from {}", peer);
+        stream.set_nodelay(true)?;
+        self.session(stream)
+    }
+
+    /// Handle packets from `stream` until the debugger detaches or disconnects
+    fn session(&mut self, mut stream: TcpStream) -> Result<()> {
+        self.no_ack = false;
+        while let Some(packet) = self.read_packet(&mut stream)? {
+            let action = match &packet {
+                Some(packet) => {
+                    if !self.no_ack {
+                        stream.write_all(b"+")?;
+                    }
+                    self.handle(packet)
+                }
+                // an interrupt while already stopped
+                None => Action::Reply(STOP_TRAP.to_string()),
+            };
+            match action {
+                Action::Reply(reply) => {
+                    self.write_packet(&mut stream, &reply)?;
+                    // the request itself was still acknowledged
+                    if packet.as_deref() == Some("QStartNoAckMode") {
+                        self.no_ack = true;
+                    }
+                }
+                Action::Continue => {
+                    let reply = self.run(&mut stream)?;
+                    self.write_packet(&mut stream, &reply)?;
+                }
+                Action::Step => {
+                    let reply = self.step();
+                    self.write_packet(&mut stream, &reply)?;
+                }
+                Action::Close(reply) => {
+                    if let Some(reply) = reply {
+                        self.write_packet(&mut stream, &reply)?;
+                    }
+                    break;
+                }
+            }
+        }
+        log::info!("GDB disconnected");
+        Ok(())
+    }
+
+    /// Read the next packet, `Some(None)` for an interrupt, or `None` once the connection closes
+    fn read_packet(&self, stream: &mut impl Read) -> Result<Option<Option<String>>> {
+        let mut byte = [0];
+        loop {
+            if stream.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            match byte[0] {
+                b'$' => break,
+                INTERRUPT => return Ok(Some(None)),
+                // acknowledgements, and anything else outside of a packet
+                _ => (),
+            }
+        }
+
+        let mut data = Vec::new();
+        loop {
+            if stream.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            match byte[0] {
+                b'#' => break,
+                b => data.push(b),
+            }
+        }
+        let mut checksum = [0; 2];
+        stream.read_exact(&mut checksum)?;
+        Ok(Some(Some(String::from_utf8_lossy(&data).into_owned())))
+    }
+
+    /// Send `data` as a packet
+    fn write_packet(&self, stream: &mut impl Write, data: &str) -> Result<()> {
+        stream.write_all(frame(data).as_bytes())?;
+        stream.flush()?;
+        Ok(())
+    }
+
+    /// Handle a single packet
+    fn handle(&mut self, packet: &str) -> Action {
+        log::debug!("GDB: {}", packet);
+        let reply = |s: &str| Action::Reply(s.to_string());
+        let (cmd, args) = match packet.char_indices().nth(1) {
+            Some((idx, _)) => packet.split_at(idx),
+            None => (packet, ""),
+        };
+
+        match cmd {
+            "?" => reply(STOP_TRAP),
+            "g" => Action::Reply(self.read_registers()),
+            "G" => match self.write_registers(args) {
+                Some(()) => reply("OK"),
+                None => reply("E01"),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(reg) if reg < REG_COUNT => Action::Reply(hex(&self.register(reg))),
+                _ => reply("E01"),
+            },
+            "P" => match self.write_register(args) {
+                Some(()) => reply("OK"),
+                None => reply("E01"),
+            },
+            "m" => match self.read_memory(args) {
+                Some(data) => Action::Reply(data),
+                None => reply("E01"),
+            },
+            "M" => match self.write_memory(args) {
+                Some(()) => reply("OK"),
+                None => reply("E01"),
+            },
+            "Z" | "z" => match self.breakpoint(args, cmd == "Z") {
+                Some(true) => reply("OK"),
+                // watchpoints aren't supported
+                Some(false) => reply(""),
+                None => reply("E01"),
+            },
+            "c" => self.resume_at(args, Action::Continue),
+            "s" => self.resume_at(args, Action::Step),
+            "b" => match args {
+                "s" => Action::Reply(self.step_back()),
+                "c" => Action::Reply(self.run_back()),
+                _ => reply(""),
+            },
+            "H" => reply("OK"),
+            "D" => Action::Close(Some("OK".to_string())),
+            "k" => Action::Close(None),
+            _ => self.query(packet),
+        }
+    }
+
+    /// Handle a query, or any other packet with a multi-letter name
+    fn query(&mut self, packet: &str) -> Action {
+        let reply = |s: &str| Action::Reply(s.to_string());
+        if packet.starts_with("qSupported") {
+            return Action::Reply(format!(
+                "PacketSize={:x};qXfer:features:read+;QStartNoAckMode+;swbreak+;ReverseStep+;ReverseContinue+",
+                PACKET_SIZE
+            ));
+        }
+        if let Some(args) = packet.strip_prefix("qRcmd,") {
+            let cmd = match unhex(args).map(String::from_utf8) {
+                Some(Ok(cmd)) => cmd,
+                _ => return reply("E01"),
+            };
+            let output = match self.monitor.run(self.chip8, &cmd) {
+                Ok(output) => output,
+                Err(e) => format!("{}\n", e),
+            };
+            return Action::Reply(hex(output.as_bytes()));
+        }
+        if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            return match parse_pair(args, ',') {
+                Some((offset, len)) => Action::Reply(xfer(TARGET_XML, offset, len)),
+                None => reply("E01"),
+            };
+        }
+
+        match packet {
+            "QStartNoAckMode" => reply("OK"),
+            "qAttached" => reply("1"),
+            "qC" => reply("QC1"),
+            "qfThreadInfo" => reply("m1"),
+            "qsThreadInfo" => reply("l"),
+            "vCont?" => reply("vCont;c;s"),
+            "vCont;c" | "vCont;c:1" => Action::Continue,
+            "vCont;s" | "vCont;s:1" => Action::Step,
+            _ => reply(""),
+        }
+    }
+
+    /// Continue or step, from the address in `args` if one is given
+    fn resume_at(&mut self, args: &str, action: Action) -> Action {
+        if !args.is_empty() {
+            match u16::from_str_radix(args, 16) {
+                Ok(addr) => self.chip8.pc = addr,
+                Err(_) => return Action::Reply("E01".to_string()),
+            }
+        }
+        action
+    }
+
+    /// Execute a single instruction, returning the stop reply
+    fn step(&mut self) -> String {
+        let mut executed = false;
+        let res = self
+            .chip8
+            .run_frame_until(|_| std::mem::replace(&mut executed, true));
+        match res {
+            Ok(_) => STOP_TRAP.to_string(),
+            Err(e) => {
+                log::error!("Stopped by fault: {}", e);
+                STOP_FAULT.to_string()
+            }
+        }
+    }
+
+    /// Undo a single instruction, returning the stop reply
+    fn step_back(&mut self) -> String {
+        if self.chip8.step_back() {
+            STOP_TRAP.to_string()
+        } else {
+            STOP_HISTORY_BEGIN.to_string()
+        }
+    }
+
+    /// Undo instructions until a breakpoint or the start of history, returning the stop reply
+    fn run_back(&mut self) -> String {
+        let breakpoints = &self.breakpoints;
+        if self.chip8.run_back_until(|pc| breakpoints.contains(&pc)) {
+            STOP_TRAP.to_string()
+        } else {
+            STOP_HISTORY_BEGIN.to_string()
+        }
+    }
+
+    /// Run frames in real time until a breakpoint, a fault, or an interrupt from the debugger,
+    /// returning the stop reply
+    fn run(&mut self, stream: &mut TcpStream) -> Result<String> {
+        // the instruction execution resumes from isn't stopped at, so a breakpoint there doesn't
+        // stop the program straight away
+        let mut first = true;
+        let mut next_frame = Instant::now();
+        loop {
+            let breakpoints = &self.breakpoints;
+            let res = self.chip8.run_frame_until(|pc| {
+                !std::mem::replace(&mut first, false) && breakpoints.contains(&pc)
+            });
+            match res {
+                Ok(true) => return Ok(STOP_TRAP.to_string()),
+                Ok(false) => (),
+                Err(e) => {
+                    log::error!("Stopped by fault: {}", e);
+                    return Ok(STOP_FAULT.to_string());
+                }
+            }
+
+            if interrupted(stream)? {
+                return Ok(STOP_TRAP.to_string());
+            }
+            next_frame += FRAME_TIME;
+            let now = Instant::now();
+            if next_frame > now {
+                thread::sleep(next_frame - now);
+            } else {
+                // running behind, don't try to catch up
+                next_frame = now;
+            }
+        }
+    }
+
+    /// Value of register `reg`, in target byte order
+    fn register(&self, reg: usize) -> Vec<u8> {
+        let chip8 = &self.chip8;
+        match reg {
+            0..=15 => vec![chip8.regs[reg as u8]],
+            16 => chip8.i.to_le_bytes().to_vec(),
+            17 => chip8.pc.to_le_bytes().to_vec(),
+            18 => vec![chip8.sp],
+            19 => vec![chip8.dt],
+            _ => vec![chip8.st],
+        }
+    }
+
+    /// Set register `reg` from `bytes`, in target byte order
+    fn set_register(&mut self, reg: usize, bytes: &[u8]) -> Option<()> {
+        let chip8 = &mut self.chip8;
+        let word = || Some(u16::from_le_bytes([*bytes.first()?, *bytes.get(1)?]));
+        let byte = *bytes.first()?;
+        match reg {
+            0..=15 => chip8.regs[reg as u8] = byte,
+            16 => chip8.i = word()?,
+            17 => chip8.pc = word()?,
+            18 if (byte as usize) <= chip8.stack.len() => chip8.sp = byte,
+            19 => chip8.dt = byte,
+            20 => chip8.st = byte,
+            _ => return None,
+        }
+        Some(())
+    }
+
+    /// `g`: all registers
+    fn read_registers(&self) -> String {
+        (0..REG_COUNT).map(|reg| hex(&self.register(reg))).collect()
+    }
+
+    /// `G XX...`: set all registers
+    fn write_registers(&mut self, args: &str) -> Option<()> {
+        let bytes = unhex(args)?;
+        let mut off = 0;
+        for reg in 0..REG_COUNT {
+            let len = self.register(reg).len();
+            self.set_register(reg, bytes.get(off..off + len)?)?;
+            off += len;
+        }
+        Some(())
+    }
+
+    /// `P n=XX...`: set a single register
+    fn write_register(&mut self, args: &str) -> Option<()> {
+        let (reg, value) = args.split_once('=')?;
+        let reg = usize::from_str_radix(reg, 16).ok()?;
+        self.set_register(reg, &unhex(value)?)
+    }
+
+    /// `m addr,length`: read memory
+    fn read_memory(&self, args: &str) -> Option<String> {
+        let (addr, len) = parse_pair(args, ',')?;
+        let end = addr.checked_add(len)?;
+        if end > Ram::RAM_SIZE {
+            return None;
+        }
+        Some(hex(&self.chip8.ram[addr..end]))
+    }
+
+    /// `M addr,length:XX...`: write memory
+    fn write_memory(&mut self, args: &str) -> Option<()> {
+        let (range, data) = args.split_once(':')?;
+        let (addr, len) = parse_pair(range, ',')?;
+        let data = unhex(data)?;
+        if data.len() != len || addr.checked_add(len)? > Ram::RAM_SIZE {
+            return None;
+        }
+        self.chip8.write_mem(addr as u16, &data).ok()
+    }
+
+    /// `Z type,addr,kind` or `z type,addr,kind`: insert or remove a breakpoint
+    ///
+    /// Returns `Some(false)` for watchpoints, which aren't supported.
+    fn breakpoint(&mut self, args: &str, insert: bool) -> Option<bool> {
+        let mut parts = args.split(',');
+        let kind = parts.next()?;
+        let addr = u16::from_str_radix(parts.next()?, 16).ok()?;
+        if kind != "0" && kind != "1" {
+            return Some(false);
+        }
+        if insert {
+            self.breakpoints.insert(addr);
+        } else {
+            self.breakpoints.remove(&addr);
+        }
+        Some(true)
+    }
+}
+
+/// Whether the debugger has sent an interrupt, without blocking
+fn interrupted(stream: &mut TcpStream) -> Result<bool> {
+    stream.set_nonblocking(true)?;
+    let mut byte = [0];
+    let res = stream.read(&mut byte);
+    stream.set_nonblocking(false)?;
+    match res {
+        Ok(1) => Ok(byte[0] == INTERRUPT),
+        // closed, treat it as an interrupt so the session ends
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Wrap `data` in a packet, with its checksum
+fn frame(data: &str) -> String {
+    let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+    format!("${}#{:02x}", data, checksum)
+}
+
+/// Encode `bytes` as lowercase hex
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode hex into bytes
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parse two hex numbers separated by `sep`
+fn parse_pair(s: &str, sep: char) -> Option<(usize, usize)> {
+    let (a, b) = s.split_once(sep)?;
+    Some((
+        usize::from_str_radix(a, 16).ok()?,
+        usize::from_str_radix(b, 16).ok()?,
+    ))
+}
+
+/// Reply to a `qXfer` read of `len` bytes of `doc` from `offset`
+fn xfer(doc: &str, offset: usize, len: usize) -> String {
+    let rest = doc.get(offset.min(doc.len())..).unwrap_or("");
+    if rest.len() <= len {
+        format!("l{}", rest)
+    } else {
+        format!("m{}", &rest[..len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Emulator;
+
+    #[test]
+    fn packets() {
+        assert_eq!(frame("OK"), "$OK#9a");
+        assert_eq!(unhex("0aff"), Some(vec![0x0A, 0xFF]));
+        assert_eq!(unhex("0af"), None);
+        assert_eq!(xfer("abcdef", 2, 2), "mcd");
+        assert_eq!(xfer("abcdef", 4, 10), "lef");
+
+        let mut chip8 = Chip8::new();
+        let stub = GdbStub::new(&mut chip8);
+        let mut input: &[u8] = b"+$m200,2#00\x03";
+        assert_eq!(
+            stub.read_packet(&mut input).unwrap(),
+            Some(Some("m200,2".to_string()))
+        );
+        assert_eq!(stub.read_packet(&mut input).unwrap(), Some(None));
+        assert_eq!(stub.read_packet(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn registers_memory_and_breakpoints() {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_rom_bytes(&[0x60, 0x12, 0xA3, 0x45, 0x12, 0x04])
+            .unwrap();
+        let mut stub = GdbStub::new(&mut chip8);
+        let reply = |s: &str| Action::Reply(s.to_string());
+
+        assert_eq!(stub.handle("m200,4"), reply("6012a345"));
+        assert_eq!(stub.handle("s"), Action::Step);
+        stub.step();
+        stub.step();
+        assert_eq!(stub.handle("p0"), reply("12"));
+        assert_eq!(stub.handle("p10"), reply("4503"));
+        assert_eq!(stub.handle("p11"), reply("0402"));
+        assert_eq!(stub.read_registers().len(), 2 * (REG_COUNT + 2));
+
+        assert_eq!(stub.handle("bs"), reply(STOP_TRAP));
+        assert_eq!(stub.handle("p10"), reply("0000"));
+        assert_eq!(stub.handle("bc"), reply(STOP_HISTORY_BEGIN));
+        assert_eq!(stub.handle("p0"), reply("00"));
+
+        assert_eq!(stub.handle("P11=0002"), reply("OK"));
+        assert_eq!(stub.handle("M300,2:abcd"), reply("OK"));
+        assert_eq!(stub.handle("m300,2"), reply("abcd"));
+        assert_eq!(stub.handle("mfff,2"), reply("E01"));
+        // `monitor poke 301 ef`
+        assert_eq!(
+            stub.handle("qRcmd,706f6b652033303120656620"),
+            Action::Reply(hex(b"Wrote 1 bytes at 0x301\n"))
+        );
+        assert_eq!(stub.handle("m300,2"), reply("abef"));
+
+        assert_eq!(stub.handle("Z0,202,2"), reply("OK"));
+        assert_eq!(stub.handle("Z2,300,1"), reply(""));
+        assert!(stub.breakpoints.contains(&0x202));
+        assert_eq!(stub.handle("z0,202,2"), reply("OK"));
+        assert!(stub.breakpoints.is_empty());
+
+        assert!(
+            matches!(stub.handle("qXfer:features:read:target.xml:0,20"), Action::Reply(r) if r.starts_with("m<?xml"))
+        );
+        assert_eq!(stub.handle("D"), Action::Close(Some("OK".to_string())));
+    }
+}
@@ -0,0 +1,343 @@
+//! Memory viewer and editor.
+//!
+//! [`Hexdump`] formats a range of memory, 16 bytes a row by default, with optional columns for
+//! the bytes as ASCII and as sprite rows, `#` for each set bit:
+//!
+//! ```text
+//! 200:   60  12 >A3 >45  D0  15 *12  04  |`..E....|  .##..... ...#..#. ...
+//! ```
+//!
+//! The bytes of the instruction at `pc` are marked `>`, the byte at `I` `@`, and bytes which
+//! changed since an earlier copy of memory `*`, in colour too if asked for.
+//!
+//! [`Monitor`] is a small command interpreter for viewing and editing memory from a debugger,
+//! e.g. through `monitor` in gdb or the debug console of an editor:
+//!
+//! * `x ADDR [LEN]` - dump `LEN` bytes (default 64) from `ADDR`, marking bytes changed since the
+//!   last dump
+//! * `poke ADDR BYTE...` - write bytes from `ADDR`
+//! * `sprites N [ADDR [COUNT]]` - draw `COUNT` sprites (default 1) of the height `DRW` takes, so
+//!   `0` for 16x16, from `ADDR` (default `I`), see [`SpriteSheet`]
+//!
+//! Numbers are hex, with or without a `0x` prefix.
+//!
+//! [`Hexdump`]: struct.Hexdump.html
+//! [`Monitor`]: struct.Monitor.html
+//! [`SpriteSheet`]: ../sprites/struct.SpriteSheet.html
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+
+use super::{memory::Ram, sprites::SpriteSize, Chip8};
+
+/// Bytes shown by `x` when no length is given
+const DEFAULT_DUMP_LEN: usize = 0x40;
+
+/// Why a byte is highlighted, in order of precedence
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Mark {
+    Pc,
+    I,
+    Changed,
+}
+
+impl Mark {
+    fn marker(self) -> char {
+        match self {
+            Self::Pc => '>',
+            Self::I => '@',
+            Self::Changed => '*',
+        }
+    }
+
+    /// ANSI escape code starting the highlight
+    fn color(self) -> &'static str {
+        match self {
+            // reverse video, underline and bold yellow
+            Self::Pc => "\x1b[7m",
+            Self::I => "\x1b[4m",
+            Self::Changed => "\x1b[1;33m",
+        }
+    }
+}
+
+/// Formatter of a hexdump, see the [module documentation](index.html)
+#[derive(Debug, Clone)]
+pub struct Hexdump<'a> {
+    mem: &'a [u8],
+    range: Range<usize>,
+    width: usize,
+    ascii: bool,
+    glyphs: bool,
+    color: bool,
+    pc: Option<usize>,
+    i: Option<usize>,
+    previous: Option<&'a [u8]>,
+}
+
+impl<'a> Hexdump<'a> {
+    /// Create a `Hexdump` of all of `mem`, 16 bytes a row with an ASCII column
+    pub fn new(mem: &'a [u8]) -> Self {
+        Self {
+            mem,
+            range: 0..mem.len(),
+            width: 16,
+            ascii: true,
+            glyphs: false,
+            color: false,
+            pc: None,
+            i: None,
+            previous: None,
+        }
+    }
+
+    /// Only show `range`, limited to the end of memory
+    pub fn range(mut self, range: Range<usize>) -> Self {
+        let end = range.end.min(self.mem.len());
+        self.range = range.start.min(end)..end;
+        self
+    }
+
+    /// Show `width` bytes a row, at least 1
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = width.max(1);
+        self
+    }
+
+    /// Show or hide the ASCII column
+    pub fn ascii(mut self, enabled: bool) -> Self {
+        self.ascii = enabled;
+        self
+    }
+
+    /// Show or hide the sprite column, best read with a narrow width
+    pub fn glyphs(mut self, enabled: bool) -> Self {
+        self.glyphs = enabled;
+        self
+    }
+
+    /// Highlight with ANSI colours as well as markers
+    pub fn color(mut self, enabled: bool) -> Self {
+        self.color = enabled;
+        self
+    }
+
+    /// Mark the instruction at `pc`
+    pub fn pc(mut self, pc: u16) -> Self {
+        self.pc = Some(pc as usize);
+        self
+    }
+
+    /// Mark the byte at `I`
+    pub fn i(mut self, i: u16) -> Self {
+        self.i = Some(i as usize);
+        self
+    }
+
+    /// Mark bytes which differ from `previous`, an earlier copy of memory
+    pub fn compare(mut self, previous: &'a [u8]) -> Self {
+        self.previous = Some(previous);
+        self
+    }
+
+    fn mark(&self, addr: usize) -> Option<Mark> {
+        if self.pc.is_some_and(|pc| addr == pc || addr == pc + 1) {
+            Some(Mark::Pc)
+        } else if self.i == Some(addr) {
+            Some(Mark::I)
+        } else if matches!(self.previous, Some(prev) if prev.get(addr) != self.mem.get(addr)) {
+            Some(Mark::Changed)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Hexdump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = self.range.clone().step_by(self.width);
+        for start in rows {
+            let end = (start + self.width).min(self.range.end);
+            let bytes = &self.mem[start..end];
+
+            write!(f, "{:03X}: ", start)?;
+            for (addr, byte) in (start..end).zip(bytes) {
+                match self.mark(addr) {
+                    Some(mark) if self.color => {
+                        write!(f, " {}{}{:02X}\x1b[0m", mark.color(), mark.marker(), byte)?
+                    }
+                    Some(mark) => write!(f, " {}{:02X}", mark.marker(), byte)?,
+                    None => write!(f, "  {:02X}", byte)?,
+                }
+            }
+            // line up the columns of a short last row
+            let missing = self.width - bytes.len();
+            write!(f, "{:width$}", "", width = 4 * missing)?;
+
+            if self.ascii {
+                let text: String = bytes
+                    .iter()
+                    .map(|b| match b {
+                        0x20..=0x7E => *b as char,
+                        _ => '.',
+                    })
+                    .collect();
+                write!(f, "  |{}|{:width$}", text, "", width = missing)?;
+            }
+            if self.glyphs {
+                write!(f, " ")?;
+                for byte in bytes {
+                    let glyph: String = (0..8)
+                        .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+                        .collect();
+                    write!(f, " {}", glyph)?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Memory commands for debuggers, see the [module documentation](index.html)
+#[derive(Debug, Default)]
+pub struct Monitor {
+    /// Memory at the last dump, to mark changes against
+    previous: Option<Vec<u8>>,
+}
+
+impl Monitor {
+    /// Create a new `Monitor`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run the command `line` on `chip8`, returning its output or why it failed
+    pub fn run(&mut self, chip8: &mut Chip8, line: &str) -> Result<String, String> {
+        let mut words = line.split_whitespace();
+        let cmd = words.next().unwrap_or_default();
+        let args = words
+            .map(|word| {
+                let hex = word
+                    .strip_prefix("0x")
+                    .or_else(|| word.strip_prefix("0X"))
+                    .unwrap_or(word);
+                usize::from_str_radix(hex, 16).map_err(|_| format!("invalid number `{}`", word))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match (cmd, &args[..]) {
+            ("x", [addr]) => Ok(self.dump(chip8, *addr, DEFAULT_DUMP_LEN)),
+            ("x", [addr, len]) => Ok(self.dump(chip8, *addr, *len)),
+            ("poke", [addr, bytes @ ..]) if !bytes.is_empty() => {
+                let bytes = bytes
+                    .iter()
+                    .map(|b| u8::try_from(*b).map_err(|_| format!("{:#X} isn't a byte", b)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let fits = addr
+                    .checked_add(bytes.len())
+                    .filter(|end| *end <= Ram::RAM_SIZE);
+                let start = match (u16::try_from(*addr), fits) {
+                    (Ok(start), Some(_)) => start,
+                    _ => {
+                        return Err(format!(
+                            "{:#X} bytes at {:#X} don't fit in RAM",
+                            bytes.len(),
+                            addr
+                        ))
+                    }
+                };
+                chip8.write_mem(start, &bytes).map_err(|e| e.to_string())?;
+                Ok(format!("Wrote {} bytes at {:#05X}\n", bytes.len(), addr))
+            }
+            ("sprites", [n, rest @ ..]) if rest.len() <= 2 => {
+                let size = match u8::try_from(*n) {
+                    Ok(n @ 0..=0xF) => SpriteSize::from_nibble(n),
+                    _ => return Err(format!("{:#X} isn't a sprite height", n)),
+                };
+                let addr = rest.first().map_or(chip8.i as usize, |addr| *addr);
+                let addr = u16::try_from(addr)
+                    .ok()
+                    .filter(|addr| (*addr as usize) < Ram::RAM_SIZE)
+                    .ok_or_else(|| format!("{:#X} is outside of RAM", addr))?;
+                let count = rest.get(1).copied().unwrap_or(1);
+                Ok(chip8.ram.sprites(addr, size).count(count).to_string())
+            }
+            _ => Err(
+                "usage: `x ADDR [LEN]`, `poke ADDR BYTE...` or `sprites N [ADDR [COUNT]]`"
+                    .to_string(),
+            ),
+        }
+    }
+
+    /// Dump `len` bytes from `addr`, marking changes since the last dump
+    fn dump(&mut self, chip8: &Chip8, addr: usize, len: usize) -> String {
+        let mem = &chip8.ram[0..Ram::RAM_SIZE];
+        let mut dump = Hexdump::new(mem)
+            .range(addr..addr.saturating_add(len))
+            .pc(chip8.pc)
+            .i(chip8.i);
+        if let Some(previous) = &self.previous {
+            dump = dump.compare(previous);
+        }
+        let text = dump.to_string();
+        self.previous = Some(mem.to_vec());
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Emulator;
+
+    #[test]
+    fn format() {
+        let mem = [0x41, 0x42, 0x00, 0xFF, 0x10];
+        let dump = Hexdump::new(&mem).width(4).pc(0x1).i(0x3).to_string();
+        assert_eq!(
+            dump,
+            "000:   41 >42 >00 @FF  |AB..|\n004:   10              |.|   \n"
+        );
+
+        let dump = Hexdump::new(&mem)
+            .range(3..10)
+            .ascii(false)
+            .glyphs(true)
+            .compare(&[0; 5])
+            .to_string();
+        assert!(dump.starts_with("003:  *FF *10"));
+        assert!(dump.ends_with("  ######## ...#....\n"));
+    }
+
+    #[test]
+    fn monitor() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x12, 0x00]).unwrap();
+        let mut monitor = Monitor::new();
+
+        assert!(monitor
+            .run(&mut chip8, "x 200 2")
+            .unwrap()
+            .starts_with("200:  >12 >00"));
+        assert!(monitor.run(&mut chip8, "poke 0x300 ab cd").is_ok());
+        assert_eq!(&chip8.ram[0x300..0x302], &[0xAB, 0xCD]);
+        assert!(monitor
+            .run(&mut chip8, "x 300 2")
+            .unwrap()
+            .starts_with("300:  *AB *CD"));
+        assert!(monitor.run(&mut chip8, "poke 300 100").is_err());
+        assert!(monitor.run(&mut chip8, "poke fff 1 2").is_err());
+        assert!(monitor.run(&mut chip8, "poke 10200 1").is_err());
+        assert!(monitor.run(&mut chip8, "poke ffffffffffffffff 1").is_err());
+        assert!(monitor.run(&mut chip8, "dump").is_err());
+
+        chip8.i = 0x300;
+        let sprite = monitor.run(&mut chip8, "sprites 2").unwrap();
+        assert_eq!(sprite, "300\n#.#.#.##\n##..##.#\n");
+        let sprites = monitor.run(&mut chip8, "sprites 0 0 2").unwrap();
+        assert!(sprites.starts_with(&format!("000{:15}020\n", "")));
+        assert!(monitor.run(&mut chip8, "sprites 10").is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! Execution history, for stepping backwards.
+//!
+//! While history is enabled (see [`Chip8::set_history`]), an undo record is kept for each
+//! executed instruction: the registers, stack and timers before it ran, the old values of the
+//! memory bytes it overwrote, and the display if it drew to it. Undoing records one at a time
+//! steps backwards through execution, see [`Chip8::step_back`] and [`Chip8::run_back_until`].
+//!
+//! Only the newest records are kept, so memory use is bounded. Input and sound aren't recorded,
+//! and profiling and coverage counts aren't undone.
+//!
+//! [`Chip8::set_history`]: ../struct.Chip8.html#method.set_history
+//! [`Chip8::step_back`]: ../struct.Chip8.html#method.step_back
+//! [`Chip8::run_back_until`]: ../struct.Chip8.html#method.run_back_until
+
+use std::collections::VecDeque;
+
+use super::{display::Display, register::Regs};
+
+/// Instructions debuggers keep history for
+pub const DEFAULT_CAPACITY: usize = 100_000;
+
+/// State to restore to undo a single instruction
+#[derive(Debug, Clone)]
+pub(crate) struct Undo {
+    pub(crate) regs: Regs,
+    pub(crate) i: u16,
+    pub(crate) pc: u16,
+    pub(crate) sp: u8,
+    pub(crate) stack: [u16; super::STACK_SIZE],
+    pub(crate) dt: u8,
+    pub(crate) st: u8,
+    pub(crate) rng: u32,
+    /// Overwritten memory bytes and their old values, in the order they were written
+    pub(crate) memory: Vec<(u16, u8)>,
+    /// The display, if the instruction drew to it
+    pub(crate) display: Option<Display>,
+}
+
+/// Undo records of the most recently executed instructions
+#[derive(Debug, Clone)]
+pub struct History {
+    records: VecDeque<Undo>,
+    capacity: usize,
+}
+
+impl History {
+    /// Create an empty `History`, keeping records of up to `capacity` instructions
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Instructions that can be undone
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Whether there is nothing to undo
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Most instructions that can be undone
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Forget everything, e.g. after the machine was changed from outside
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    /// Add the record of the latest instruction, dropping the oldest if full
+    pub(crate) fn push(&mut self, undo: Undo) {
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        if self.capacity > 0 {
+            self.records.push_back(undo);
+        }
+    }
+
+    /// Take the record of the latest instruction
+    pub(crate) fn pop(&mut self) -> Option<Undo> {
+        self.records.pop_back()
+    }
+}
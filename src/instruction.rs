@@ -21,56 +21,132 @@
 use std::fmt;
 
 use super::{
+    error::{Chip8Error, Result},
+    font,
     opcode::{OpCode, Operands},
+    optable::{self, OpDef},
+    symbols::Symbols,
     Chip8,
 };
 
 /// The function an [`OpCode`] executes
 ///
 /// [`OpCode`]: ../opcode/struct.OpCode.html
-pub type InstrFn = fn(&mut Chip8, Operands);
+pub type InstrFn = fn(&mut Chip8, Operands) -> Result<()>;
 pub type InstrName = &'static str;
 
+/// An [`OpCode`] decoded according to its row of the [`optable`]
+///
+/// [`OpCode`]: ../opcode/struct.OpCode.html
+/// [`optable`]: ../optable/index.html
+#[derive(Debug, Copy, Clone)]
 pub struct Instruction {
     opcode: OpCode,
-    name: InstrName,
+    def: &'static OpDef,
     operands: Operands,
-    #[allow(dead_code)]
-    instruction: InstrFn,
 }
 
 impl Instruction {
-    /// Create a new `Instruction`
-    pub fn new(opcode: OpCode, name: InstrName, ops: Operands, inst: InstrFn) -> Self {
+    /// Create a new `Instruction`, decoding the operands of `opcode` as described by `def`
+    pub fn new(opcode: OpCode, def: &'static OpDef) -> Self {
         Self {
             opcode,
-            name,
-            operands: ops,
-            instruction: inst,
+            def,
+            operands: def.format.operands(opcode),
         }
     }
 
+    /// Get the [`OpCode`] the `Instruction` was decoded from
+    ///
+    /// [`OpCode`]: ../opcode/struct.OpCode.html
+    pub fn opcode(&self) -> OpCode {
+        self.opcode
+    }
+
+    /// Get the row of the opcode table the `Instruction` was decoded with
+    pub fn def(&self) -> &'static OpDef {
+        self.def
+    }
+
+    /// Get the mnemonic of the `Instruction`
+    pub fn name(&self) -> InstrName {
+        self.def.mnemonic()
+    }
+
+    /// Get the [`Operands`] of the `Instruction`
+    ///
+    /// [`Operands`]: ../opcode/enum.Operands.html
+    pub fn operands(&self) -> &Operands {
+        &self.operands
+    }
+
+    /// Write the `Instruction` in assembly syntax, e.g. `ADD V1, 0x10`
+    pub fn disassemble(&self) -> String {
+        self.def.disassemble(self.opcode)
+    }
+
+    /// Write the `Instruction` in assembly syntax, with addresses named by `symbols`, e.g.
+    /// `CALL draw_player`
+    pub fn disassemble_with(&self, symbols: Option<&Symbols>) -> String {
+        self.def.disassemble_with(self.opcode, symbols)
+    }
+
     /// Execute an `Instruction`
-    pub fn exec(self, chip8: &mut Chip8) {
-        log::trace!("Execute `{}`", self);
-        let inst = self.instruction;
-        inst(chip8, self.operands);
+    ///
+    /// Fails if the instruction raised a fault, e.g. a protected memory write.
+    pub fn exec(self, chip8: &mut Chip8) -> Result<()> {
+        let inst = self.def.handler;
+        inst(chip8, self.operands)
     }
 }
 
 impl fmt::Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "({:04X}) {:<4}\t{:}",
-            self.opcode, self.name, self.operands
-        )
+        write!(f, "({:04X}) {}", self.opcode, self.disassemble())
     }
 }
 
-pub fn not_implemented(chip8: &mut Chip8, operands: Operands) {
-    let instruction = chip8.get_opcode(chip8.pc - 2).decode();
-    log::warn!("Ignoring unimplemented instruction: {}", instruction);
+/// Skip the next instruction if `cond` holds
+///
+/// Longer instructions, e.g. the XO-CHIP `F000 nnnn`, are skipped as a whole.
+fn skip_if(chip8: &mut Chip8, cond: bool) -> Result<()> {
+    if cond {
+        let opcode = chip8.get_opcode(chip8.pc)?;
+        let len = optable::lookup_for(opcode, chip8.platform).map_or(2, |def| def.len);
+        chip8.pc = chip8.pc.wrapping_add(len);
+    }
+    Ok(())
+}
+
+/// Reset `VF` after a logic instruction, if the quirk is enabled
+fn reset_vf(chip8: &mut Chip8) {
+    if chip8.quirks.vf_reset {
+        chip8.regs[0xF] = 0;
+    }
+}
+
+/// Get the value a shift instruction shifts, `Vy` or `Vx` depending on the quirk
+fn shift_source(chip8: &Chip8, x: u8, y: u8) -> u8 {
+    if chip8.quirks.shift_uses_vy {
+        chip8.regs[y]
+    } else {
+        chip8.regs[x]
+    }
+}
+
+/// Move `I` past the registers `V0..=Vx` after a load or store, if the quirk is enabled
+fn increment_i(chip8: &mut Chip8, x: u8) {
+    if chip8.quirks.load_store_increments_i {
+        chip8.i = chip8.i.wrapping_add(x as u16 + 1);
+    }
+}
+
+pub fn not_implemented(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    match chip8.get_opcode(chip8.pc.wrapping_sub(2)) {
+        Ok(opcode) => log::warn!("Ignoring unimplemented instruction: {}", opcode.decode()),
+        Err(e) => log::warn!("Ignoring unimplemented instruction: {}", e),
+    }
+    Ok(())
 }
 
 /// `0nnn - SYS addr`
@@ -80,15 +156,17 @@ pub fn not_implemented(chip8: &mut Chip8, operands: Operands) {
 /// This instruction is only used on the old computers on which Chip-8 was originally implemented.
 ///
 /// **NOTE** It is ignored by modern interpreters.
-pub fn sys(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn sys(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    log::debug!("Ignoring `SYS {:#05X}`", operands.addr());
+    Ok(())
 }
 
 /// `00E0 - CLS`
 ///
 /// Clear the display.
-pub fn clear(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn clear(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    chip8.display.clear();
+    Ok(())
 }
 
 /// `00EE - RET`
@@ -97,8 +175,15 @@ pub fn clear(chip8: &mut Chip8, operands: Operands) {
 ///
 /// The interpreter sets the program counter to the address at the top of the stack, then subtracts
 /// 1 from the stack pointer.
-pub fn r#return(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn r#return(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    if chip8.sp == 0 {
+        let pc = chip8.pc.wrapping_sub(2);
+        log::error!("Return with an empty stack at {:#06X}", pc);
+        return Err(Chip8Error::StackUnderflow { pc });
+    }
+    chip8.sp -= 1;
+    chip8.pc = chip8.stack[chip8.sp as usize];
+    Ok(())
 }
 
 /// `1nnn - JP addr`
@@ -106,8 +191,9 @@ pub fn r#return(chip8: &mut Chip8, operands: Operands) {
 /// Jump to location `nnn`.
 ///
 /// The interpreter sets the program counter to `nnn`.
-pub fn jump(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn jump(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    chip8.pc = operands.addr();
+    Ok(())
 }
 
 /// `2nnn - CALL addr`
@@ -116,8 +202,17 @@ pub fn jump(chip8: &mut Chip8, operands: Operands) {
 ///
 /// The interpreter increments the stack pointer, then puts the current `PC` on the top of the
 /// stack. The `PC` is then set to `nnn`.
-pub fn call(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn call(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let sp = chip8.sp as usize;
+    if sp == chip8.stack.len() {
+        let pc = chip8.pc.wrapping_sub(2);
+        log::error!("Stack overflow at {:#06X}", pc);
+        return Err(Chip8Error::StackOverflow { pc });
+    }
+    chip8.stack[sp] = chip8.pc;
+    chip8.sp += 1;
+    chip8.pc = operands.addr();
+    Ok(())
 }
 
 /// `3xkk - SE Vx, byte`
@@ -126,8 +221,9 @@ pub fn call(chip8: &mut Chip8, operands: Operands) {
 ///
 /// The interpreter compares register `Vx` to `kk`, and if they are equal, increments the program
 /// counter by 2.
-pub fn skip_eq_byte(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn skip_eq_byte(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let (x, kk) = operands.reg_and_const();
+    skip_if(chip8, chip8.regs[x] == kk)
 }
 
 /// `4xkk - SNE Vx, byte`
@@ -136,8 +232,9 @@ pub fn skip_eq_byte(chip8: &mut Chip8, operands: Operands) {
 ///
 /// The interpreter compares register `Vx` to `kk`, and if they are not equal, increments the
 /// program counter by 2.
-pub fn skip_ne_byte(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn skip_ne_byte(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let (x, kk) = operands.reg_and_const();
+    skip_if(chip8, chip8.regs[x] != kk)
 }
 
 /// `5xy0 - SE Vx, Vy`
@@ -146,8 +243,9 @@ pub fn skip_ne_byte(chip8: &mut Chip8, operands: Operands) {
 ///
 /// The interpreter compares register `Vx` to register `Vy`, and if they are equal, increments the
 /// program counter by 2.
-pub fn skip_eq(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn skip_eq(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let (x, y) = operands.regs();
+    skip_if(chip8, chip8.regs[x] == chip8.regs[y])
 }
 
 /// `6xkk - LD Vx, byte`
@@ -155,8 +253,10 @@ pub fn skip_eq(chip8: &mut Chip8, operands: Operands) {
 /// Set `Vx = kk`.
 ///
 /// The interpreter puts the value `kk` into register `Vx`.
-pub fn load_byte(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn load_byte(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let (x, kk) = operands.reg_and_const();
+    chip8.regs[x] = kk;
+    Ok(())
 }
 
 /// `7xkk - ADD Vx, byte`
@@ -164,8 +264,10 @@ pub fn load_byte(chip8: &mut Chip8, operands: Operands) {
 /// Set `Vx = Vx + kk`.
 ///
 /// Adds the value kk to the value of register `Vx`, then stores the result in `Vx`.
-pub fn add_byte(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn add_byte(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let (x, kk) = operands.reg_and_const();
+    chip8.regs[x] = chip8.regs[x].wrapping_add(kk);
+    Ok(())
 }
 
 /// `8xy0 - LD Vx, Vy`
@@ -173,8 +275,10 @@ pub fn add_byte(chip8: &mut Chip8, operands: Operands) {
 /// Set `Vx = Vy`.
 ///
 /// Stores the value of register `Vy` in register `Vx`.
-pub fn load(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn load(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let (x, y) = operands.regs();
+    chip8.regs[x] = chip8.regs[y];
+    Ok(())
 }
 
 /// `8xy1 - OR Vx, Vy`
@@ -185,8 +289,11 @@ pub fn load(chip8: &mut Chip8, operands: Operands) {
 ///
 /// **NOTE** A bitwise OR compares the corrseponding bits from two values, and if either bit is 1,
 /// then the same bit in the result is also 1. Otherwise, it is 0.
-pub fn or(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn or(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let (x, y) = operands.regs();
+    chip8.regs[x] |= chip8.regs[y];
+    reset_vf(chip8);
+    Ok(())
 }
 
 /// `8xy2 - AND Vx, Vy`
@@ -197,8 +304,11 @@ pub fn or(chip8: &mut Chip8, operands: Operands) {
 ///
 /// **NOTE** A bitwise AND compares the corrseponding bits from two values, and if both bits are 1,
 /// then the same bit in the result is also 1. Otherwise, it is 0.
-pub fn and(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn and(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let (x, y) = operands.regs();
+    chip8.regs[x] &= chip8.regs[y];
+    reset_vf(chip8);
+    Ok(())
 }
 
 /// `8xy3 - XOR Vx, Vy`
@@ -209,8 +319,11 @@ pub fn and(chip8: &mut Chip8, operands: Operands) {
 ///
 /// **NOTE** An exclusive OR compares the corrseponding bits from two values, and if the bits are
 /// not both the same, then the corresponding bit in the result is set to 1. Otherwise, it is 0.
-pub fn xor(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn xor(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let (x, y) = operands.regs();
+    chip8.regs[x] ^= chip8.regs[y];
+    reset_vf(chip8);
+    Ok(())
 }
 
 /// `8xy4 - ADD Vx, Vy`
@@ -220,8 +333,12 @@ pub fn xor(chip8: &mut Chip8, operands: Operands) {
 /// The values of `Vx` and `Vy` are added together. If the result is greater than 8 bits (i.e.,
 /// `> 255`,) `VF` is set to 1, otherwise 0. Only the lowest 8 bits of the result are kept, and
 /// stored in `Vx`.
-pub fn add(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn add(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let (x, y) = operands.regs();
+    let (sum, carry) = chip8.regs[x].overflowing_add(chip8.regs[y]);
+    chip8.regs[x] = sum;
+    chip8.regs[0xF] = carry as u8;
+    Ok(())
 }
 
 /// `8xy5 - SUB Vx, Vy`
@@ -230,8 +347,12 @@ pub fn add(chip8: &mut Chip8, operands: Operands) {
 ///
 /// If `Vx > Vy`, then `VF` is set to 1, otherwise 0. Then `Vy` is subtracted from `Vx`, and the
 /// results stored in `Vx`.
-pub fn sub(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn sub(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let (x, y) = operands.regs();
+    let (diff, borrow) = chip8.regs[x].overflowing_sub(chip8.regs[y]);
+    chip8.regs[x] = diff;
+    chip8.regs[0xF] = !borrow as u8;
+    Ok(())
 }
 
 /// `8xy6 - SHR Vx {, Vy}`
@@ -240,8 +361,12 @@ pub fn sub(chip8: &mut Chip8, operands: Operands) {
 ///
 /// If the least-significant bit of Vx is 1, then VF is set to 1, otherwise 0. Then Vx is divided
 /// by 2.
-pub fn shift_right(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn shift_right(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let (x, y) = operands.regs();
+    let val = shift_source(chip8, x, y);
+    chip8.regs[x] = val >> 1;
+    chip8.regs[0xF] = val & 0x1;
+    Ok(())
 }
 
 /// `8xy7 - SUBN Vx, Vy`
@@ -250,8 +375,12 @@ pub fn shift_right(chip8: &mut Chip8, operands: Operands) {
 ///
 /// If `Vy > Vx`, then `VF` is set to 1, otherwise 0. Then `Vx` is subtracted from `Vy`, and the
 /// results stored in `Vx`.
-pub fn sub_inv(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn sub_inv(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let (x, y) = operands.regs();
+    let (diff, borrow) = chip8.regs[y].overflowing_sub(chip8.regs[x]);
+    chip8.regs[x] = diff;
+    chip8.regs[0xF] = !borrow as u8;
+    Ok(())
 }
 
 /// `8xyE - SHL Vx {, Vy}`
@@ -260,8 +389,12 @@ pub fn sub_inv(chip8: &mut Chip8, operands: Operands) {
 ///
 /// If the most-significant bit of `Vx` is 1, then `VF` is set to 1, otherwise to 0. Then `Vx` is
 /// multiplied by 2.
-pub fn shift_left(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn shift_left(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let (x, y) = operands.regs();
+    let val = shift_source(chip8, x, y);
+    chip8.regs[x] = val << 1;
+    chip8.regs[0xF] = val >> 7;
+    Ok(())
 }
 
 /// `9xy0 - SNE Vx, Vy`
@@ -270,8 +403,9 @@ pub fn shift_left(chip8: &mut Chip8, operands: Operands) {
 ///
 /// The values of `Vx` and `Vy` are compared, and if they are not equal, the program counter is
 /// increased by 2.
-pub fn skip_ne(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn skip_ne(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let (x, y) = operands.regs();
+    skip_if(chip8, chip8.regs[x] != chip8.regs[y])
 }
 
 /// `Annn - LD I, addr`
@@ -279,8 +413,9 @@ pub fn skip_ne(chip8: &mut Chip8, operands: Operands) {
 /// Set `I = nnn`.
 ///
 /// The value of register `I` is set to `nnn`.
-pub fn load_i(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn load_i(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    chip8.i = operands.addr();
+    Ok(())
 }
 
 /// `Bnnn - JP V0, addr`
@@ -288,8 +423,15 @@ pub fn load_i(chip8: &mut Chip8, operands: Operands) {
 /// Jump to location `nnn + V0`.
 ///
 /// The program counter is set to `nnn` plus the value of `V0`.
-pub fn jump0(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn jump0(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let nnn = operands.addr();
+    let x = if chip8.quirks.jump_uses_vx {
+        (nnn >> 8) as u8
+    } else {
+        0
+    };
+    chip8.pc = nnn.wrapping_add(chip8.regs[x] as u16);
+    Ok(())
 }
 
 /// `Cxkk - RND Vx, byte`
@@ -301,8 +443,10 @@ pub fn jump0(chip8: &mut Chip8, operands: Operands) {
 /// AND.
 ///
 /// [`8xy2`]: TODO
-pub fn rand_byte(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn rand_byte(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let (x, kk) = operands.reg_and_const();
+    chip8.regs[x] = chip8.random_byte() & kk;
+    Ok(())
 }
 
 /// `Dxyn - DRW Vx, Vy, nibble`
@@ -316,10 +460,17 @@ pub fn rand_byte(chip8: &mut Chip8, operands: Operands) {
 /// wraps around to the opposite side of the screen. See instruction [`8xy3`] for more information
 /// on XOR, and [`Display`], for more information on the Chip-8 screen and sprites.
 ///
-/// [`8xy3`]: TODO
-/// [`Display`]: TODO
-pub fn draw_sprite(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+/// [`8xy3`]: fn.xor.html
+/// [`Display`]: ../display/index.html
+pub fn draw_sprite(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let (x, y, n) = operands.regs_and_const();
+    let (vx, vy) = (chip8.regs[x] as usize, chip8.regs[y] as usize);
+
+    let sprite = chip8.ram.read_slice(chip8.i as usize, n as usize)?;
+    let clip = chip8.quirks.clip_sprites;
+    let collision = chip8.display.draw_sprite(vx, vy, &sprite, clip);
+    chip8.regs[0xF] = collision as u8;
+    Ok(())
 }
 
 /// `Ex9E - SKP Vx`
@@ -328,8 +479,9 @@ pub fn draw_sprite(chip8: &mut Chip8, operands: Operands) {
 ///
 /// Checks the keyboard, and if the key corresponding to the value of `Vx` is currently in the down
 /// position, `PC` is increased by 2.
-pub fn skip_pressed(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn skip_pressed(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let pressed = chip8.keypad.is_pressed(chip8.regs[operands.reg()]);
+    skip_if(chip8, pressed)
 }
 
 /// `ExA1 - SKNP Vx`
@@ -338,8 +490,9 @@ pub fn skip_pressed(chip8: &mut Chip8, operands: Operands) {
 ///
 /// Checks the keyboard, and if the key corresponding to the value of `Vx` is currently in the up
 /// position, `PC` is increased by 2.
-pub fn skip_not_pressed(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn skip_not_pressed(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let pressed = chip8.keypad.is_pressed(chip8.regs[operands.reg()]);
+    skip_if(chip8, !pressed)
 }
 
 /// `Fx07 - LD Vx, DT`
@@ -347,8 +500,9 @@ pub fn skip_not_pressed(chip8: &mut Chip8, operands: Operands) {
 /// Set `Vx = delay timer value`.
 ///
 /// The value of `DT` is placed into `Vx`.
-pub fn load_dt(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn load_dt(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    chip8.regs[operands.reg()] = chip8.dt;
+    Ok(())
 }
 
 /// `Fx0A - LD Vx, K`
@@ -356,8 +510,15 @@ pub fn load_dt(chip8: &mut Chip8, operands: Operands) {
 /// Wait for a key press, store the value of the key in `Vx`.
 ///
 /// All execution stops until a key is pressed, then the value of that key is stored in `Vx`.
-pub fn wait_for_key(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+///
+/// **NOTE** As on the COSMAC VIP, the key is stored once it is released. While waiting, the
+/// instruction is repeated, so timers keep counting down.
+pub fn wait_for_key(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    match chip8.keypad.take_released() {
+        Some(key) => chip8.regs[operands.reg()] = key,
+        None => chip8.pc = chip8.pc.wrapping_sub(2),
+    }
+    Ok(())
 }
 
 /// `Fx15 - LD DT, Vx`
@@ -365,8 +526,9 @@ pub fn wait_for_key(chip8: &mut Chip8, operands: Operands) {
 /// Set `delay timer = Vx`.
 ///
 /// `DT` is set equal to the value of `Vx`.
-pub fn set_delay_timer(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn set_delay_timer(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    chip8.dt = chip8.regs[operands.reg()];
+    Ok(())
 }
 
 /// `Fx18 - LD ST, Vx`
@@ -374,8 +536,9 @@ pub fn set_delay_timer(chip8: &mut Chip8, operands: Operands) {
 /// Set `sound timer = Vx`.
 ///
 /// `ST` is set equal to the value of `Vx`.
-pub fn set_sound_timer(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn set_sound_timer(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    chip8.st = chip8.regs[operands.reg()];
+    Ok(())
 }
 
 /// `Fx1E - ADD I, Vx`
@@ -383,8 +546,9 @@ pub fn set_sound_timer(chip8: &mut Chip8, operands: Operands) {
 /// Set `I = I + Vx`.
 ///
 /// The values of `I` and `Vx` are added, and the results are stored in `I`.
-pub fn add_i(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn add_i(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    chip8.i = chip8.i.wrapping_add(chip8.regs[operands.reg()] as u16);
+    Ok(())
 }
 
 /// `Fx29 - LD F, Vx`
@@ -395,8 +559,10 @@ pub fn add_i(chip8: &mut Chip8, operands: Operands) {
 /// of `Vx`. See [`Display`], for more information on the Chip-8 hexadecimal font.
 ///
 /// [`Display`]: TODO
-pub fn load_sprite(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn load_sprite(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let digit = (chip8.regs[operands.reg()] & 0xF) as u16;
+    chip8.i = font::FONT_ADDR + digit * font::GLYPH_SIZE as u16;
+    Ok(())
 }
 
 /// `Fx33 - LD B, Vx`
@@ -405,8 +571,9 @@ pub fn load_sprite(chip8: &mut Chip8, operands: Operands) {
 ///
 /// The interpreter takes the decimal value of `Vx`, and places the hundreds digit in memory at
 /// location in `I`, the tens digit at location `I+1`, and the ones digit at location `I+2`.
-pub fn store_bcd(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn store_bcd(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let vx = chip8.regs[operands.reg()];
+    chip8.store_mem_slice(chip8.i as usize, &[vx / 100, vx / 10 % 10, vx % 10])
 }
 
 /// `Fx55 - LD [I], Vx`
@@ -415,8 +582,12 @@ pub fn store_bcd(chip8: &mut Chip8, operands: Operands) {
 ///
 /// The interpreter copies the values of registers `V0` through `Vx` into memory, starting at the
 /// address in `I`.
-pub fn store_regs(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn store_regs(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let x = operands.reg();
+    let regs: Vec<u8> = (0..=x).map(|r| chip8.regs[r]).collect();
+    chip8.store_mem_slice(chip8.i as usize, &regs)?;
+    increment_i(chip8, x);
+    Ok(())
 }
 
 /// `Fx65 - LD Vx, [I]`
@@ -425,6 +596,12 @@ pub fn store_regs(chip8: &mut Chip8, operands: Operands) {
 ///
 /// The interpreter reads values from memory starting at location `I` into registers `V0` through
 /// `Vx`.
-pub fn load_regs(chip8: &mut Chip8, operands: Operands) {
-    todo!()
+pub fn load_regs(chip8: &mut Chip8, operands: Operands) -> Result<()> {
+    let x = operands.reg();
+    let bytes = chip8.ram.read_slice(chip8.i as usize, x as usize + 1)?;
+    for (r, byte) in (0..=x).zip(bytes.iter()) {
+        chip8.regs[r] = *byte;
+    }
+    increment_i(chip8, x);
+    Ok(())
 }
@@ -0,0 +1,238 @@
+//! Mapping of host keys to the Chip-8 keypad.
+//!
+//! Frontends report host keys by name (e.g. `"W"`, `"Up"`, `"Space"`), which are compared
+//! ignoring case. The default mapping uses the left hand side of a QWERTY keyboard:
+//!
+//! ```text
+//! 1 2 3 4        1 2 3 C
+//! Q W E R   ->   4 5 6 D
+//! A S D F        7 8 9 E
+//! Z X C V        A 0 B F
+//! ```
+//!
+//! Any number of host keys may map to the same keypad key. Keys the [ROM database] gives a
+//! purpose are also mapped to the arrow keys, `Space` and `Enter`, or `IJKL`, `U` and `O` for a
+//! second player.
+//!
+//! [ROM database]: ../database/index.html
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use super::keypad::{Keypad, KEY_COUNT};
+
+/// Host keys of the default mapping, in keypad layout order
+const QWERTY: [(&str, u8); KEY_COUNT] = [
+    ("1", 0x1),
+    ("2", 0x2),
+    ("3", 0x3),
+    ("4", 0xC),
+    ("Q", 0x4),
+    ("W", 0x5),
+    ("E", 0x6),
+    ("R", 0xD),
+    ("A", 0x7),
+    ("S", 0x8),
+    ("D", 0x9),
+    ("F", 0xE),
+    ("Z", 0xA),
+    ("X", 0x0),
+    ("C", 0xB),
+    ("V", 0xF),
+];
+
+/// Host key for each purpose the ROM database gives keypad keys
+const ROM_KEYS: [(&str, &str); 12] = [
+    ("up", "Up"),
+    ("down", "Down"),
+    ("left", "Left"),
+    ("right", "Right"),
+    ("a", "Space"),
+    ("b", "Enter"),
+    ("player2Up", "I"),
+    ("player2Down", "K"),
+    ("player2Left", "J"),
+    ("player2Right", "L"),
+    ("player2A", "U"),
+    ("player2B", "O"),
+];
+
+/// Translates host key events into keypad presses
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    /// Keypad key for each host key, by lowercase name
+    keys: HashMap<String, u8>,
+    /// Host keys currently down, so repeated events and overlapping keys are handled
+    held: HashSet<String>,
+}
+
+impl Keymap {
+    /// Create a `Keymap` without any keys mapped
+    pub fn empty() -> Self {
+        Self {
+            keys: HashMap::new(),
+            held: HashSet::new(),
+        }
+    }
+
+    /// Map `host` to the keypad `key`, in addition to any existing host keys for `key`
+    pub fn bind(&mut self, host: &str, key: u8) {
+        self.keys.insert(host.to_lowercase(), key & 0xF);
+    }
+
+    /// Remove all host keys mapped to the keypad `key`
+    pub fn unbind(&mut self, key: u8) {
+        let key = key & 0xF;
+        self.keys.retain(|_, k| *k != key);
+    }
+
+    /// Get the keypad key `host` is mapped to
+    pub fn get(&self, host: &str) -> Option<u8> {
+        self.keys.get(&host.to_lowercase()).copied()
+    }
+
+    /// Get the host keys mapped to the keypad `key`, sorted by name
+    pub fn host_keys(&self, key: u8) -> Vec<&str> {
+        let mut hosts: Vec<_> = self
+            .keys
+            .iter()
+            .filter(|(_, k)| **k == key)
+            .map(|(host, _)| host.as_str())
+            .collect();
+        hosts.sort_unstable();
+        hosts
+    }
+
+    /// Replace the host keys of keypad keys, given as a hex digit (e.g. `"A"`) with the names of
+    /// their host keys, as in the [`config`] file
+    ///
+    /// [`config`]: ../config/index.html
+    pub fn with_overrides(mut self, keys: &BTreeMap<String, Vec<String>>) -> Result<Self, String> {
+        for (key, hosts) in keys {
+            let key = match u8::from_str_radix(key, 16) {
+                Ok(key) if (key as usize) < KEY_COUNT => key,
+                _ => return Err(format!("invalid keypad key `{}`, expected 0-F", key)),
+            };
+
+            self.unbind(key);
+            for host in hosts {
+                self.bind(host, key);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Also map host keys to the keypad keys the ROM database gives a purpose, e.g.
+    /// `"up" => 0x5` maps the up arrow to key 5
+    pub fn with_rom_keys(mut self, keys: &HashMap<String, u8>) -> Self {
+        for (purpose, key) in keys {
+            match ROM_KEYS.iter().find(|(p, _)| p == purpose) {
+                Some((_, host)) => self.bind(host, *key),
+                None => log::debug!("Ignoring ROM key `{}`", purpose),
+            }
+        }
+        self
+    }
+
+    /// Handle `host` being pressed
+    pub fn key_down(&mut self, host: &str, keypad: &mut Keypad) {
+        let host = host.to_lowercase();
+        if let Some(key) = self.keys.get(&host).copied() {
+            if self.held.insert(host) {
+                keypad.press(key);
+            }
+        }
+    }
+
+    /// Handle `host` being released
+    ///
+    /// The keypad key is only released once none of its host keys are held.
+    pub fn key_up(&mut self, host: &str, keypad: &mut Keypad) {
+        let host = host.to_lowercase();
+        if !self.held.remove(&host) {
+            return;
+        }
+        if let Some(key) = self.keys.get(&host).copied() {
+            if !self.held.iter().any(|h| self.keys.get(h) == Some(&key)) {
+                keypad.release(key);
+            }
+        }
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Self::empty();
+        for (host, key) in &QWERTY {
+            keymap.bind(host, *key);
+        }
+        keymap
+    }
+}
+
+impl fmt::Display for Keymap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys: Vec<_> = (0..KEY_COUNT as u8)
+            .map(|key| format!("{:X}={}", key, self.host_keys(key).join("/")))
+            .collect();
+        write!(f, "{}", keys.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_layout() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.get("q"), Some(0x4));
+        assert_eq!(keymap.get("V"), Some(0xF));
+        assert_eq!(keymap.get("Up"), None);
+    }
+
+    #[test]
+    fn several_host_keys() {
+        let mut overrides = BTreeMap::new();
+        overrides.insert("5".to_string(), vec!["W".to_string(), "Up".to_string()]);
+        let mut keymap = Keymap::default().with_overrides(&overrides).unwrap();
+        let mut keypad = Keypad::new();
+
+        keymap.key_down("W", &mut keypad);
+        keymap.key_down("UP", &mut keypad);
+        keymap.key_down("up", &mut keypad);
+        keymap.key_up("w", &mut keypad);
+        assert!(keypad.is_pressed(0x5));
+        keymap.key_up("Up", &mut keypad);
+        assert!(!keypad.is_pressed(0x5));
+
+        overrides.insert("G".to_string(), vec![]);
+        assert!(Keymap::default().with_overrides(&overrides).is_err());
+    }
+
+    #[test]
+    fn unbind() {
+        let mut keymap = Keymap::default();
+        keymap.bind("Up", 0x15);
+        assert_eq!(keymap.host_keys(0x5), vec!["up", "w"]);
+
+        keymap.unbind(0x15);
+        assert!(keymap.host_keys(0x5).is_empty());
+        assert_eq!(keymap.get("W"), None);
+        assert_eq!(keymap.get("Q"), Some(0x4));
+    }
+
+    #[test]
+    fn rom_keys() {
+        let mut keys = HashMap::new();
+        keys.insert("up".to_string(), 0x5);
+        keys.insert("player2A".to_string(), 0x1A);
+        keys.insert("jump".to_string(), 0x6);
+        let keymap = Keymap::default().with_rom_keys(&keys);
+
+        assert_eq!(keymap.get("Up"), Some(0x5));
+        assert_eq!(keymap.get("W"), Some(0x5));
+        assert_eq!(keymap.get("U"), Some(0xA));
+        assert_eq!(keymap.host_keys(0x6), vec!["e"]);
+    }
+}
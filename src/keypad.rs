@@ -0,0 +1,119 @@
+//! Chip-8 keypad.
+//!
+//! The computers which originally used the Chip-8 language had a 16-key hexadecimal keypad with
+//! the following layout:
+//!
+//! ```text
+//! +---+---+---+---+
+//! | 1 | 2 | 3 | C |
+//! +---+---+---+---+
+//! | 4 | 5 | 6 | D |
+//! +---+---+---+---+
+//! | 7 | 8 | 9 | E |
+//! +---+---+---+---+
+//! | A | 0 | B | F |
+//! +---+---+---+---+
+//! ```
+//!
+//! Host keys are translated to keypad keys by a [`Keymap`].
+//!
+//! [`Keymap`]: ../keymap/struct.Keymap.html
+
+/// Number of keys on the keypad
+pub const KEY_COUNT: usize = 16;
+
+/// State of the 16 keypad keys
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Keypad {
+    /// One bit per key, set while the key is down
+    pressed: u16,
+    /// Last key released while waiting for a key, see [`take_released`]
+    ///
+    /// [`take_released`]: #method.take_released
+    released: Option<u8>,
+    /// Whether `Fx0A` is waiting for a key
+    waiting: bool,
+}
+
+impl Keypad {
+    /// Create a new `Keypad` with no keys down
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Press `key`, ignoring anything outside of `0x0..=0xF`
+    pub fn press(&mut self, key: u8) {
+        if (key as usize) < KEY_COUNT {
+            self.pressed |= 1 << key;
+        }
+    }
+
+    /// Release `key`, ignoring anything outside of `0x0..=0xF`
+    pub fn release(&mut self, key: u8) {
+        if self.is_pressed(key) {
+            self.pressed &= !(1 << key);
+            if self.waiting {
+                self.released = Some(key);
+            }
+        }
+    }
+
+    /// Whether `key` is down, keys outside of `0x0..=0xF` never are
+    pub fn is_pressed(&self, key: u8) -> bool {
+        (key as usize) < KEY_COUNT && self.pressed & (1 << key) != 0
+    }
+
+    /// Release all keys
+    pub fn clear(&mut self) {
+        self.pressed = 0;
+    }
+
+    /// Wait for a key to be pressed and released, returning it once it has been
+    ///
+    /// Like the COSMAC VIP, a key only counts once it is released, so the key that ended the wait
+    /// isn't seen as still pressed by the instructions that follow.
+    pub fn take_released(&mut self) -> Option<u8> {
+        if !self.waiting {
+            self.waiting = true;
+            self.released = None;
+        }
+
+        let key = self.released.take();
+        if key.is_some() {
+            self.waiting = false;
+        }
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn press_and_release() {
+        let mut keypad = Keypad::new();
+        keypad.press(0xA);
+        keypad.press(0x10);
+        assert!(keypad.is_pressed(0xA));
+        assert!(!keypad.is_pressed(0xB));
+        assert!(!keypad.is_pressed(0x10));
+        keypad.release(0xA);
+        assert!(!keypad.is_pressed(0xA));
+    }
+
+    #[test]
+    fn wait_for_release() {
+        let mut keypad = Keypad::new();
+        // releases before the wait starts don't count
+        keypad.press(0x1);
+        keypad.release(0x1);
+        assert_eq!(keypad.take_released(), None);
+
+        keypad.press(0x5);
+        assert_eq!(keypad.take_released(), None);
+        keypad.release(0x5);
+        assert_eq!(keypad.take_released(), Some(0x5));
+        assert_eq!(keypad.take_released(), None);
+    }
+}
@@ -1,30 +1,93 @@
+pub mod audio;
+pub mod builder;
+pub mod cache;
+pub mod capture;
+pub mod config;
+pub mod coverage;
+pub mod dap;
+pub mod database;
+pub mod display;
 pub mod error;
+pub mod font;
+pub mod gdb;
+pub mod hexdump;
+pub mod history;
 pub mod instruction;
+pub mod keymap;
+pub mod keypad;
 pub mod memory;
 pub mod opcode;
+pub mod optable;
+pub mod pacer;
+pub mod profile;
+pub mod quirks;
 pub mod register;
+pub mod render;
+pub mod scan;
+pub mod script;
+pub mod sprites;
+pub mod state;
+pub mod symbols;
+pub mod timing;
 pub mod types;
 
 use std::io;
+use std::ops::Range;
 use std::path::Path;
+use std::sync::Arc;
 
 use error::{Chip8Error, Result};
 use opcode::OpCode;
 
 /// CPU clock speed.
 const CLOCK_HZ: f32 = 600.0;
+/// Rate the timers count down at, one tick per frame.
+const TIMER_HZ: f32 = 60.0;
 /// Size of the stack.
 const STACK_SIZE: usize = 16;
+/// Initial state of the random number generator, so runs are reproducible by default.
+const RNG_SEED: u32 = 0x2545_F491;
 
 pub trait Emulator: std::fmt::Debug {
+    /// Load a ROM from an in-memory buffer into memory of the emulator.
+    fn load_rom_bytes(&mut self, rom: &[u8]) -> Result<()>;
+
+    /// Set the symbol file used to name addresses when disassembling, tracing and profiling.
+    ///
+    /// Ignored by emulators which don't name addresses.
+    fn set_symbols(&mut self, _symbols: symbols::Symbols) {}
+
     /// Load a ROM into memory of the emulator.
-    fn load_rom(&mut self, reader: &dyn AsRef<Path>) -> Result<()>;
+    fn load_rom(&mut self, reader: &dyn AsRef<Path>) -> Result<()> {
+        let rom = std::fs::read(reader)?;
+        self.load_rom_bytes(&rom)
+    }
+
+    /// Load a ROM into memory of the emulator, along with the symbol file naming its addresses.
+    fn load_rom_with_symbols(
+        &mut self,
+        reader: &dyn AsRef<Path>,
+        symbols: &dyn AsRef<Path>,
+    ) -> Result<()> {
+        self.set_symbols(symbols::Symbols::open(symbols)?);
+        self.load_rom(reader)
+    }
+
+    /// Load a ROM from `reader` into memory of the emulator.
+    fn load_rom_reader<R: io::Read>(&mut self, mut reader: R) -> Result<()>
+    where
+        Self: Sized,
+    {
+        let mut rom = Vec::new();
+        reader.read_to_end(&mut rom)?;
+        self.load_rom_bytes(&rom)
+    }
 }
 
 #[derive(Debug)]
 pub struct Chip8 {
     /// System RAM
-    pub ram: memory::Ram, // TODO: remove `pub`
+    ram: memory::Ram,
 
     /// General purpose registers, `V0..VF`
     regs: register::Regs,
@@ -32,81 +95,1030 @@ pub struct Chip8 {
     i: u16,
     /// Program counter
     pc: u16,
+    /// Return addresses of the subroutines being executed
+    stack: [u16; STACK_SIZE],
+    /// Stack pointer, the number of addresses on `stack`
+    sp: u8,
     /// Delay timer
     dt: u8,
     /// Sound timer.
     st: u8,
+    /// Whether the sound timer was running when the timers last ticked
+    sounded: bool,
+    /// Display framebuffer
+    display: display::Display,
+    /// Hex keypad
+    keypad: keypad::Keypad,
+
+    /// Trap writes to reserved memory and execution outside of the ROM
+    protect: bool,
+    /// Addresses occupied by the loaded ROM
+    rom: Range<u16>,
+    /// Addresses of code loaded besides the ROM, which may also be executed
+    code: Vec<Range<u16>>,
+    /// Address the ROM is loaded at
+    load_addr: u16,
+    /// Address execution starts from, if different from `load_addr`
+    entry: Option<u16>,
+
+    /// Decoded instructions, if caching is enabled
+    cache: Option<cache::DecodeCache>,
+    /// State of the random number generator used by `RND`
+    rng: u32,
+    /// Execution counts, if profiling is enabled
+    profile: Option<profile::Profile>,
+    /// Executed addresses and skips, if coverage is enabled
+    coverage: Option<coverage::Coverage>,
+    /// Undo records of the latest instructions, if history is enabled
+    history: Option<history::History>,
+
+    /// Platform whose instructions are decoded
+    platform: quirks::Platform,
+    /// Interpreter quirks to emulate
+    quirks: quirks::Quirks,
+    /// CPU clock speed, in instructions per second
+    clock_hz: f32,
+    /// Fraction of an instruction left over from the last frame
+    cycle_carry: f32,
+    /// How long instructions take to execute
+    timing: timing::Timing,
+    /// Machine cycles the last frame overran by, with `Timing::Vip`
+    cycle_debt: u32,
+    /// Instructions or machine cycles left in a frame that was stopped early
+    frame_left: Option<u32>,
+
+    /// ROM metadata consulted when loading a ROM
+    database: Option<Arc<database::Database>>,
+    /// Metadata of the loaded ROM, if it was found in the database
+    rom_info: Option<database::RomInfo>,
+    /// Labels and source lines of the loaded ROM
+    symbols: Option<symbols::Symbols>,
 }
 
 impl Chip8 {
     /// Initialize `Chip8` to default state and load in system fonts.
     pub fn new() -> Self {
-        Self {
+        let mut chip8 = Self {
             ram: memory::Ram::default(),
 
             regs: register::Regs::default(),
             i: 0x000,
             pc: register::PROGRAM_START,
+            stack: [0; STACK_SIZE],
+            sp: 0,
             dt: 0x0,
             st: 0x0,
+            sounded: false,
+            display: display::Display::new(),
+            keypad: keypad::Keypad::new(),
+
+            protect: false,
+            rom: register::PROGRAM_START..register::PROGRAM_START,
+            code: Vec::new(),
+            load_addr: register::PROGRAM_START,
+            entry: None,
+
+            cache: Some(cache::DecodeCache::new()),
+            rng: RNG_SEED,
+            profile: None,
+            coverage: None,
+            history: None,
+
+            platform: quirks::Platform::Chip8,
+            quirks: quirks::Quirks::default(),
+            clock_hz: CLOCK_HZ,
+            cycle_carry: 0.0,
+            timing: timing::Timing::default(),
+            cycle_debt: 0,
+            frame_left: None,
+
+            database: None,
+            rom_info: None,
+            symbols: None,
+        };
+
+        chip8.ram[font::FONT_ADDR as usize..font::FONT_ADDR as usize + font::FONT.len()]
+            .copy_from_slice(&font::FONT);
+        chip8
+    }
+
+    /// Create a [`Chip8Builder`] to construct a fully configured `Chip8`
+    ///
+    /// [`Chip8Builder`]: builder/struct.Chip8Builder.html
+    pub fn builder() -> builder::Chip8Builder {
+        builder::Chip8Builder::new()
+    }
+
+    /// Get the platform whose instructions are decoded
+    pub fn platform(&self) -> quirks::Platform {
+        self.platform
+    }
+
+    /// Set the platform whose instructions are decoded, instructions of other platforms are
+    /// ignored
+    ///
+    /// The quirks are set separately, see [`set_quirks`].
+    ///
+    /// [`set_quirks`]: #method.set_quirks
+    pub fn set_platform(&mut self, platform: quirks::Platform) {
+        self.platform = platform;
+        if let Some(cache) = self.cache.as_mut() {
+            cache.clear();
         }
+    }
+
+    /// Get the interpreter quirks being emulated
+    pub fn quirks(&self) -> quirks::Quirks {
+        self.quirks
+    }
+
+    /// Set the interpreter quirks to emulate
+    pub fn set_quirks(&mut self, quirks: quirks::Quirks) {
+        self.quirks = quirks;
+    }
 
-        // TODO: load builtin font
+    /// Get the CPU clock speed, in instructions per second
+    pub fn clock_hz(&self) -> f32 {
+        self.clock_hz
+    }
+
+    /// Set the CPU clock speed, in instructions per second
+    pub fn set_clock_hz(&mut self, hz: f32) {
+        self.clock_hz = hz;
+    }
+
+    /// Get the timing model
+    pub fn timing(&self) -> timing::Timing {
+        self.timing
+    }
+
+    /// Set the timing model, see [`timing`]
+    ///
+    /// With [`Timing::Vip`] the clock speed is ignored.
+    ///
+    /// [`timing`]: timing/index.html
+    /// [`Timing::Vip`]: timing/enum.Timing.html#variant.Vip
+    pub fn set_timing(&mut self, timing: timing::Timing) {
+        self.timing = timing;
+        self.cycle_carry = 0.0;
+        self.cycle_debt = 0;
+        self.frame_left = None;
+    }
+
+    /// Set the ROM database consulted by `load_rom` to pick quirks and clock speed.
+    ///
+    /// Settings made after the ROM is loaded take precedence over the database.
+    pub fn set_database(&mut self, db: Arc<database::Database>) {
+        self.database = Some(db);
+    }
+
+    /// Get the metadata of the loaded ROM, if it was found in the database
+    pub fn rom_info(&self) -> Option<&database::RomInfo> {
+        self.rom_info.as_ref()
     }
 
     /// Get [`OpCode`] from `idx`
     ///
+    /// Fails if `idx` is out of range and RAM is in [`AccessMode::Strict`].
+    ///
     /// [`OpCode`]: opcode/struct.OpCode.html
-    pub fn get_opcode(&self, idx: u16) -> OpCode {
+    /// [`AccessMode::Strict`]: memory/enum.AccessMode.html#variant.Strict
+    pub fn get_opcode(&self, idx: u16) -> Result<OpCode> {
         let idx = idx as usize;
-        OpCode::from((self.ram[idx], self.ram[idx + 1]))
+        let bytes = self.ram.read_slice(idx, 2)?;
+        Ok(OpCode::from((bytes[0], bytes[1])))
     }
-}
 
-impl Emulator for Chip8 {
-    fn load_rom(&mut self, reader: &dyn AsRef<Path>) -> Result<()> {
-        use memory::Ram;
-        use std::fs;
-        use std::io::Write;
+    /// Get the symbols of the loaded ROM, if a symbol file was given
+    pub fn symbols(&self) -> Option<&symbols::Symbols> {
+        self.symbols.as_ref()
+    }
 
-        let rom = fs::read(reader)?;
-        let rom_len = rom.len();
+    /// Describe `addr` by the closest label if there are symbols, e.g. `draw_player+0x4`, or in
+    /// hex otherwise
+    pub fn describe_addr(&self, addr: u16) -> String {
+        match &self.symbols {
+            Some(symbols) => symbols.describe(addr),
+            None => format!("{:#05X}", addr),
+        }
+    }
+
+    /// Format memory as a [`Hexdump`], marking the instruction at `pc` and the byte at `I`
+    ///
+    /// [`Hexdump`]: hexdump/struct.Hexdump.html
+    pub fn hexdump(&self) -> hexdump::Hexdump<'_> {
+        self.ram.hexdump().pc(self.pc).i(self.i)
+    }
+
+    /// Show `count` sprites of `size` from `I`, the sprite data the next `DRW` is likely to draw
+    pub fn sprites(&self, size: sprites::SpriteSize, count: usize) -> sprites::SpriteSheet<'_> {
+        self.ram.sprites(self.i, size).count(count)
+    }
+
+    /// Set the address `load_rom` places the ROM at.
+    ///
+    /// Defaults to [`PROGRAM_START`], ETI 660 programs expect [`ETI_660_PROGRAM_START`].
+    ///
+    /// [`PROGRAM_START`]: register/constant.PROGRAM_START.html
+    /// [`ETI_660_PROGRAM_START`]: register/constant.ETI_660_PROGRAM_START.html
+    pub fn set_load_addr(&mut self, addr: u16) {
+        self.load_addr = addr;
+    }
 
-        // ensure the ROM is smaller than available RAM
-        if rom_len > Ram::RAM_SIZE {
+    /// Set the address execution starts from after `load_rom`.
+    ///
+    /// Defaults to the load address.
+    pub fn set_entry(&mut self, addr: u16) {
+        self.entry = Some(addr);
+    }
+
+    /// Copy `blob` into memory at `addr`, e.g. to preload data a ROM expects.
+    ///
+    /// Fails if `blob` doesn't fit between `addr` and the end of RAM.
+    pub fn load_blob(&mut self, addr: u16, blob: &[u8]) -> Result<()> {
+        use memory::Ram;
+
+        let available = Ram::RAM_SIZE.saturating_sub(addr as usize);
+        if blob.len() > available {
             log::error!(
-                "Rom size ({}) is greater than available RAM ({})",
-                rom_len,
-                Ram::RAM_SIZE
+                "Blob size ({}) is greater than available RAM ({}) at {:#05X}",
+                blob.len(),
+                available,
+                addr
             );
-            return Err(Chip8Error::Io(io::Error::new(
-                io::ErrorKind::WriteZero,
-                "ROM was larger than available RAM",
-            )));
+            return Err(Chip8Error::TooLarge {
+                size: blob.len(),
+                available,
+            });
+        }
+
+        self.ram.write_slice(addr as usize, blob)
+    }
+
+    /// Copy code into memory at `addr`, like [`load_blob`], and allow executing it while memory
+    /// protection is enabled.
+    ///
+    /// [`load_blob`]: #method.load_blob
+    pub fn load_code(&mut self, addr: u16, code: &[u8]) -> Result<()> {
+        self.load_blob(addr, code)?;
+        self.code.push(addr..addr + code.len() as u16);
+        Ok(())
+    }
+
+    /// Enable or disable memory protection.
+    ///
+    /// While enabled, writes into the reserved interpreter region fail with
+    /// [`Chip8Error::ProtectedWrite`] and fetching an instruction from outside of the loaded ROM
+    /// and the code loaded with [`load_code`] fails with [`Chip8Error::ExecOutsideRom`].
+    ///
+    /// [`load_code`]: #method.load_code
+    ///
+    /// [`Chip8Error::ProtectedWrite`]: error/enum.Chip8Error.html#variant.ProtectedWrite
+    /// [`Chip8Error::ExecOutsideRom`]: error/enum.Chip8Error.html#variant.ExecOutsideRom
+    pub fn set_protection(&mut self, enabled: bool) {
+        self.protect = enabled;
+    }
+
+    /// Get a read-only view of the registers, stack, timers and memory
+    pub fn state(&self) -> state::MachineState<'_> {
+        state::MachineState::new(self)
+    }
+
+    /// Set register `Vx`
+    ///
+    /// # Panics
+    ///
+    /// If `x` is above `0xF`.
+    pub fn set_reg(&mut self, x: u8, value: u8) {
+        self.regs[x] = value;
+    }
+
+    /// Set the address register `I`
+    pub fn set_i(&mut self, value: u16) {
+        self.i = value;
+    }
+
+    /// Set the program counter, to continue execution from `addr`
+    pub fn set_pc(&mut self, addr: u16) {
+        self.pc = addr;
+    }
+
+    /// Set the delay timer
+    pub fn set_dt(&mut self, value: u8) {
+        self.dt = value;
+    }
+
+    /// Set the sound timer
+    pub fn set_st(&mut self, value: u8) {
+        self.st = value;
+    }
+
+    /// Write `data` to memory from `addr`, following the [`AccessMode`] of RAM
+    ///
+    /// Cached instructions decoded from the bytes are invalidated, and the write isn't undone by
+    /// stepping back.
+    ///
+    /// [`AccessMode`]: memory/enum.AccessMode.html
+    pub fn write_mem(&mut self, addr: u16, data: &[u8]) -> Result<()> {
+        self.ram.write_slice(addr as usize, data)?;
+        // keep it out of the undo record of the next instruction
+        self.ram.take_journal();
+        Ok(())
+    }
+
+    /// Get the display framebuffer
+    pub fn display(&self) -> &display::Display {
+        &self.display
+    }
+
+    /// Get the keypad
+    pub fn keypad(&self) -> &keypad::Keypad {
+        &self.keypad
+    }
+
+    /// Get the keypad, to press and release keys
+    pub fn keypad_mut(&mut self) -> &mut keypad::Keypad {
+        &mut self.keypad
+    }
+
+    /// Decrement the delay and sound timers, called at 60Hz
+    pub fn tick_timers(&mut self) {
+        self.sounded = self.st > 0;
+        self.dt = self.dt.saturating_sub(1);
+        self.st = self.st.saturating_sub(1);
+    }
+
+    /// Run one frame, 1/60th of a second: as many instructions as fit in a frame, then a timer
+    /// tick
+    ///
+    /// With [`Timing::Fixed`], that is the clock speed divided by 60. Clock speeds that aren't a
+    /// multiple of 60Hz carry the leftover fraction of an instruction over to the next frame.
+    /// `DRW` ends the frame early with the `display_wait` quirk, and always with
+    /// [`Timing::Vip`].
+    ///
+    /// [`Timing::Fixed`]: timing/enum.Timing.html#variant.Fixed
+    /// [`Timing::Vip`]: timing/enum.Timing.html#variant.Vip
+    pub fn run_frame(&mut self) -> Result<()> {
+        self.run_frame_until(|_| false).map(|_| ())
+    }
+
+    /// Run the rest of the current frame like [`run_frame`], but stop before executing an
+    /// instruction at an address `stop` returns `true` for, e.g. a breakpoint
+    ///
+    /// Returns whether it stopped early, in which case the next call carries on with the same
+    /// frame.
+    ///
+    /// [`run_frame`]: #method.run_frame
+    pub fn run_frame_until(&mut self, mut stop: impl FnMut(u16) -> bool) -> Result<bool> {
+        let mut left = match self.frame_left {
+            Some(left) => left,
+            None => self.frame_budget(),
+        };
+
+        while left > 0 {
+            if stop(self.pc) {
+                self.frame_left = Some(left);
+                return Ok(true);
+            }
+            // leave the frame as it was if the instruction fails
+            self.frame_left = Some(left);
+            let inst = self.execute()?;
+            let drew = inst.opcode().to_match_tuple().0 == 0xD;
+            left = match self.timing {
+                // `DRW` waits for the display interrupt, which starts the next frame
+                timing::Timing::Vip if drew => 0,
+                timing::Timing::Fixed if drew && self.quirks.display_wait => 0,
+                timing::Timing::Fixed => left - 1,
+                timing::Timing::Vip => {
+                    let cycles = timing::vip_cycles(&inst);
+                    // an instruction running past the end of the frame delays the next one
+                    self.cycle_debt = cycles.saturating_sub(left);
+                    left.saturating_sub(cycles)
+                }
+            };
+        }
+
+        self.frame_left = None;
+        self.tick_timers();
+        Ok(false)
+    }
+
+    /// Instructions (`Timing::Fixed`) or machine cycles (`Timing::Vip`) available in a new frame
+    fn frame_budget(&mut self) -> u32 {
+        match self.timing {
+            timing::Timing::Fixed => {
+                let cycles = self.clock_hz / TIMER_HZ + self.cycle_carry;
+                let whole = cycles.floor();
+                self.cycle_carry = cycles - whole;
+                whole as u32
+            }
+            timing::Timing::Vip => {
+                let budget = timing::VIP_FRAME_CYCLES - timing::VIP_INTERRUPT_CYCLES;
+                let debt = std::mem::take(&mut self.cycle_debt);
+                budget.saturating_sub(debt)
+            }
+        }
+    }
+
+    /// Whether the sound timer is running, i.e. a tone should be playing
+    pub fn sound_active(&self) -> bool {
+        self.st > 0
+    }
+
+    /// Whether the tone played during the last frame, i.e. the sound timer was running before
+    /// the timers ticked at the end of it
+    pub fn sounded(&self) -> bool {
+        self.sounded
+    }
+
+    /// Enable or disable caching of decoded instructions, enabled by default.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.cache = if enabled {
+            Some(cache::DecodeCache::new())
+        } else {
+            None
+        };
+    }
+
+    /// Enable or disable profiling, see [`profile`]
+    ///
+    /// Enabling profiling starts a new, empty [`Profile`].
+    ///
+    /// [`profile`]: profile/index.html
+    /// [`Profile`]: profile/struct.Profile.html
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profile = if enabled {
+            Some(profile::Profile::new())
+        } else {
+            None
+        };
+    }
+
+    /// Get the execution counts gathered, if profiling is enabled
+    pub fn profile(&self) -> Option<&profile::Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Enable or disable recording of code coverage, see [`coverage`]
+    ///
+    /// Enabling coverage starts a new, empty [`Coverage`].
+    ///
+    /// [`coverage`]: coverage/index.html
+    /// [`Coverage`]: coverage/struct.Coverage.html
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.coverage = if enabled {
+            Some(coverage::Coverage::new())
+        } else {
+            None
+        };
+    }
+
+    /// Get the code coverage recorded, if coverage is enabled
+    pub fn coverage(&self) -> Option<&coverage::Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Keep undo records of the last `capacity` instructions, see [`history`]
+    ///
+    /// A `capacity` of 0 disables history. Changing the capacity starts a new, empty
+    /// [`History`].
+    ///
+    /// [`history`]: history/index.html
+    /// [`History`]: history/struct.History.html
+    pub fn set_history(&mut self, capacity: usize) {
+        self.history = if capacity > 0 {
+            Some(history::History::new(capacity))
+        } else {
+            None
+        };
+        self.ram.set_journaling(capacity > 0);
+    }
+
+    /// Get the undo records kept, if history is enabled
+    pub fn history(&self) -> Option<&history::History> {
+        self.history.as_ref()
+    }
+
+    /// Undo the last instruction executed, returning `false` if there is no history of it
+    pub fn step_back(&mut self) -> bool {
+        let undo = match self.history.as_mut().and_then(history::History::pop) {
+            Some(undo) => undo,
+            None => return false,
+        };
+        for (addr, val) in undo.memory.into_iter().rev() {
+            self.ram[addr as usize] = val;
+        }
+        // restoring memory isn't a write to undo later
+        self.ram.take_journal();
+        if let Some(display) = undo.display {
+            self.display = display;
+        }
+        self.regs = undo.regs;
+        self.i = undo.i;
+        self.pc = undo.pc;
+        self.sp = undo.sp;
+        self.stack = undo.stack;
+        self.dt = undo.dt;
+        self.st = undo.st;
+        self.rng = undo.rng;
+        true
+    }
+
+    /// Undo instructions until `stop` returns `true` for the address of one, returning `false`
+    /// if the history ran out first
+    ///
+    /// At least one instruction is undone, so running back to the previous breakpoint works
+    /// when stopped at one.
+    pub fn run_back_until(&mut self, mut stop: impl FnMut(u16) -> bool) -> bool {
+        while self.step_back() {
+            if stop(self.pc) {
+                return true;
+            }
         }
+        false
+    }
+
+    /// Seed the random number generator used by `RND`
+    pub fn set_seed(&mut self, seed: u32) {
+        // xorshift never leaves 0, so don't let it start there
+        self.rng = if seed == 0 { RNG_SEED } else { seed };
+    }
+
+    /// Fetch, decode and execute a single instruction
+    pub fn step(&mut self) -> Result<()> {
+        self.execute().map(|_| ())
+    }
+
+    /// Whether `addr` is in the ROM or in other loaded code
+    fn executable(&self, addr: u16) -> bool {
+        self.rom.contains(&addr) || self.code.iter().any(|code| code.contains(&addr))
+    }
+
+    /// Fetch, decode and execute a single instruction, returning it
+    fn execute(&mut self) -> Result<instruction::Instruction> {
+        let pc = self.pc;
+        if self.protect && !self.executable(pc) {
+            log::error!("Execution left the loaded ROM at {:#06X}", pc);
+            return Err(Chip8Error::ExecOutsideRom { pc });
+        }
+
+        let inst = self.fetch(pc)?;
+        log::trace!("Execute {} `{}`", self.describe_addr(pc), inst);
+        let undo = self.history.as_ref().map(|_| self.undo_record(inst));
+        self.pc = pc.wrapping_add(2);
+        let res = inst.exec(self);
+        if let Some(mut undo) = undo {
+            undo.memory = self.ram.take_journal();
+            if let Some(history) = self.history.as_mut() {
+                history.push(undo);
+            }
+        }
+        res?;
+
+        if let Some(profile) = self.profile.as_mut() {
+            profile.record(pc, inst);
+        }
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record(pc);
+            if coverage::is_skip(inst.opcode()) {
+                coverage.record_branch(pc, self.pc != pc.wrapping_add(inst.def().len));
+            }
+        }
+        Ok(inst)
+    }
+
+    /// Record the state `inst` may change, before it executes
+    fn undo_record(&self, inst: instruction::Instruction) -> history::Undo {
+        // only `CLS` and `DRW` change the display
+        let display = match inst.opcode().to_match_tuple() {
+            (0x0, 0x0, 0xE, 0x0) | (0xD, ..) => Some(self.display.clone()),
+            _ => None,
+        };
+        history::Undo {
+            regs: self.regs.clone(),
+            i: self.i,
+            pc: self.pc,
+            sp: self.sp,
+            stack: self.stack,
+            dt: self.dt,
+            st: self.st,
+            rng: self.rng,
+            memory: Vec::new(),
+            display,
+        }
+    }
+
+    /// Get the decoded instruction at `pc`, from the cache if possible
+    fn fetch(&mut self, pc: u16) -> Result<instruction::Instruction> {
+        let cache = match self.cache.as_mut() {
+            Some(cache) => cache,
+            None => return Ok(self.get_opcode(pc)?.decode_for(self.platform)),
+        };
+
+        self.ram.drain_writes(|addr| cache.invalidate(addr));
+        if let Some(inst) = cache.get(pc) {
+            return Ok(inst);
+        }
+
+        let bytes = self.ram.read_slice(pc as usize, 2)?;
+        let inst = OpCode::from((bytes[0], bytes[1])).decode_for(self.platform);
+        cache.insert(pc, inst);
+        Ok(inst)
+    }
+
+    /// Get the next byte from the random number generator
+    pub(crate) fn random_byte(&mut self) -> u8 {
+        // xorshift32
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng >> 24) as u8
+    }
+
+    /// Write `data` to memory on behalf of the executing instruction
+    pub(crate) fn store_mem_slice(&mut self, addr: usize, data: &[u8]) -> Result<()> {
+        self.check_write(addr, data.len())?;
+        self.ram.write_slice(addr, data)
+    }
+
+    /// Ensure a write of `len` bytes at `addr` doesn't touch the reserved region
+    fn check_write(&self, addr: usize, len: usize) -> Result<()> {
+        use memory::Ram;
+
+        if !self.protect || len == 0 {
+            return Ok(());
+        }
+
+        // check each byte so writes wrapping past the end of RAM are caught too
+        let reserved = (0..len)
+            .map(|off| addr.wrapping_add(off) % Ram::RAM_SIZE)
+            .find(|a| *a < Ram::RESERVED_SIZE);
+        match reserved {
+            Some(addr) => {
+                // `pc` has already been advanced past the executing instruction
+                let pc = self.pc.wrapping_sub(2);
+                log::error!("Write to reserved memory {:#06X} at {:#06X}", addr, pc);
+                Err(Chip8Error::ProtectedWrite { pc, addr })
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Emulator for Chip8 {
+    fn set_symbols(&mut self, symbols: symbols::Symbols) {
+        self.symbols = Some(symbols);
+    }
+
+    fn load_rom_bytes(&mut self, rom: &[u8]) -> Result<()> {
+        let rom_len = rom.len();
 
         // TODO: check if ROM is valid before loading it into memory
         //       (needs to contain at least 1 instruction)
-        // TODO: Get range indexing to work without interacting with the underlying field
-        let mut ram =
-            io::BufWriter::new(&mut self.ram.0[register::PROGRAM_START as usize..Ram::RAM_SIZE]);
-        ram.write_all(rom.as_ref())?;
+        self.load_blob(self.load_addr, rom)?;
+        self.rom = self.load_addr..self.load_addr + rom_len as u16;
+        self.pc = self.entry.unwrap_or(self.load_addr);
 
-        log::debug!("Loaded ROM of size {}", rom_len);
+        // only a ROM that loaded changes the settings
+        self.rom_info = self
+            .database
+            .as_ref()
+            .and_then(|db| db.lookup(rom))
+            .cloned();
+        if let Some(info) = &self.rom_info {
+            log::info!("Found `{}` ({}) in ROM database", info.title, info.platform);
+            self.quirks = info.quirks;
+            if let Some(hz) = info.clock_hz {
+                self.clock_hz = hz;
+            }
+            self.set_platform(info.platform);
+        }
+
+        log::debug!(
+            "Loaded ROM of size {} at {:#05X}, entry {:#05X}",
+            rom_len,
+            self.load_addr,
+            self.pc
+        );
         Ok(())
     }
 }
 
 impl Default for Chip8 {
     fn default() -> Self {
-        Self {
-            ram: memory::Ram::default(),
+        Self::new()
+    }
+}
 
-            regs: register::Regs::default(),
-            i: 0x000,
-            pc: register::PROGRAM_START,
-            dt: 0x0,
-            st: 0x0,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exec_outside_rom() {
+        let mut chip8 = Chip8::new();
+        chip8.set_protection(true);
+        assert!(matches!(
+            chip8.step(),
+            Err(Chip8Error::ExecOutsideRom { pc: 0x200 })
+        ));
+    }
+
+    #[test]
+    fn exec_loaded_code() {
+        let mut chip8 = Chip8::new();
+        chip8.set_protection(true);
+        // 6005: LD V0, 0x05, 1200: JP 0x200
+        chip8.load_code(0x300, &[0x60, 0x05, 0x12, 0x00]).unwrap();
+        chip8.pc = 0x300;
+        chip8.step().unwrap();
+        chip8.step().unwrap();
+        assert_eq!(chip8.pc, 0x200);
+        assert!(matches!(
+            chip8.step(),
+            Err(Chip8Error::ExecOutsideRom { pc: 0x200 })
+        ));
+    }
+
+    #[test]
+    fn protected_write() {
+        let mut chip8 = Chip8::new();
+        chip8.pc = 0x202;
+        assert!(chip8.write_mem(0x050, &[0xFF]).is_ok());
+
+        chip8.set_protection(true);
+        assert!(matches!(
+            chip8.store_mem_slice(0xFFF, &[0x01, 0x02]),
+            Err(Chip8Error::ProtectedWrite {
+                pc: 0x200,
+                addr: 0x000
+            })
+        ));
+        assert!(chip8.store_mem_slice(0x200, &[0xFF]).is_ok());
+    }
+
+    #[test]
+    fn load_blob_fits() {
+        let mut chip8 = Chip8::new();
+        assert!(chip8.load_blob(0xE00, &[0xAA; 0x200]).is_ok());
+        assert!(matches!(
+            chip8.load_blob(0xE01, &[0xAA; 0x200]),
+            Err(Chip8Error::TooLarge {
+                size: 0x200,
+                available: 0x1FF
+            })
+        ));
+    }
+
+    #[test]
+    fn load_rom_reader() {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_rom_reader(io::Cursor::new([0x12, 0x00]))
+            .unwrap();
+        assert_eq!(&chip8.ram[0x200..0x202], &[0x12, 0x00]);
+    }
+
+    #[test]
+    fn keypad_instructions() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0xF3, 0x0A, 0xE3, 0x9E]).unwrap();
+
+        chip8.step().unwrap();
+        chip8.keypad_mut().press(0x7);
+        chip8.step().unwrap();
+        assert_eq!(chip8.pc, 0x200);
+        chip8.keypad_mut().release(0x7);
+        chip8.step().unwrap();
+        assert_eq!((chip8.regs[0x3], chip8.pc), (0x7, 0x202));
+
+        chip8.keypad_mut().press(0x7);
+        chip8.step().unwrap();
+        assert_eq!(chip8.pc, 0x206);
+    }
+
+    #[test]
+    fn self_modifying_code() {
+        let rom = [
+            0xA2, 0x0A, // 200: LD I, 20A
+            0x60, 0x73, // 202: LD V0, 73
+            0x61, 0x05, // 204: LD V1, 05
+            0x22, 0x0A, // 206: CALL 20A
+            0x12, 0x10, // 208: JP 210
+            0x73, 0x01, // 20A: ADD V3, 01
+            0x00, 0xEE, // 20C: RET
+            0x00, 0x00, // 20E
+            0xF1, 0x55, // 210: LD [I], V1
+            0x22, 0x0A, // 212: CALL 20A
+        ];
+        for cached in [true, false] {
+            let mut chip8 = Chip8::new();
+            chip8.set_decode_cache(cached);
+            chip8.load_rom_bytes(&rom).unwrap();
+            for _ in 0..11 {
+                chip8.step().unwrap();
+            }
+            assert_eq!(chip8.regs[0x3], 6);
+            assert_eq!(chip8.pc, 0x214);
+        }
+    }
+
+    #[test]
+    fn arithmetic_flags() {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_rom_bytes(&[
+                0x60, 0xFF, // LD V0, FF
+                0x61, 0x02, // LD V1, 02
+                0x80, 0x14, // ADD V0, V1
+                0x8F, 0x15, // SUB VF, V1
+                0x82, 0x16, // SHR V2, V1
+            ])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.step().unwrap();
+        }
+        assert_eq!((chip8.regs[0x0], chip8.regs[0xF]), (0x01, 1));
+        // the flag is written last, so wins over the result
+        chip8.step().unwrap();
+        assert_eq!(chip8.regs[0xF], 0);
+        chip8.step().unwrap();
+        assert_eq!((chip8.regs[0x2], chip8.regs[0xF]), (0x01, 0));
+    }
+
+    #[test]
+    fn stack_limits() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x22, 0x00]).unwrap();
+        for _ in 0..STACK_SIZE {
+            chip8.step().unwrap();
+        }
+        assert!(matches!(
+            chip8.step(),
+            Err(Chip8Error::StackOverflow { pc: 0x200 })
+        ));
+
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x00, 0xEE]).unwrap();
+        assert!(matches!(
+            chip8.step(),
+            Err(Chip8Error::StackUnderflow { pc: 0x200 })
+        ));
+    }
+
+    #[test]
+    fn vip_timing() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.regs[0x0], 5);
+
+        chip8.set_timing(timing::Timing::Vip);
+        chip8.regs[0x0] = 0;
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.regs[0x0], 26);
+
+        // `DRW` ends the frame, whatever the quirk says
+        let mut chip8 = Chip8::new();
+        chip8.set_timing(timing::Timing::Vip);
+        chip8.set_quirks(quirks::Quirks {
+            display_wait: false,
+            ..quirks::Quirks::chip8()
+        });
+        chip8
+            .load_rom_bytes(&[0xD0, 0x15, 0x70, 0x01, 0x12, 0x00])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.run_frame().unwrap();
+        }
+        assert_eq!(chip8.regs[0x0], 2);
+    }
+
+    #[test]
+    fn display_wait() {
+        let rom = [
+            0xD0, 0x11, // 200: DRW V0, V1, 1
+            0x70, 0x01, // 202: ADD V0, 01
+            0x12, 0x00, // 204: JP 200
+        ];
+        for (wait, expected) in [(true, 1), (false, 7)] {
+            let mut chip8 = Chip8::new();
+            chip8.set_quirks(quirks::Quirks {
+                display_wait: wait,
+                ..quirks::Quirks::chip8()
+            });
+            chip8.load_rom_bytes(&rom).unwrap();
+            chip8.run_frame().unwrap();
+            chip8.run_frame().unwrap();
+            // 10 instructions a frame, unless each `DRW` ends one
+            assert_eq!(chip8.regs[0x0], expected);
         }
     }
+
+    #[test]
+    fn database_settings() {
+        let json = format!(
+            r#"[{{ "title": "Big", "roms": {{ "{}": {{ "platforms": ["superchip"], "tickrate": 30 }} }} }}]"#,
+            database::sha1_hex(&[0; 0xE01])
+        );
+        let mut chip8 = Chip8::new();
+        chip8.set_database(Arc::new(database::Database::from_json(&json).unwrap()));
+
+        // too large to load, so the database entry doesn't apply
+        assert!(chip8.load_rom_bytes(&[0; 0xE01]).is_err());
+        assert_eq!(chip8.quirks(), quirks::Quirks::chip8());
+        assert_eq!(chip8.clock_hz(), CLOCK_HZ);
+        assert!(chip8.rom_info().is_none());
+
+        // not in the database, so the defaults stay
+        chip8.load_rom_bytes(&[0x12, 0x00]).unwrap();
+        assert!(chip8.rom_info().is_none());
+        assert_eq!(chip8.quirks(), quirks::Quirks::chip8());
+    }
+
+    #[test]
+    fn platform_instructions() {
+        let rom = [
+            0x30, 0x00, // 200: SE V0, 00
+            0xF0, 0x00, // 202: LD I, LONG on XO-CHIP
+            0x61, 0x05, // 204: LD V1, 05
+            0x12, 0x06, // 206: JP 206
+        ];
+        // only XO-CHIP skips `F000 nnnn` as a whole
+        for (platform, expected) in [(quirks::Platform::XoChip, 0), (quirks::Platform::Chip8, 5)] {
+            let mut chip8 = Chip8::new();
+            chip8.set_platform(platform);
+            chip8.load_rom_bytes(&rom).unwrap();
+            chip8.run_frame().unwrap();
+            assert_eq!(chip8.regs[0x1], expected);
+        }
+    }
+
+    #[test]
+    fn step_back() {
+        let mut chip8 = Chip8::new();
+        chip8.set_history(3);
+        chip8
+            .load_rom_bytes(&[
+                0x60, 0xFF, // 200: LD V0, FF
+                0xA3, 0x00, // 202: LD I, 300
+                0xF0, 0x33, // 204: LD B, V0
+                0xD0, 0x05, // 206: DRW V0, V0, 5
+                0x22, 0x00, // 208: CALL 200
+            ])
+            .unwrap();
+        for _ in 0..5 {
+            chip8.step().unwrap();
+        }
+        assert_eq!(&chip8.ram[0x300..0x303], &[2, 5, 5]);
+        assert_eq!(chip8.history().unwrap().len(), 3);
+
+        // only back to before `LD B, V0`, the oldest instruction kept
+        assert!(!chip8.run_back_until(|pc| pc == 0x200));
+        assert_eq!(chip8.pc, 0x204);
+        assert_eq!(chip8.sp, 0);
+        assert_eq!(chip8.i, 0x300);
+        assert_eq!(&chip8.ram[0x300..0x303], &[0, 0, 0]);
+        assert!(chip8.display.pixels().iter().all(|p| *p == 0));
+        assert!(!chip8.step_back());
+
+        // and forwards again
+        chip8.step().unwrap();
+        assert_eq!(&chip8.ram[0x300..0x303], &[2, 5, 5]);
+    }
+
+    #[test]
+    fn machine_state() {
+        let mut chip8 = Chip8::new();
+        chip8.set_history(10);
+        chip8
+            .load_rom_bytes(&[
+                0x60, 0x2A, // 200: LD V0, 2A
+                0x22, 0x06, // 202: CALL 206
+                0x00, 0x00, // 204
+                0xF0, 0x55, // 206: LD [I], V0
+            ])
+            .unwrap();
+        chip8.set_i(0x300);
+        chip8.step().unwrap();
+        chip8.step().unwrap();
+
+        let state = chip8.state();
+        assert_eq!(state.reg(0x0), 0x2A);
+        assert_eq!(state.regs()[0x0], 0x2A);
+        assert_eq!((state.i(), state.pc()), (0x300, 0x206));
+        assert_eq!(state.stack(), &[0x204]);
+        assert_eq!(&state.memory()[0x206..0x208], &[0xF0, 0x55]);
+
+        chip8.set_reg(0x0, 0x11);
+        chip8.write_mem(0x301, &[0xAB]).unwrap();
+        chip8.step().unwrap();
+        assert_eq!(&chip8.state().memory()[0x300..0x302], &[0x11, 0xAB]);
+        // undoing `LD [I], V0` leaves the write from outside alone
+        assert!(chip8.step_back());
+        assert_eq!(&chip8.state().memory()[0x300..0x302], &[0x00, 0xAB]);
+
+        chip8.set_pc(0x200);
+        assert_eq!(chip8.state().pc(), 0x200);
+    }
 }
@@ -1,45 +1,620 @@
-use std::io;
-use std::path::PathBuf;
+use std::fs;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use clap::{AppSettings, Clap};
+use clap::{AppSettings, Clap, IntoApp};
 
-use chip8::Emulator;
+use chip8::{
+    audio::{Audio, WavWriter},
+    capture::{self, GifRecorder},
+    config::{Config, Settings},
+    dap::DapServer,
+    database::{self, Database},
+    error::{Chip8Error, Result},
+    gdb::GdbStub,
+    keymap::Keymap,
+    opcode::OpCode,
+    optable,
+    pacer::{Pacer, Speed},
+    quirks::Platform,
+    register,
+    render::{self, Palette},
+    scan,
+    script::Script,
+    sprites::{SpriteSheet, SpriteSize},
+    symbols::Symbols,
+    timing::Timing,
+    Chip8, Emulator,
+};
 
 #[derive(Clap)]
 #[clap(setting = AppSettings::ColoredHelp)]
 pub struct Args {
-    /// Start with debugging info
+    /// Log debugging info to stderr, `RUST_LOG` overrides the level
     #[clap(short = "D", long)]
     debug: bool,
+    /// Address to load the ROM at, e.g. `0x600` for ETI 660 programs
+    #[clap(long, default_value = "0x200", parse(try_from_str = parse_addr))]
+    load_addr: u16,
+    /// Address to start execution from [default: the load address]
+    #[clap(long, parse(try_from_str = parse_addr))]
+    entry: Option<u16>,
+    /// Preload a binary blob into memory before the ROM, given as `ADDR:FILE`. Blobs may be
+    /// executed like the ROM under `--protect`
+    #[clap(long, number_of_values = 1, parse(try_from_str = parse_preload))]
+    preload: Vec<(u16, PathBuf)>,
+    /// Trap writes to the reserved interpreter memory and execution outside of the ROM and
+    /// preloaded blobs
+    #[clap(long)]
+    protect: bool,
+    /// Configuration file [default: `chip8/config.toml` in the user config directory]
+    #[clap(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+    /// ROM database (`programs.json`) to pick the platform, quirks and speed from [default:
+    /// `chip8/programs.json` in the user data directory]
+    #[clap(long, parse(from_os_str))]
+    db: Option<PathBuf>,
+    /// Platform to emulate, overriding the ROM database [possible values: chip8, schip, xochip]
+    #[clap(long)]
+    platform: Option<Platform>,
+    /// CPU clock speed in instructions per second, overriding the ROM database
+    #[clap(long)]
+    clock: Option<f32>,
+    /// Instruction timing, `fixed` (set by the clock speed) or `vip` (COSMAC VIP cycle costs)
+    #[clap(long)]
+    timing: Option<Timing>,
+    /// Display colours, either a theme (classic, inverted, amber, phosphor, lcd, octo,
+    /// high-contrast) or 2 or 4 comma separated `#RRGGBB` colours
+    #[clap(long)]
+    palette: Option<Palette>,
+    /// Screen pixels per Chip-8 pixel [default: 10]
+    #[clap(long, parse(try_from_str = parse_scale))]
+    scale: Option<usize>,
+    /// Emulation speed, a multiplier of real time (e.g. `2x`) or `uncapped`
+    #[clap(long, default_value = "1x")]
+    speed: Speed,
+    /// Run the ROM without a display for this many frames (1/60th of a second each), then
+    /// report the frame rate. Host keys are pressed and released by typing `down KEY` and
+    /// `up KEY` lines on stdin, `turbo` toggles uncapped speed, `speed 2x` changes the speed,
+    /// `fps` shows the frame rate,
+    /// `shot PATH` saves a screenshot and `record start PATH` and `record stop` record a GIF
+    #[clap(long)]
+    frames: Option<u64>,
+    /// Profile the run given by `--frames`, printing a report of the hot spots and writing the
+    /// call stacks to this file in folded format, for flamegraph tools
+    #[clap(long, parse(from_os_str))]
+    profile: Option<PathBuf>,
+    /// Record code coverage of the run given by `--frames`, writing an lcov tracefile to this
+    /// file and the annotated disassembly it refers to next to it, with the extension `.asm`
+    #[clap(long, parse(from_os_str))]
+    coverage: Option<PathBuf>,
+    /// Show the sprites at `I` of this size (`8xN` or `16x16`) in the terminal during the run
+    /// given by `--frames`, redrawn whenever they change
+    #[clap(long)]
+    sprites: Option<SpriteSize>,
+    /// Record the sound of the run given by `--frames` or `--script` to this WAV file
+    #[clap(long, parse(from_os_str))]
+    wav: Option<PathBuf>,
+    /// Save a screenshot of the display at the end of the run given by `--frames` or
+    /// `--script` to this file, PNG or PBM by its extension
+    #[clap(long, parse(from_os_str))]
+    screenshot: Option<PathBuf>,
+    /// Record the display during the run given by `--frames` or `--script` to this GIF
+    #[clap(long, parse(from_os_str))]
+    record: Option<PathBuf>,
+    /// Symbol file naming the addresses of the ROM, for tracing and profiling
+    #[clap(long, parse(from_os_str))]
+    symbols: Option<PathBuf>,
+    /// Run the ROM without a display under the control of this Rhai script, as fast as
+    /// possible, until the script calls `stop()` or the frames given by `--frames` have run
+    #[clap(long, parse(from_os_str))]
+    script: Option<PathBuf>,
+    /// Wait for GDB to attach on this address, e.g. `127.0.0.1:2159`, instead of running
+    #[clap(long)]
+    gdb: Option<String>,
     /// The rom to use
     #[clap(parse(from_os_str))]
+    rom: Option<PathBuf>,
+    #[clap(subcommand)]
+    cmd: Option<Command>,
+}
+
+#[derive(Clap)]
+pub enum Command {
+    /// Scan a ROM and recommend a platform and quirks to run it with
+    Info(InfoArgs),
+    /// Disassemble the reachable instructions of a ROM
+    Disasm(DisasmArgs),
+    /// Draw memory of a ROM as sprites, in the terminal or as a PNG
+    Sprites(SpritesArgs),
+    /// Print a reference of every supported opcode, as Markdown
+    Opcodes,
+    /// Serve the Debug Adapter Protocol on stdin and stdout, for debugging from an editor
+    Dap,
+}
+
+#[derive(Clap)]
+pub struct InfoArgs {
+    /// Address the ROM is loaded at
+    #[clap(long, default_value = "0x200", parse(try_from_str = parse_addr))]
+    load_addr: u16,
+    /// The rom to scan
+    #[clap(parse(from_os_str))]
+    rom: PathBuf,
+}
+
+#[derive(Clap)]
+pub struct DisasmArgs {
+    /// Address the ROM is loaded at
+    #[clap(long, default_value = "0x200", parse(try_from_str = parse_addr))]
+    load_addr: u16,
+    /// Symbol file naming the addresses of the ROM
+    #[clap(long, parse(from_os_str))]
+    symbols: Option<PathBuf>,
+    /// Platform whose instructions to decode [default: the platform the ROM was most likely
+    /// written for] [possible values: chip8, schip, xochip]
+    #[clap(long)]
+    platform: Option<Platform>,
+    /// The rom to disassemble
+    #[clap(parse(from_os_str))]
+    rom: PathBuf,
+}
+
+#[derive(Clap)]
+pub struct SpritesArgs {
+    /// Address the ROM is loaded at
+    #[clap(long, default_value = "0x200", parse(try_from_str = parse_addr))]
+    load_addr: u16,
+    /// Address of the first sprite [default: the load address]
+    #[clap(long, parse(try_from_str = parse_addr))]
+    addr: Option<u16>,
+    /// Sprite size, `8xN` for N from 1 to 15 or `16x16`
+    #[clap(long, default_value = "8x8")]
+    size: SpriteSize,
+    /// Number of sprites to draw
+    #[clap(long, default_value = "16")]
+    count: usize,
+    /// Sprites a row
+    #[clap(long, default_value = "8")]
+    columns: usize,
+    /// Write the sprites to this PNG instead of the terminal
+    #[clap(long, parse(from_os_str))]
+    png: Option<PathBuf>,
+    /// Colours of the PNG, as for the main command
+    #[clap(long)]
+    palette: Option<Palette>,
+    /// Image pixels per sprite pixel in the PNG [default: 10]
+    #[clap(long, parse(try_from_str = parse_scale))]
+    scale: Option<usize>,
+    /// The rom to draw
+    #[clap(parse(from_os_str))]
     rom: PathBuf,
 }
 
 fn main() {
     let args = Args::parse();
 
-    use chip8::opcode::OpCode;
-    use chip8::types::Addr;
-    let opcode = OpCode::from((0xFA, 0xCE));
-    let opcode_tuple = opcode.to_match_tuple();
-    let addr = Addr::from(0xFACE);
-    println!("{}", addr);
-    println!("{:#04X?}", opcode_tuple);
+    // scripts print through the log, so they stay visible
+    let level = if args.debug {
+        "debug"
+    } else {
+        "warn, chip8::script = info"
+    };
+    if let Err(e) = flexi_logger::Logger::with_env_or_str(level).start() {
+        eprintln!("warning: logging disabled: {}", e);
+    }
 
-    println!("\n\n{}", opcode_tuple.1);
+    let res = match args.cmd {
+        Some(Command::Info(ref info_args)) => info(info_args),
+        Some(Command::Disasm(ref disasm_args)) => disasm(disasm_args),
+        Some(Command::Sprites(ref sprites_args)) => sprites(sprites_args),
+        Some(Command::Opcodes) => {
+            print!("{}", optable::reference());
+            Ok(())
+        }
+        Some(Command::Dap) => {
+            let stdin = io::BufReader::new(io::stdin());
+            DapServer::new().serve(stdin, io::stdout())
+        }
+        None if args.rom.is_none() => {
+            eprintln!("error: no ROM given\n");
+            Args::into_app().print_help().ok();
+            process::exit(2);
+        }
+        None => run(args),
+    };
+    if let Err(e) = res {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(args: Args) -> Result<()> {
+    let config = match &args.config {
+        Some(path) => Config::open(path)?,
+        None => Config::load_default()?,
+    };
 
-    use chip8::Chip8;
     let mut emu = Chip8::new();
-    emu.load_rom(&args.rom).unwrap();
+    let database = match &args.db {
+        Some(path) => Some(Database::open(path)?),
+        None => Database::load_default()?,
+    };
+    if let Some(database) = database {
+        emu.set_database(Arc::new(database));
+    }
+    emu.set_load_addr(args.load_addr);
+    if let Some(entry) = args.entry {
+        emu.set_entry(entry);
+    }
+
+    // settings for all ROMs come first, so the ROM database can override them
+    config.settings.apply(&mut emu);
+    emu.set_protection(args.protect || config.machine.protect.unwrap_or(false));
+
+    if let Some(path) = &args.symbols {
+        emu.set_symbols(Symbols::open(path)?);
+    }
+    for (addr, path) in &args.preload {
+        emu.load_code(*addr, &fs::read(path)?)?;
+    }
+    // `rom` is only optional when a subcommand is given, which `main` has checked
+    let path = args.rom.as_ref().expect("missing ROM");
+    let rom = fs::read(path)?;
+    emu.load_rom_bytes(&rom)?;
+
+    if let Some(info) = emu.rom_info() {
+        println!("{} ({})", info.title, info.platform);
+    }
+
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let rom_settings = config.rom_settings(&name, &database::sha1_hex(&rom));
+    rom_settings.apply(&mut emu);
+    Settings {
+        platform: args.platform,
+        clock_hz: args.clock,
+        timing: args.timing,
+        ..Settings::default()
+    }
+    .apply(&mut emu);
+
+    // an explicit palette wins over the colours the ROM database suggests
+    let palette = match args.palette {
+        Some(palette) => palette,
+        None => rom_settings
+            .palette()
+            .or_else(|| {
+                emu.rom_info()
+                    .filter(|info| !info.colors.is_empty())
+                    .map(|info| Palette::from_hex(&info.colors))
+            })
+            .or_else(|| config.settings.palette())
+            .unwrap_or_else(|| Ok(Palette::default()))
+            .unwrap_or_else(|e| {
+                log::warn!("Ignoring palette: {}", e);
+                Palette::default()
+            }),
+    };
+    let scale = args
+        .scale
+        .or(rom_settings.scale)
+        .or(config.settings.scale)
+        .unwrap_or(render::DEFAULT_SCALE);
+
+    let rom_keys = emu
+        .rom_info()
+        .map(|info| info.keys.clone())
+        .unwrap_or_default();
+    let keymap = Keymap::default().with_rom_keys(&rom_keys);
+    let mut keymap = keymap
+        .clone()
+        .with_overrides(&config.settings.merge(&rom_settings).keymap)
+        .unwrap_or_else(|e| {
+            log::warn!("Ignoring keymap: {}", e);
+            keymap
+        });
+    log::debug!("Keymap: {}", keymap);
+
+    let display = emu.display();
+    println!(
+        "Display: {}x{} at {}x, palette {}",
+        display.width(),
+        display.height(),
+        scale,
+        palette
+    );
 
-    println!("{:?}", emu.ram)
+    // there's no sound device to play to, so sound can only be recorded
+    let mut audio = match &args.wav {
+        Some(path) => {
+            let config = config.audio();
+            let wav = WavWriter::create(path, config.sample_rate)?;
+            Some(Audio::new(config, Box::new(wav)))
+        }
+        None => None,
+    };
+    let mut recorder = match &args.record {
+        Some(path) => Some(GifRecorder::create(
+            path,
+            display.width(),
+            display.height(),
+            &palette,
+            scale,
+        )?),
+        None => None,
+    };
+
+    if let Some(addr) = &args.gdb {
+        GdbStub::new(&mut emu).serve(addr.as_str())?;
+    } else if let Some(path) = &args.script {
+        let mut script = Script::with_keymap(&fs::read_to_string(path)?, emu, keymap)?;
+        let mut frames = 0;
+        while args.frames.is_none_or(|limit| frames < limit) {
+            frames += 1;
+            if !script.run_frame()? {
+                break;
+            }
+            output_frame(&mut audio, &mut recorder, &script.chip8())?;
+        }
+        println!("Script ran {} frames", frames);
+        emu = script.into_chip8();
+    } else if let Some(frames) = args.frames {
+        emu.set_profiling(args.profile.is_some());
+        emu.set_coverage(args.coverage.is_some());
+        let mut pacer = Pacer::new(args.speed);
+        pacer.set_limit(Some(frames));
+        let start = Instant::now();
+        let mut shown = String::new();
+        let commands = console();
+        while !pacer.finished() {
+            for line in commands.try_iter() {
+                let words: Vec<_> = line.split_whitespace().collect();
+                match words.as_slice() {
+                    ["down", host] => keymap.key_down(host, emu.keypad_mut()),
+                    ["up", host] => keymap.key_up(host, emu.keypad_mut()),
+                    ["turbo"] => {
+                        pacer.toggle_uncapped();
+                        println!("Speed: {}", pacer.speed());
+                    }
+                    ["fps"] => println!("{:.0} fps", pacer.fps()),
+                    ["speed", speed] => match speed.parse::<Speed>() {
+                        Ok(speed) => {
+                            pacer.set_speed(speed);
+                            println!("Speed: {}", pacer.speed());
+                        }
+                        Err(e) => log::warn!("{}", e),
+                    },
+                    ["shot", path] => {
+                        match capture::screenshot(emu.display(), &palette, scale, path) {
+                            Ok(()) => println!("Saved screenshot to {}", path),
+                            Err(e) => log::warn!("Screenshot failed: {}", e),
+                        }
+                    }
+                    ["record", "start", path] if recorder.is_none() => {
+                        let display = emu.display();
+                        let width = display.width();
+                        let height = display.height();
+                        match GifRecorder::create(path, width, height, &palette, scale) {
+                            Ok(started) => {
+                                recorder = Some(started);
+                                println!("Recording to {}", path);
+                            }
+                            Err(e) => log::warn!("Recording failed: {}", e),
+                        }
+                    }
+                    ["record", "start", _] => log::warn!("Already recording"),
+                    ["record", "stop"] => match recorder.take().map(GifRecorder::finish) {
+                        Some(Ok(_)) => println!("Recording stopped"),
+                        Some(Err(e)) => log::warn!("Recording failed: {}", e),
+                        None => log::warn!("Not recording"),
+                    },
+                    [] => (),
+                    _ => log::warn!("Unknown command `{}`", line),
+                }
+            }
+            let output = |emu: &Chip8| output_frame(&mut audio, &mut recorder, emu);
+            if pacer.run_with(&mut emu, Instant::now(), output)? == 0 {
+                thread::sleep(Duration::from_millis(1));
+            } else if let Some(size) = args.sprites {
+                let sprites = emu.sprites(size, 16).to_string();
+                if sprites != shown {
+                    // clear the terminal and draw from the top left
+                    print!("\x1b[H\x1b[2J{}", sprites);
+                    shown = sprites;
+                }
+            }
+        }
+        let elapsed = start.elapsed().as_secs_f64();
+        println!(
+            "Ran {} frames at {} in {:.2}s ({:.0} fps)",
+            frames,
+            pacer.speed(),
+            elapsed,
+            frames as f64 / elapsed
+        );
+
+        if let (Some(path), Some(profile)) = (&args.profile, emu.profile()) {
+            print!("{}", profile.report(emu.symbols()));
+            profile.write_folded(emu.symbols(), BufWriter::new(fs::File::create(path)?))?;
+        }
+        if let (Some(path), Some(coverage)) = (&args.coverage, emu.coverage()) {
+            let source = path.with_extension("asm");
+            coverage.write_annotated(
+                &rom,
+                args.load_addr,
+                BufWriter::new(fs::File::create(&source)?),
+            )?;
+            coverage.write_lcov(
+                &source.to_string_lossy(),
+                &rom,
+                args.load_addr,
+                BufWriter::new(fs::File::create(path)?),
+            )?;
+            println!("Coverage: {}", coverage.summary(&rom, args.load_addr));
+        }
+    }
+
+    if let Some(audio) = &mut audio {
+        audio.finish()?;
+    }
+    if let Some(recorder) = recorder {
+        recorder.finish()?;
+    }
+    if let Some(path) = &args.screenshot {
+        capture::screenshot(emu.display(), &palette, scale, path)?;
+    }
+
+    print!("{}", emu.hexdump());
+    Ok(())
+}
+
+fn info(args: &InfoArgs) -> Result<()> {
+    let rom = read_rom(&args.rom, args.load_addr)?;
+    println!("SHA-1: {}", database::sha1_hex(&rom));
+    let analysis = scan::scan(&rom, args.load_addr);
+    print!("{}", analysis);
+    println!(
+        "Suggested: chip8 --platform {} {}",
+        format!("{:?}", analysis.platform).to_lowercase(),
+        args.rom.display()
+    );
+
+    Ok(())
+}
+
+fn disasm(args: &DisasmArgs) -> Result<()> {
+    let rom = read_rom(&args.rom, args.load_addr)?;
+    let symbols = args.symbols.as_ref().map(Symbols::open).transpose()?;
+    let analysis = scan::scan(&rom, args.load_addr);
+    let platform = args.platform.unwrap_or(analysis.platform);
+    for addr in &analysis.reachable {
+        if let Some(label) = symbols.as_ref().and_then(|symbols| symbols.label(*addr)) {
+            println!("{}:", label);
+        }
+        let idx = (addr - args.load_addr) as usize;
+        let inst = OpCode::from((rom[idx], rom[idx + 1])).decode_for(platform);
+        let words: Vec<_> = rom[idx..]
+            .chunks_exact(2)
+            .take(inst.def().len as usize / 2)
+            .map(|word| format!("{:02X}{:02X}", word[0], word[1]))
+            .collect();
+        println!(
+            "{:03X}: ({}) {}",
+            addr,
+            words.join(" "),
+            inst.disassemble_with(symbols.as_ref())
+        );
+    }
+
+    Ok(())
+}
+
+fn sprites(args: &SpritesArgs) -> Result<()> {
+    let mut emu = Chip8::new();
+    emu.set_load_addr(args.load_addr);
+    emu.load_rom_bytes(&fs::read(&args.rom)?)?;
+
+    let addr = args.addr.unwrap_or(args.load_addr);
+    let sheet = SpriteSheet::new(emu.state().memory(), addr, args.size)
+        .count(args.count)
+        .columns(args.columns);
+    match &args.png {
+        Some(path) => {
+            let palette = args.palette.unwrap_or_default();
+            let scale = args.scale.unwrap_or(render::DEFAULT_SCALE);
+            let mut writer = BufWriter::new(fs::File::create(path)?);
+            sheet.write_png(&palette, scale, &mut writer)?;
+            writer.flush()?;
+        }
+        None => print!("{}", sheet),
+    }
+
+    Ok(())
+}
+
+/// Write a frame the run produced to the sound and display recordings, if any
+fn output_frame(
+    audio: &mut Option<Audio>,
+    recorder: &mut Option<GifRecorder<BufWriter<fs::File>>>,
+    emu: &Chip8,
+) -> Result<()> {
+    if let Some(audio) = audio {
+        audio.frame(emu)?;
+    }
+    if let Some(recorder) = recorder {
+        recorder.frame(emu.display())?;
+    }
+    Ok(())
+}
+
+/// Read the commands typed on stdin during a run, a line at a time
+fn console() -> mpsc::Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(|line| line.ok()) {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// Read a ROM to scan, failing if it doesn't fit in RAM after `load_addr`
+fn read_rom(path: &Path, load_addr: u16) -> Result<Vec<u8>> {
+    let rom = fs::read(path)?;
+    let available = chip8::memory::Ram::RAM_SIZE - load_addr as usize;
+    if rom.len() > available {
+        return Err(Chip8Error::TooLarge {
+            size: rom.len(),
+            available,
+        });
+    }
+    Ok(rom)
+}
+
+/// Parse a memory address given in hex (`0x200`) or decimal (`512`)
+fn parse_addr(s: &str) -> std::result::Result<u16, String> {
+    let addr = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|e| format!("invalid address `{}`: {}", s, e))?;
+
+    if addr as usize >= chip8::memory::Ram::RAM_SIZE {
+        return Err(format!("address `{}` is outside of RAM", s));
+    }
+    Ok(addr)
+}
 
-    // if let Err(e) = run(args) {
-    //     eprintln!("error: {}", e);
-    // }
+/// Parse a display scale, a whole number from 1 up to 10 times the default
+fn parse_scale(s: &str) -> std::result::Result<usize, String> {
+    match s.parse() {
+        Ok(scale) if (1..=render::DEFAULT_SCALE * 10).contains(&scale) => Ok(scale),
+        _ => Err(format!(
+            "invalid scale `{}`, expected a whole number from 1 to {}",
+            s,
+            render::DEFAULT_SCALE * 10
+        )),
+    }
 }
 
-fn run(args: Args) -> io::Result<()> {
-    todo!()
+/// Parse a preload given as `ADDR:FILE`
+fn parse_preload(s: &str) -> std::result::Result<(u16, PathBuf), String> {
+    let mut parts = s.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some(addr), Some(path)) if !path.is_empty() => Ok((parse_addr(addr)?, path.into())),
+        _ => Err(format!(
+            "invalid preload `{}`, expected `ADDR:FILE` e.g. `{:#05X}:data.bin`",
+            s,
+            register::ETI_660_PROGRAM_START
+        )),
+    }
 }
@@ -33,27 +33,214 @@
 //! +---------------+= 0x000 Start of Chip-8 RAM
 //! ```
 
+use std::borrow::Cow;
 use std::fmt;
 use std::ops;
 
+use crate::error::{Chip8Error, Result};
+use crate::hexdump::Hexdump;
+use crate::sprites::{SpriteSheet, SpriteSize};
+use crate::types::Addr;
+
+/// How out-of-range accesses through the checked `Ram` methods are handled
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum AccessMode {
+    /// Addresses wrap at 12 bits, matching the masking done by [`Addr`]
+    ///
+    /// [`Addr`]: ../types/struct.Addr.html
+    #[default]
+    Wrap,
+    /// Out-of-range accesses return [`Chip8Error::OutOfBounds`]
+    ///
+    /// [`Chip8Error::OutOfBounds`]: ../error/enum.Chip8Error.html#variant.OutOfBounds
+    Strict,
+}
+
 /// Struct representing the CHIP-8 system RAM
-#[repr(transparent)]
-pub struct Ram(pub(crate) [u8; Self::RAM_SIZE]);
+pub struct Ram {
+    mem: [u8; Self::RAM_SIZE],
+    mode: AccessMode,
+    /// Addresses written since the last [`drain_writes`], one bit per byte
+    ///
+    /// [`drain_writes`]: #method.drain_writes
+    written: [u64; Self::RAM_SIZE / 64],
+    /// Whether any bit of `written` is set
+    dirty: bool,
+    /// Overwritten bytes and their old values since the last [`take_journal`], if journaling
+    ///
+    /// [`take_journal`]: #method.take_journal
+    journal: Option<Vec<(u16, u8)>>,
+}
 
 impl Ram {
     /// Size of memory, 4096 bytes
     pub const RAM_SIZE: usize = 0x1000;
+    /// Size of the region reserved for the interpreter, `0x000..0x1FF` inclusive
+    pub const RESERVED_SIZE: usize = 0x200;
 
     // XXX: this method is not required since we impl Default, and that's all we call in new()
     /// Create new `Ram`
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Create new `Ram` using `mode` for out-of-range accesses
+    pub fn with_mode(mode: AccessMode) -> Self {
+        Self {
+            mode,
+            ..Self::default()
+        }
+    }
+
+    /// Get the current [`AccessMode`]
+    ///
+    /// [`AccessMode`]: enum.AccessMode.html
+    pub fn mode(&self) -> AccessMode {
+        self.mode
+    }
+
+    /// Set the [`AccessMode`] used by the checked accessors
+    ///
+    /// [`AccessMode`]: enum.AccessMode.html
+    pub fn set_mode(&mut self, mode: AccessMode) {
+        self.mode = mode;
+    }
+
+    /// Read the byte at `addr`
+    pub fn read(&self, addr: usize) -> Result<u8> {
+        Ok(self.mem[self.resolve(addr)?])
+    }
+
+    /// Write `val` to the byte at `addr`
+    pub fn write(&mut self, addr: usize, val: u8) -> Result<()> {
+        let idx = self.resolve(addr)?;
+        self.mark_written(idx, 1);
+        self.mem[idx] = val;
+        Ok(())
+    }
+
+    /// Read `len` bytes starting at `addr`
+    ///
+    /// The returned slice is only copied if it wraps around the end of memory.
+    pub fn read_slice(&self, addr: usize, len: usize) -> Result<Cow<'_, [u8]>> {
+        let start = self.resolve(addr)?;
+        match start.checked_add(len) {
+            Some(end) if end <= Self::RAM_SIZE => Ok(Cow::Borrowed(&self.mem[start..end])),
+            _ => {
+                // resolve the last byte first so `Strict` fails before allocating
+                if len > 0 {
+                    self.resolve(addr.saturating_add(len - 1))?;
+                }
+                let bytes = (0..len).map(|off| self.mem[(start + off) & ADDR_MASK]);
+                Ok(Cow::Owned(bytes.collect()))
+            }
+        }
+    }
+
+    /// Write all of `data` starting at `addr`
+    ///
+    /// Nothing is written if any part of `data` would fall out of range.
+    pub fn write_slice(&mut self, addr: usize, data: &[u8]) -> Result<()> {
+        let start = self.resolve(addr)?;
+        if !data.is_empty() {
+            self.resolve(addr.saturating_add(data.len() - 1))?;
+        }
+        self.mark_written(start, data.len());
+        for (off, byte) in data.iter().enumerate() {
+            self.mem[(start + off) & ADDR_MASK] = *byte;
+        }
+        Ok(())
+    }
+
+    /// Call `f` with every address written since the last call, in ascending order
+    ///
+    /// Writes through indexing count too, so anything caching the contents of memory (e.g.
+    /// decoded instructions) can find what it needs to invalidate.
+    pub fn drain_writes(&mut self, mut f: impl FnMut(usize)) {
+        if !self.dirty {
+            return;
+        }
+        for (word, bits) in self.written.iter_mut().enumerate() {
+            while *bits != 0 {
+                let bit = bits.trailing_zeros() as usize;
+                *bits &= *bits - 1;
+                f(word * 64 + bit);
+            }
+        }
+        self.dirty = false;
+    }
+
+    /// Format all of memory as a [`Hexdump`], which can be narrowed down to a range
+    ///
+    /// [`Hexdump`]: ../hexdump/struct.Hexdump.html
+    pub fn hexdump(&self) -> Hexdump<'_> {
+        Hexdump::new(&self.mem)
+    }
+
+    /// Show the memory at `addr` as a [`SpriteSheet`] of sprites of `size`
+    ///
+    /// [`SpriteSheet`]: ../sprites/struct.SpriteSheet.html
+    pub fn sprites(&self, addr: u16, size: SpriteSize) -> SpriteSheet<'_> {
+        SpriteSheet::new(&self.mem, addr, size)
+    }
+
+    /// Start or stop recording the old value of every byte written, see [`take_journal`]
+    ///
+    /// [`take_journal`]: #method.take_journal
+    pub fn set_journaling(&mut self, enabled: bool) {
+        self.journal = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// Take the bytes written since the last call, with their old values, in the order they
+    /// were written
+    ///
+    /// Always empty unless journaling is enabled.
+    pub fn take_journal(&mut self) -> Vec<(u16, u8)> {
+        self.journal
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Record a write of `len` bytes from `idx`, wrapping at the end of memory, before it
+    /// happens
+    fn mark_written(&mut self, idx: usize, len: usize) {
+        for addr in (idx..idx + len.min(Self::RAM_SIZE)).map(|a| a & ADDR_MASK) {
+            self.written[addr / 64] |= 1 << (addr % 64);
+            if let Some(journal) = self.journal.as_mut() {
+                journal.push((addr as u16, self.mem[addr]));
+            }
+        }
+        self.dirty |= len > 0;
+    }
+
+    /// Map `addr` to an index into memory according to the current [`AccessMode`]
+    ///
+    /// [`AccessMode`]: enum.AccessMode.html
+    fn resolve(&self, addr: usize) -> Result<usize> {
+        match self.mode {
+            AccessMode::Wrap => Ok(addr & ADDR_MASK),
+            AccessMode::Strict if addr < Self::RAM_SIZE => Ok(addr),
+            AccessMode::Strict => {
+                log::error!("Memory access out of bounds: {:#06X}", addr);
+                Err(Chip8Error::OutOfBounds(addr))
+            }
+        }
+    }
 }
 
+/// Mask applied to addresses when wrapping at 12 bits
+const ADDR_MASK: usize = Ram::RAM_SIZE - 1;
+
 impl Default for Ram {
     fn default() -> Self {
-        Self([0x00; Self::RAM_SIZE])
+        Self {
+            mem: [0x00; Self::RAM_SIZE],
+            mode: AccessMode::default(),
+            written: [0; Self::RAM_SIZE / 64],
+            dirty: false,
+            journal: None,
+        }
     }
 }
 
@@ -61,78 +248,130 @@ impl ops::Index<usize> for Ram {
     type Output = u8;
 
     fn index(&self, index: usize) -> &Self::Output {
-        &self.0[index]
+        &self.mem[index]
     }
 }
 
 impl ops::IndexMut<usize> for Ram {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        &mut self.0[index]
+        self.mark_written(index, 1);
+        &mut self.mem[index]
+    }
+}
+
+impl ops::Index<Addr> for Ram {
+    type Output = u8;
+
+    fn index(&self, index: Addr) -> &Self::Output {
+        &self.mem[usize::from(index)]
+    }
+}
+
+impl ops::IndexMut<Addr> for Ram {
+    fn index_mut(&mut self, index: Addr) -> &mut Self::Output {
+        self.mark_written(usize::from(index), 1);
+        &mut self.mem[usize::from(index)]
+    }
+}
+
+impl ops::Index<ops::Range<usize>> for Ram {
+    type Output = [u8];
+
+    fn index(&self, index: ops::Range<usize>) -> &Self::Output {
+        &self.mem[index]
     }
 }
 
-// TODO: impl Index<Range<usize>> for Ram
-
-// impl ops::Index<Addr> for Ram {
-//     type Output = u8;
-//
-//     fn index(&self, index: Addr) -> &Self::Output {
-//         &self.0[usize::from(index)]
-//     }
-// }
-//
-// impl ops::IndexMut<Addr> for Ram {
-//     fn index_mut(&mut self, index: Addr) -> &mut Self::Output {
-//         &mut self.0[usize::from(index)]
-//     }
-// }
-//
-// impl ops::Index<ops::Range<u16>> for Ram {
-//     type Output = [u8; 2];
-//
-//     fn index(&self, index: std::ops::Range<u16>) -> &Self::Output {
-//         &self[index]
-//     }
-// }
-//
-// impl ops::IndexMut<ops::Range<u16>> for Ram {
-//     fn index_mut(&mut self, index: std::ops::Range<u16>) -> &mut Self::Output {
-//         todo!()
-//     }
-// }
+impl ops::IndexMut<ops::Range<usize>> for Ram {
+    fn index_mut(&mut self, index: ops::Range<usize>) -> &mut Self::Output {
+        self.mark_written(index.start, index.len());
+        &mut self.mem[index]
+    }
+}
+
+impl ops::Index<ops::Range<Addr>> for Ram {
+    type Output = [u8];
+
+    fn index(&self, index: ops::Range<Addr>) -> &Self::Output {
+        &self.mem[usize::from(index.start)..usize::from(index.end)]
+    }
+}
+
+impl ops::IndexMut<ops::Range<Addr>> for Ram {
+    fn index_mut(&mut self, index: ops::Range<Addr>) -> &mut Self::Output {
+        let (start, end) = (usize::from(index.start), usize::from(index.end));
+        self.mark_written(start, end.saturating_sub(start));
+        &mut self.mem[start..end]
+    }
+}
 
 impl fmt::Debug for Ram {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // this function doesn't seem efficient
-        let mut vec: Vec<String> = Vec::with_capacity(17);
-        vec.push(String::from("Address"));
-        for digit in 0x0..0xF + 1 {
-            vec.push(format!("{:X} ", digit));
-        }
+        fmt::Display::fmt(&self.hexdump(), f)
+    }
+}
 
-        // write out the headers
-        for header in &vec {
-            write!(f, "{} ", header)?;
-        }
-        write!(f, "\n")?;
-        // we're finished printing the headers now
-        // clear the vec and get it ready for a line of data
-        vec.clear();
-
-        let mut pc = 0x00;
-        for chunk in self.0.chunks(16) {
-            vec.push(format!("${:#06X}", pc));
-            for addr in chunk {
-                vec.push(format!("{:02X}", addr));
-            }
-            for i in &vec {
-                write!(f, "{} ", i)?;
-            }
-            write!(f, "\n")?;
-            vec.clear();
-            pc += 0x10;
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        Ok(())
+    #[test]
+    fn wrap_read_write() {
+        let mut ram = Ram::new();
+        ram.write(0x1234, 0xAB).unwrap();
+        assert_eq!(ram[0x234], 0xAB);
+        assert_eq!(ram.read(0x0234).unwrap(), 0xAB);
+    }
+
+    #[test]
+    fn wrap_slices() {
+        let mut ram = Ram::new();
+        ram.write_slice(0xFFE, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(&ram[0xFFE..0x1000], &[1, 2]);
+        assert_eq!(&ram[0x000..0x002], &[3, 4]);
+        assert_eq!(&*ram.read_slice(0xFFE, 4).unwrap(), &[1, 2, 3, 4]);
+        assert!(matches!(
+            ram.read_slice(0x200, 2).unwrap(),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn strict_out_of_bounds() {
+        let mut ram = Ram::with_mode(AccessMode::Strict);
+        assert!(matches!(
+            ram.read(0x1000),
+            Err(Chip8Error::OutOfBounds(0x1000))
+        ));
+        assert!(matches!(
+            ram.read_slice(0xFFF, 2),
+            Err(Chip8Error::OutOfBounds(0x1000))
+        ));
+        assert!(ram.write_slice(0xFFE, &[1, 2, 3]).is_err());
+        assert_eq!(ram[0xFFE], 0x00, "failed writes must not be partial");
+        assert!(ram.write_slice(0xFFE, &[1, 2]).is_ok());
+    }
+
+    #[test]
+    fn index_addr() {
+        let mut ram = Ram::new();
+        ram[Addr::from(0x1200)] = 0x12;
+        assert_eq!(ram[0x200], 0x12);
+        assert_eq!(&ram[Addr::from(0x200)..Addr::from(0x201)], &[0x12]);
+    }
+
+    #[test]
+    fn drain_writes() {
+        let mut ram = Ram::new();
+        ram.write_slice(0xFFF, &[1, 2]).unwrap();
+        ram[0x300] = 3;
+
+        let mut written = Vec::new();
+        ram.drain_writes(|addr| written.push(addr));
+        assert_eq!(written, vec![0x000, 0x300, 0xFFF]);
+
+        written.clear();
+        ram.drain_writes(|addr| written.push(addr));
+        assert!(written.is_empty());
     }
 }
@@ -3,17 +3,14 @@
 use std::convert::TryFrom;
 use std::fmt;
 
-use super::{
-    instruction::{self, Instruction},
-    types::Nibble,
-};
+use super::{instruction::Instruction, optable, quirks::Platform};
 
 /// A type representing the individual nibbles of an `OpCode`.
 pub type OpCodeTuple = (u8, u8, u8, u8);
 
 // TODO: better description
 /// A struct containing the raw opcode to decode
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct OpCode(u16);
 
 impl OpCode {
@@ -25,29 +22,46 @@ impl OpCode {
         self.into()
     }
 
-    /// Decode an `OpCode` to an appropriate [`Instruction`]
+    /// The lowest 12 bits of the opcode (`nnn`)
+    pub fn addr(self) -> u16 {
+        self.0 & 0x0FFF
+    }
+
+    /// The lowest 8 bits of the opcode (`kk`)
+    pub fn byte(self) -> u8 {
+        (self.0 & 0x00FF) as u8
+    }
+
+    /// Decode an `OpCode` to an appropriate [`Instruction`], using the [`optable`]
+    ///
+    /// Super Chip-48 and XO-CHIP instructions are decoded, but not executed.
     ///
     /// [`Instruction`]: ../instruction/struct.Instruction.html
+    /// [`optable`]: ../optable/index.html
     pub fn decode(self) -> Instruction {
-        match self.to_match_tuple() {
-            (0x0, 0x0, 0xE, 0x0) => {
-                Instruction::new(self, "CLS", Operands::Empty, instruction::clear)
-            }
-            (0x0, 0x0, 0xE, 0xE) => {
-                Instruction::new(self, "RET", Operands::Empty, instruction::r#return)
-            }
-            (0x0, _, _, _) => Instruction::new(self, "SYS", Operands::Empty, instruction::sys),
-            _ => {
+        self.decode_def(optable::lookup(self))
+    }
+
+    /// Decode an `OpCode` as [`decode`] does, only to instructions `platform` supports
+    ///
+    /// [`decode`]: #method.decode
+    pub fn decode_for(self, platform: Platform) -> Instruction {
+        self.decode_def(optable::lookup_for(self, platform))
+    }
+
+    fn decode_def(self, def: Option<&'static optable::OpDef>) -> Instruction {
+        match def {
+            Some(def) => Instruction::new(self, def),
+            None => {
                 log::warn!("Failed to decode: `{:#06X}`", self);
-                Instruction::new(self, "???", Operands::Empty, instruction::not_implemented)
+                Instruction::new(self, &optable::UNKNOWN)
             }
         }
-        //todo!("implement decode")
     }
 }
 
 /// Operands variants for an opcode
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub enum Operands {
     /// No operands
     Empty,
@@ -55,6 +69,8 @@ pub enum Operands {
     Address(u16),
     /// Register name (`x`)
     Reg(u8),
+    /// 4 bit constant (`n`)
+    Const(u8),
     /// Register names (`xy`)
     Regs(u8, u8),
     /// Register name and 8 bit constant (`xkk`)
@@ -63,6 +79,50 @@ pub enum Operands {
     RegsAndConst(u8, u8, u8),
 }
 
+// Instructions are only ever handed the operands they were decoded with, so a mismatch in the
+// accessors below is a bug in the `optable`.
+impl Operands {
+    /// Get the address of `Address` operands
+    pub fn addr(&self) -> u16 {
+        match *self {
+            Self::Address(nnn) => nnn,
+            _ => panic!("expected address operand, got `{:?}`", self),
+        }
+    }
+
+    /// Get the register name of `Reg` operands
+    pub fn reg(&self) -> u8 {
+        match *self {
+            Self::Reg(x) => x,
+            _ => panic!("expected register operand, got `{:?}`", self),
+        }
+    }
+
+    /// Get the register names of `Regs` operands
+    pub fn regs(&self) -> (u8, u8) {
+        match *self {
+            Self::Regs(x, y) => (x, y),
+            _ => panic!("expected register operands, got `{:?}`", self),
+        }
+    }
+
+    /// Get the register name and constant of `RegAndConst` operands
+    pub fn reg_and_const(&self) -> (u8, u8) {
+        match *self {
+            Self::RegAndConst(x, kk) => (x, kk),
+            _ => panic!("expected register and constant operands, got `{:?}`", self),
+        }
+    }
+
+    /// Get the register names and constant of `RegsAndConst` operands
+    pub fn regs_and_const(&self) -> (u8, u8, u8) {
+        match *self {
+            Self::RegsAndConst(x, y, n) => (x, y, n),
+            _ => panic!("expected register and constant operands, got `{:?}`", self),
+        }
+    }
+}
+
 // Only need this is we can't get chunks() to work for [u8;2]
 impl TryFrom<&[u8]> for OpCode {
     type Error = String; // TODO: use proper error type
@@ -101,13 +161,25 @@ impl From<(u8, u8)> for OpCode {
     }
 }
 
+impl From<u16> for OpCode {
+    fn from(value: u16) -> Self {
+        Self(value)
+    }
+}
+
+impl From<OpCode> for u16 {
+    fn from(opcode: OpCode) -> Self {
+        opcode.0
+    }
+}
+
 impl From<OpCode> for (u8, u8, u8, u8) {
     fn from(opcode: OpCode) -> Self {
         (
             ((opcode.0 & 0xF000) >> 12) as u8,
             ((opcode.0 & 0x0F00) >> 8) as u8,
             ((opcode.0 & 0x00F0) >> 4) as u8,
-            ((opcode.0 & 0x000F) >> 0) as u8,
+            (opcode.0 & 0x000F) as u8,
         )
     }
 }
@@ -147,6 +219,7 @@ impl fmt::Display for Operands {
             Self::Empty => write!(f, ""),
             Self::Address(addr) => write!(f, "{:#03X}", addr),
             Self::Reg(vx) => write!(f, "V{:X}", vx),
+            Self::Const(n) => write!(f, "{:#03X}", n),
             Self::Regs(vx, vy) => write!(f, "V{:X} V{:X}", vx, vy),
             Self::RegAndConst(vx, kk) => write!(f, "V{:X} {:#04X}", vx, kk),
             Self::RegsAndConst(vx, vy, n) => write!(f, "V{:X} V{:X} {:#03X}", vx, vy, n),
@@ -0,0 +1,424 @@
+//! Chip-8 opcode table.
+//!
+//! Every instruction is described by a single row of [`OPCODES`]: the opcode pattern, its
+//! assembly syntax, operand format, the function executing it, its cost and the platforms which
+//! support it. Decoding, disassembly, assembly, ROM scanning and the opcode reference are all
+//! driven by the table, so supporting a new instruction only means adding a row.
+//!
+//! Patterns are written the usual way, hex digits must match exactly and letters are operands,
+//! e.g. `8xy4`. Rows are matched in order, so more specific patterns must come first. The
+//! emulator only matches the rows its platform supports, so e.g. `00FF` is `SYS` on Chip-8.
+//!
+//! [`OPCODES`]: constant.OPCODES.html
+
+use std::fmt::Write;
+
+use super::{
+    instruction::{self, InstrFn},
+    opcode::{OpCode, Operands},
+    quirks::Platform,
+    symbols::Symbols,
+};
+
+/// How the operands of an instruction are encoded
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Format {
+    /// No operands
+    None,
+    /// 12 bit address, `nnn`
+    Addr,
+    /// Register, `x`
+    X,
+    /// 4 bit constant in the lowest nibble, `n`
+    N,
+    /// 4 bit constant in place of a register, `x`
+    XConst,
+    /// Two registers, `xy`
+    XY,
+    /// Register and 8 bit constant, `xkk`
+    XKK,
+    /// Two registers and a 4 bit constant, `xyn`
+    XYN,
+}
+
+impl Format {
+    /// Syntax placeholders of the format, with the shift and mask of their bits in the opcode
+    fn fields(self) -> &'static [(&'static str, u16, u16)] {
+        match self {
+            Self::None => &[],
+            Self::Addr => &[("nnn", 0, 0xFFF)],
+            Self::X => &[("Vx", 8, 0xF)],
+            Self::N => &[("n", 0, 0xF)],
+            Self::XConst => &[("n", 8, 0xF)],
+            Self::XY => &[("Vx", 8, 0xF), ("Vy", 4, 0xF)],
+            Self::XKK => &[("Vx", 8, 0xF), ("kk", 0, 0xFF)],
+            Self::XYN => &[("Vx", 8, 0xF), ("Vy", 4, 0xF), ("n", 0, 0xF)],
+        }
+    }
+
+    /// Extract the [`Operands`] of `opcode`
+    ///
+    /// [`Operands`]: ../opcode/enum.Operands.html
+    pub fn operands(self, opcode: OpCode) -> Operands {
+        let (_, x, y, n) = opcode.to_match_tuple();
+        match self {
+            Self::None => Operands::Empty,
+            Self::Addr => Operands::Address(opcode.addr()),
+            Self::X => Operands::Reg(x),
+            Self::N => Operands::Const(n),
+            Self::XConst => Operands::Const(x),
+            Self::XY => Operands::Regs(x, y),
+            Self::XKK => Operands::RegAndConst(x, opcode.byte()),
+            Self::XYN => Operands::RegsAndConst(x, y, n),
+        }
+    }
+}
+
+/// A row of the opcode table
+#[derive(Debug)]
+pub struct OpDef {
+    /// Opcode pattern, e.g. `8xy4`
+    pub pattern: &'static str,
+    /// Assembly syntax, using the placeholders `Vx`, `Vy`, `nnn`, `kk` and `n`
+    pub syntax: &'static str,
+    /// How the operands are encoded
+    pub format: Format,
+    /// Function executing the instruction
+    pub handler: InstrFn,
+    /// Approximate cost on the COSMAC VIP in machine cycles, not counting fetch and decode
+    ///
+    /// Extension instructions never ran on the VIP and use a nominal cost.
+    pub cycles: u32,
+    /// Platforms supporting the instruction
+    pub platforms: &'static [Platform],
+    /// Length of the instruction in bytes, including any operand words after the opcode
+    pub len: u16,
+    /// Bits of the opcode fixed by the pattern
+    mask: u16,
+    /// Value of the fixed bits
+    value: u16,
+}
+
+impl OpDef {
+    const fn new(
+        pattern: &'static str,
+        syntax: &'static str,
+        format: Format,
+        handler: InstrFn,
+        cycles: u32,
+        platforms: &'static [Platform],
+    ) -> Self {
+        let (mask, value) = parse_pattern(pattern);
+        Self {
+            pattern,
+            syntax,
+            format,
+            handler,
+            cycles,
+            platforms,
+            len: 2,
+            mask,
+            value,
+        }
+    }
+
+    /// Make the row a 4 byte instruction, whose opcode is followed by an operand word
+    const fn long(self) -> Self {
+        Self { len: 4, ..self }
+    }
+
+    /// Whether `opcode` is an instance of this row
+    pub fn matches(&self, opcode: OpCode) -> bool {
+        u16::from(opcode) & self.mask == self.value
+    }
+
+    /// Mnemonic of the instruction, the first word of its syntax
+    pub fn mnemonic(&self) -> &'static str {
+        self.syntax.split(' ').next().unwrap_or(self.syntax)
+    }
+
+    /// Whether `platform` supports the instruction
+    pub fn supports(&self, platform: Platform) -> bool {
+        self.platforms.contains(&platform)
+    }
+
+    /// Whether the instruction is part of the original Chip-8
+    pub fn is_extension(&self) -> bool {
+        !self.platforms.contains(&Platform::Chip8)
+    }
+
+    /// Write `opcode` in assembly syntax
+    pub fn disassemble(&self, opcode: OpCode) -> String {
+        self.disassemble_with(opcode, None)
+    }
+
+    /// Write `opcode` in assembly syntax, with addresses named by `symbols`
+    pub fn disassemble_with(&self, opcode: OpCode, symbols: Option<&Symbols>) -> String {
+        let fields = self.format.fields();
+        let word = u16::from(opcode);
+
+        let mut out = String::new();
+        for (i, token) in tokens(self.syntax).enumerate() {
+            if i == 1 {
+                out.push(' ');
+            } else if i > 1 {
+                out.push_str(", ");
+            }
+            match fields.iter().find(|(name, ..)| *name == token) {
+                Some((name, shift, mask)) if name.starts_with('V') => {
+                    write!(out, "V{:X}", (word >> shift) & mask).unwrap();
+                }
+                Some((name, shift, mask)) => {
+                    let value = (word >> shift) & mask;
+                    match symbols {
+                        Some(symbols) if *name == "nnn" => out.push_str(&symbols.describe(value)),
+                        _ => write!(out, "{:#X}", value).unwrap(),
+                    }
+                }
+                None => out.push_str(token),
+            }
+        }
+        out
+    }
+
+    /// Assemble `line` if it has this row's syntax
+    fn assemble(&self, line: &str) -> Option<u16> {
+        let fields = self.format.fields();
+        let mut word = self.value;
+
+        let mut expected = tokens(self.syntax);
+        let mut given = tokens(line);
+        loop {
+            match (expected.next(), given.next()) {
+                (None, None) => return Some(word),
+                (Some(token), Some(arg)) => match fields.iter().find(|(name, ..)| *name == token) {
+                    Some((name, shift, mask)) => {
+                        let val = if name.starts_with('V') {
+                            parse_reg(arg)?
+                        } else {
+                            parse_num(arg)?
+                        };
+                        if val > *mask {
+                            return None;
+                        }
+                        word |= val << shift;
+                    }
+                    None if token.eq_ignore_ascii_case(arg) => (),
+                    None => return None,
+                },
+                _ => return None,
+            }
+        }
+    }
+}
+
+const ALL: &[Platform] = &[Platform::Chip8, Platform::Schip, Platform::XoChip];
+const SCHIP: &[Platform] = &[Platform::Schip, Platform::XoChip];
+const XO: &[Platform] = &[Platform::XoChip];
+
+/// Nominal cost of extension instructions
+const EXT: u32 = 20;
+
+/// The opcode table, see the [module documentation](index.html)
+pub const OPCODES: &[OpDef] = {
+    use instruction::*;
+    use Format::*;
+
+    &[
+        OpDef::new("00E0", "CLS", None, clear, 3078, ALL),
+        OpDef::new("00EE", "RET", None, r#return, 10, ALL),
+        OpDef::new("00Cn", "SCD n", N, not_implemented, EXT, SCHIP),
+        OpDef::new("00Dn", "SCU n", N, not_implemented, EXT, XO),
+        OpDef::new("00FB", "SCR", None, not_implemented, EXT, SCHIP),
+        OpDef::new("00FC", "SCL", None, not_implemented, EXT, SCHIP),
+        OpDef::new("00FD", "EXIT", None, not_implemented, EXT, SCHIP),
+        OpDef::new("00FE", "LOW", None, not_implemented, EXT, SCHIP),
+        OpDef::new("00FF", "HIGH", None, not_implemented, EXT, SCHIP),
+        OpDef::new("0nnn", "SYS nnn", Addr, sys, 0, ALL),
+        OpDef::new("1nnn", "JP nnn", Addr, jump, 12, ALL),
+        OpDef::new("2nnn", "CALL nnn", Addr, call, 26, ALL),
+        OpDef::new("3xkk", "SE Vx, kk", XKK, skip_eq_byte, 10, ALL),
+        OpDef::new("4xkk", "SNE Vx, kk", XKK, skip_ne_byte, 10, ALL),
+        OpDef::new("5xy0", "SE Vx, Vy", XY, skip_eq, 14, ALL),
+        OpDef::new("5xy2", "SAVE Vx, Vy", XY, not_implemented, EXT, XO),
+        OpDef::new("5xy3", "LOAD Vx, Vy", XY, not_implemented, EXT, XO),
+        OpDef::new("6xkk", "LD Vx, kk", XKK, load_byte, 6, ALL),
+        OpDef::new("7xkk", "ADD Vx, kk", XKK, add_byte, 10, ALL),
+        OpDef::new("8xy0", "LD Vx, Vy", XY, load, 12, ALL),
+        OpDef::new("8xy1", "OR Vx, Vy", XY, or, 44, ALL),
+        OpDef::new("8xy2", "AND Vx, Vy", XY, and, 44, ALL),
+        OpDef::new("8xy3", "XOR Vx, Vy", XY, xor, 44, ALL),
+        OpDef::new("8xy4", "ADD Vx, Vy", XY, add, 44, ALL),
+        OpDef::new("8xy5", "SUB Vx, Vy", XY, sub, 44, ALL),
+        OpDef::new("8xy6", "SHR Vx, Vy", XY, shift_right, 44, ALL),
+        OpDef::new("8xy7", "SUBN Vx, Vy", XY, sub_inv, 44, ALL),
+        OpDef::new("8xyE", "SHL Vx, Vy", XY, shift_left, 44, ALL),
+        OpDef::new("9xy0", "SNE Vx, Vy", XY, skip_ne, 14, ALL),
+        OpDef::new("Annn", "LD I, nnn", Addr, load_i, 12, ALL),
+        OpDef::new("Bnnn", "JP V0, nnn", Addr, jump0, 22, ALL),
+        OpDef::new("Cxkk", "RND Vx, kk", XKK, rand_byte, 36, ALL),
+        OpDef::new("Dxy0", "DRW Vx, Vy, 0", XYN, draw_sprite, EXT, SCHIP),
+        OpDef::new("Dxyn", "DRW Vx, Vy, n", XYN, draw_sprite, 22, ALL),
+        OpDef::new("Ex9E", "SKP Vx", X, skip_pressed, 14, ALL),
+        OpDef::new("ExA1", "SKNP Vx", X, skip_not_pressed, 14, ALL),
+        OpDef::new("F000", "LD I, LONG", None, not_implemented, EXT, XO).long(),
+        OpDef::new("Fn01", "PLANE n", XConst, not_implemented, EXT, XO),
+        OpDef::new("F002", "AUDIO", None, not_implemented, EXT, XO),
+        OpDef::new("Fx07", "LD Vx, DT", X, load_dt, 10, ALL),
+        OpDef::new("Fx0A", "LD Vx, K", X, wait_for_key, 18, ALL),
+        OpDef::new("Fx15", "LD DT, Vx", X, set_delay_timer, 10, ALL),
+        OpDef::new("Fx18", "LD ST, Vx", X, set_sound_timer, 10, ALL),
+        OpDef::new("Fx1E", "ADD I, Vx", X, add_i, 16, ALL),
+        OpDef::new("Fx29", "LD F, Vx", X, load_sprite, 16, ALL),
+        OpDef::new("Fx30", "LD HF, Vx", X, not_implemented, EXT, SCHIP),
+        OpDef::new("Fx33", "LD B, Vx", X, store_bcd, 80, ALL),
+        OpDef::new("Fx3A", "PITCH Vx", X, not_implemented, EXT, XO),
+        OpDef::new("Fx55", "LD [I], Vx", X, store_regs, 14, ALL),
+        OpDef::new("Fx65", "LD Vx, [I]", X, load_regs, 14, ALL),
+        OpDef::new("Fx75", "LD R, Vx", X, not_implemented, EXT, SCHIP),
+        OpDef::new("Fx85", "LD Vx, R", X, not_implemented, EXT, SCHIP),
+    ]
+};
+
+/// Row used for opcodes matching none of [`OPCODES`], which are ignored
+///
+/// [`OPCODES`]: constant.OPCODES.html
+pub static UNKNOWN: OpDef = OpDef::new(
+    "????",
+    "???",
+    Format::None,
+    instruction::not_implemented,
+    0,
+    &[],
+);
+
+/// Find the row `opcode` is an instance of
+pub fn lookup(opcode: OpCode) -> Option<&'static OpDef> {
+    OPCODES.iter().find(|def| def.matches(opcode))
+}
+
+/// Find the row `opcode` is an instance of, among the rows `platform` supports
+pub fn lookup_for(opcode: OpCode, platform: Platform) -> Option<&'static OpDef> {
+    OPCODES
+        .iter()
+        .find(|def| def.supports(platform) && def.matches(opcode))
+}
+
+/// Assemble a single instruction, e.g. `ADD V1, 0x10`
+///
+/// Numbers are given in hex with a `0x` or `#` prefix, or in decimal.
+pub fn assemble(line: &str) -> Result<u16, String> {
+    OPCODES
+        .iter()
+        .find_map(|def| def.assemble(line))
+        .ok_or_else(|| format!("invalid instruction `{}`", line.trim()))
+}
+
+/// Generate a Markdown reference of every instruction
+pub fn reference() -> String {
+    let mut out = String::from("| Opcode | Syntax | Platforms | Cycles |\n");
+    out.push_str("|--------|--------|-----------|--------|\n");
+    for def in OPCODES {
+        let platforms: Vec<_> = def.platforms.iter().map(|p| p.to_string()).collect();
+        writeln!(
+            out,
+            "| `{}` | `{}` | {} | {} |",
+            def.pattern,
+            def.syntax,
+            platforms.join(", "),
+            def.cycles
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// Split assembly into its mnemonic and operands
+fn tokens(line: &str) -> impl Iterator<Item = &str> {
+    line.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|token| !token.is_empty())
+}
+
+/// Parse a register name, e.g. `VA`
+fn parse_reg(s: &str) -> Option<u16> {
+    let digit = s.strip_prefix('V').or_else(|| s.strip_prefix('v'))?;
+    match u16::from_str_radix(digit, 16) {
+        Ok(reg) if digit.len() == 1 => Some(reg),
+        _ => None,
+    }
+}
+
+/// Parse a number in hex (`0x1F` or `#1F`) or decimal
+fn parse_num(s: &str) -> Option<u16> {
+    let hex = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .or_else(|| s.strip_prefix('#'));
+    match hex {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Get the mask and value of the fixed bits of `pattern`
+const fn parse_pattern(pattern: &str) -> (u16, u16) {
+    let bytes = pattern.as_bytes();
+    let (mut mask, mut value) = (0, 0);
+    let mut i = 0;
+    while i < bytes.len() {
+        let digit = match bytes[i] {
+            b @ b'0'..=b'9' => Some(b - b'0'),
+            b @ b'A'..=b'F' => Some(b - b'A' + 10),
+            _ => None,
+        };
+        mask <<= 4;
+        value <<= 4;
+        if let Some(digit) = digit {
+            mask |= 0xF;
+            value |= digit as u16;
+        }
+        i += 1;
+    }
+    (mask, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        assert_eq!(parse_pattern("8xyE"), (0xF00F, 0x800E));
+        assert_eq!(parse_pattern("00E0"), (0xFFFF, 0x00E0));
+        assert_eq!(lookup(OpCode::from((0xD1, 0x20))).unwrap().pattern, "Dxy0");
+        assert_eq!(lookup(OpCode::from((0xD1, 0x25))).unwrap().pattern, "Dxyn");
+        assert_eq!(lookup(OpCode::from((0x00, 0xE0))).unwrap().pattern, "00E0");
+        assert!(lookup(OpCode::from((0x80, 0x08))).is_none());
+    }
+
+    #[test]
+    fn platform_rows() {
+        let high = OpCode::from((0x00, 0xFF));
+        assert_eq!(lookup_for(high, Platform::Schip).unwrap().syntax, "HIGH");
+        assert_eq!(lookup_for(high, Platform::Chip8).unwrap().syntax, "SYS nnn");
+        let draw = OpCode::from((0xD1, 0x20));
+        assert_eq!(lookup_for(draw, Platform::Chip8).unwrap().pattern, "Dxyn");
+        assert!(lookup_for(OpCode::from((0xF0, 0x00)), Platform::Schip).is_none());
+    }
+
+    #[test]
+    fn round_trip() {
+        // every row assembles back from its own disassembly
+        for def in OPCODES {
+            let opcode = OpCode::from(def.value | 0x0120 & !def.mask);
+            let text = def.disassemble(opcode);
+            assert_eq!(assemble(&text), Ok(u16::from(opcode)), "{}", text);
+        }
+
+        assert_eq!(assemble("add v1, #10"), Ok(0x7110));
+        assert_eq!(assemble("LD [I], VA"), Ok(0xFA55));
+        assert_eq!(assemble("DRW V1, V2, 15"), Ok(0xD12F));
+        assert!(assemble("LD V1, 0x100").is_err());
+        assert!(assemble("NOP").is_err());
+    }
+}
@@ -0,0 +1,308 @@
+//! Real time pacing of emulation.
+//!
+//! A [`Pacer`] decides how many frames (1/60th of a second of emulated time, see
+//! [`Chip8::run_frame`]) to run each time the host asks, according to the [`Speed`]:
+//!
+//! * `Scaled` keeps emulated time in step with real time, multiplied by a factor, e.g. `2x` to
+//!   fast forward through slow sections
+//! * `Uncapped` runs as many frames as the host can manage
+//!
+//! Either way, the ratio of instructions to timer ticks within a frame doesn't change, so
+//! programs behave the same, just faster. The number of frames run per second of real time is
+//! measured, as a readout for the frontend.
+//!
+//! [`Pacer`]: struct.Pacer.html
+//! [`Speed`]: enum.Speed.html
+//! [`Chip8::run_frame`]: ../struct.Chip8.html#method.run_frame
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use super::{error::Result, Chip8};
+
+/// Emulated frames per second at normal speed
+const FRAMES_PER_SECOND: f64 = 60.0;
+/// Real time an uncapped `Pacer` runs frames for before handing back to the host
+const UNCAPPED_BUDGET: Duration = Duration::from_millis(16);
+/// Most frames of real time a scaled `Pacer` catches up on at once, e.g. after a stall
+const MAX_CATCH_UP: f64 = 8.0;
+/// Real time the frame rate is averaged over
+const FPS_WINDOW: Duration = Duration::from_millis(500);
+
+/// How fast emulated time passes
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Speed {
+    /// Real time, multiplied by a factor
+    Scaled(f32),
+    /// As fast as the host allows
+    Uncapped,
+}
+
+impl Speed {
+    /// Real time
+    pub const NORMAL: Self = Self::Scaled(1.0);
+}
+
+impl Default for Speed {
+    fn default() -> Self {
+        Self::NORMAL
+    }
+}
+
+impl FromStr for Speed {
+    type Err = String;
+
+    /// Parse a multiplier (`2` or `2x`), or `uncapped`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("uncapped") {
+            return Ok(Self::Uncapped);
+        }
+
+        let factor = s.strip_suffix('x').unwrap_or(s);
+        match factor.parse::<f32>() {
+            Ok(factor) if factor > 0.0 && factor.is_finite() => Ok(Self::Scaled(factor)),
+            _ => Err(format!(
+                "invalid speed `{}`, expected a multiplier or `uncapped`",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Scaled(factor) => write!(f, "{}x", factor),
+            Self::Uncapped => write!(f, "uncapped"),
+        }
+    }
+}
+
+/// Runs frames of a [`Chip8`] in step with real time
+///
+/// [`Chip8`]: ../struct.Chip8.html
+#[derive(Debug)]
+pub struct Pacer {
+    speed: Speed,
+    /// Speed to return to when leaving uncapped mode
+    scaled: Speed,
+    /// When frames were last run
+    last: Option<Instant>,
+    /// Fraction of a frame owed from the last call
+    carry: f64,
+    /// Frames run since the start of the current measuring window
+    window_frames: u32,
+    /// Start of the current measuring window
+    window_start: Option<Instant>,
+    /// Frames per second measured over the last window
+    fps: f32,
+    /// Frames run in total
+    frames: u64,
+    /// Total number of frames to stop after
+    limit: Option<u64>,
+}
+
+impl Pacer {
+    /// Create a `Pacer` running at `speed`
+    pub fn new(speed: Speed) -> Self {
+        Self {
+            speed,
+            scaled: match speed {
+                Speed::Uncapped => Speed::NORMAL,
+                scaled => scaled,
+            },
+            last: None,
+            carry: 0.0,
+            window_frames: 0,
+            window_start: None,
+            fps: 0.0,
+            frames: 0,
+            limit: None,
+        }
+    }
+
+    /// Get the current speed
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    /// Change the speed, taking effect from the next call to [`run`]
+    ///
+    /// [`run`]: #method.run
+    pub fn set_speed(&mut self, speed: Speed) {
+        if let Speed::Scaled(_) = speed {
+            self.scaled = speed;
+        }
+        self.speed = speed;
+        self.carry = 0.0;
+    }
+
+    /// Switch between uncapped and the last scaled speed, e.g. while a turbo key is held
+    pub fn toggle_uncapped(&mut self) {
+        let speed = match self.speed {
+            Speed::Uncapped => self.scaled,
+            Speed::Scaled(_) => Speed::Uncapped,
+        };
+        self.set_speed(speed);
+    }
+
+    /// Emulated frames run per second of real time, over the last half second
+    pub fn fps(&self) -> f32 {
+        self.fps
+    }
+
+    /// Number of frames run in total
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Stop after `frames` frames in total, e.g. for test runs of a fixed length
+    pub fn set_limit(&mut self, frames: Option<u64>) {
+        self.limit = frames;
+    }
+
+    /// Whether the frame limit has been reached
+    pub fn finished(&self) -> bool {
+        matches!(self.limit, Some(limit) if self.frames >= limit)
+    }
+
+    /// Run the frames due at `now`, returning how many were run
+    ///
+    /// The first call only starts the clock. Uncapped, frames are run until a host frame's worth
+    /// of real time has passed, so the host can still redraw and poll input.
+    pub fn run(&mut self, chip8: &mut Chip8, now: Instant) -> Result<u32> {
+        self.run_with(chip8, now, |_| Ok(()))
+    }
+
+    /// Like [`run`], calling `frame` after each frame, e.g. to generate its sound
+    ///
+    /// [`run`]: #method.run
+    pub fn run_with(
+        &mut self,
+        chip8: &mut Chip8,
+        now: Instant,
+        mut frame: impl FnMut(&Chip8) -> Result<()>,
+    ) -> Result<u32> {
+        let last = self.last.replace(now).unwrap_or(now);
+        self.window_start.get_or_insert(now);
+        let remaining = self
+            .limit
+            .map_or(u64::MAX, |limit| limit.saturating_sub(self.frames));
+
+        let frames = match self.speed {
+            Speed::Scaled(factor) => {
+                let elapsed = now.saturating_duration_since(last).as_secs_f64();
+                let due = elapsed * FRAMES_PER_SECOND * factor as f64 + self.carry;
+                // after a stall, drop the frames that can't be caught up on
+                let max = MAX_CATCH_UP * (factor as f64).max(1.0);
+                let due = due.min(max);
+                let frames = due.floor();
+                self.carry = due - frames;
+
+                let frames = (frames as u64).min(remaining) as u32;
+                for _ in 0..frames {
+                    chip8.run_frame()?;
+                    frame(chip8)?;
+                }
+                frames
+            }
+            Speed::Uncapped => {
+                let mut frames = 0;
+                while (frames as u64) < remaining
+                    && (frames == 0 || now.elapsed() < UNCAPPED_BUDGET)
+                {
+                    chip8.run_frame()?;
+                    frame(chip8)?;
+                    frames += 1;
+                }
+                frames
+            }
+        };
+
+        self.frames += frames as u64;
+        self.window_frames += frames;
+        self.measure(now);
+        Ok(frames)
+    }
+
+    /// Update the frame rate once a measuring window has passed
+    fn measure(&mut self, now: Instant) {
+        let start = match self.window_start {
+            Some(start) => start,
+            None => return,
+        };
+        let elapsed = now.saturating_duration_since(start);
+        if elapsed >= FPS_WINDOW {
+            self.fps = self.window_frames as f32 / elapsed.as_secs_f32();
+            self.window_frames = 0;
+            self.window_start = Some(now);
+        }
+    }
+}
+
+impl Default for Pacer {
+    fn default() -> Self {
+        Self::new(Speed::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Emulator;
+
+    #[test]
+    fn parse_speed() {
+        assert_eq!("2x".parse(), Ok(Speed::Scaled(2.0)));
+        assert_eq!("0.5".parse(), Ok(Speed::Scaled(0.5)));
+        assert_eq!("Uncapped".parse(), Ok(Speed::Uncapped));
+        assert!("0".parse::<Speed>().is_err());
+        assert!("fast".parse::<Speed>().is_err());
+    }
+
+    #[test]
+    fn scaled_frames() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x12, 0x00]).unwrap();
+        let mut pacer = Pacer::new(Speed::Scaled(2.0));
+
+        let start = Instant::now();
+        assert_eq!(pacer.run(&mut chip8, start).unwrap(), 0);
+        let now = start + Duration::from_millis(100);
+        assert_eq!(pacer.run(&mut chip8, now).unwrap(), 12);
+        // a long stall only catches up on a few frames
+        let now = now + Duration::from_secs(10);
+        assert_eq!(pacer.run(&mut chip8, now).unwrap(), 16);
+        assert_eq!(pacer.frames(), 28);
+        assert!(pacer.fps() > 0.0);
+
+        pacer.toggle_uncapped();
+        pacer.set_limit(Some(30));
+        assert_eq!(pacer.run(&mut chip8, Instant::now()).unwrap(), 2);
+        assert!(pacer.finished());
+        pacer.toggle_uncapped();
+        assert_eq!(pacer.speed(), Speed::Scaled(2.0));
+    }
+
+    #[test]
+    fn catch_up_cap() {
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&[0x12, 0x00]).unwrap();
+
+        // slower than real time, still up to 8 frames are caught up on
+        let mut pacer = Pacer::new(Speed::Scaled(0.5));
+        let start = Instant::now();
+        pacer.run(&mut chip8, start).unwrap();
+        let now = start + Duration::from_secs(1);
+        assert_eq!(pacer.run(&mut chip8, now).unwrap(), 8);
+        // the frames dropped aren't owed later
+        let now = now + Duration::from_millis(100);
+        assert_eq!(pacer.run(&mut chip8, now).unwrap(), 3);
+
+        // faster than real time, the cap scales with the speed
+        pacer.set_speed(Speed::Scaled(4.0));
+        let now = now + Duration::from_secs(1);
+        assert_eq!(pacer.run(&mut chip8, now).unwrap(), 32);
+    }
+}
@@ -0,0 +1,344 @@
+//! Instruction profiler.
+//!
+//! While profiling is enabled (see [`Chip8::set_profiling`]), every executed instruction is
+//! counted by address and by mnemonic, along with the subroutines on the call stack at the time.
+//! Subroutines are followed through `CALL`/`RET` pairs and named after their label if there are
+//! [`Symbols`], otherwise their address, e.g. `sub_2A0`, with the code outside of any subroutine
+//! named `main`.
+//!
+//! The [`Profile`] can be printed as a report of the hot spots, or written as folded stacks, one
+//! line per distinct call stack with its instruction count, which flamegraph tools read:
+//!
+//! ```text
+//! main;sub_2A0;sub_300 1234
+//! ```
+//!
+//! Estimated COSMAC VIP machine cycles are reported next to instruction counts, as the cost on
+//! slow interpreters is what matters when optimising for them.
+//!
+//! [`Chip8::set_profiling`]: ../struct.Chip8.html#method.set_profiling
+//! [`Profile`]: struct.Profile.html
+//! [`Symbols`]: ../symbols/struct.Symbols.html
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::Write;
+
+use super::{error::Result, instruction::Instruction, symbols::Symbols, timing};
+
+/// Number of addresses listed in the report
+const TOP_ADDRESSES: usize = 20;
+
+/// Execution counts of a single address
+#[derive(Debug, Copy, Clone)]
+pub struct AddrStats {
+    /// Times the instruction at the address was executed
+    pub count: u64,
+    /// Estimated VIP machine cycles spent on it
+    pub cycles: u64,
+    /// Instruction last executed at the address
+    pub inst: Instruction,
+}
+
+/// Time spent in a single subroutine
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct RoutineStats {
+    /// Times the subroutine was called
+    pub calls: u64,
+    /// Instructions executed in the subroutine and the subroutines it called
+    pub total: u64,
+    /// Instructions executed in the subroutine itself
+    pub own: u64,
+}
+
+/// Report of the hot spots of a [`Profile`], naming addresses by their symbols
+///
+/// [`Profile`]: struct.Profile.html
+#[derive(Debug, Copy, Clone)]
+pub struct Report<'a> {
+    profile: &'a Profile,
+    symbols: Option<&'a Symbols>,
+}
+
+/// Execution counts gathered while profiling
+#[derive(Debug, Default)]
+pub struct Profile {
+    /// Instructions executed in total
+    total: u64,
+    /// Counts by address
+    addrs: BTreeMap<u16, AddrStats>,
+    /// Counts by mnemonic
+    mnemonics: HashMap<&'static str, u64>,
+    /// Calls of each subroutine, by address
+    calls: HashMap<u16, u64>,
+    /// Entry addresses of the subroutines being executed
+    stack: Vec<u16>,
+    /// Instructions executed with each call stack
+    folded: HashMap<Vec<u16>, u64>,
+}
+
+impl Profile {
+    /// Create a new, empty `Profile`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count `inst`, executed at `addr`
+    ///
+    /// Called once the instruction executed successfully. A `CALL` counts towards the caller and
+    /// a `RET` towards the subroutine returning.
+    pub fn record(&mut self, addr: u16, inst: Instruction) {
+        let cycles = timing::vip_cycles(&inst) as u64;
+        self.total += 1;
+
+        let stats = self.addrs.entry(addr).or_insert(AddrStats {
+            count: 0,
+            cycles: 0,
+            inst,
+        });
+        stats.count += 1;
+        stats.cycles += cycles;
+        stats.inst = inst;
+
+        *self.mnemonics.entry(inst.name()).or_insert(0) += 1;
+        match self.folded.get_mut(&self.stack) {
+            Some(count) => *count += 1,
+            None => {
+                self.folded.insert(self.stack.clone(), 1);
+            }
+        }
+
+        match inst.opcode().to_match_tuple() {
+            (0x2, ..) => {
+                let sub = inst.opcode().addr();
+                *self.calls.entry(sub).or_insert(0) += 1;
+                self.stack.push(sub);
+            }
+            (0x0, 0x0, 0xE, 0xE) => {
+                self.stack.pop();
+            }
+            _ => (),
+        }
+    }
+
+    /// Instructions executed in total
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Counts of every executed address
+    pub fn addrs(&self) -> &BTreeMap<u16, AddrStats> {
+        &self.addrs
+    }
+
+    /// Instructions executed by mnemonic, most executed first
+    pub fn mnemonics(&self) -> Vec<(&'static str, u64)> {
+        let mut mnemonics: Vec<_> = self.mnemonics.iter().map(|(m, c)| (*m, *c)).collect();
+        mnemonics.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        mnemonics
+    }
+
+    /// Time spent in each subroutine, by entry address
+    ///
+    /// Recursive calls are only counted once towards the total of the subroutine.
+    pub fn routines(&self) -> BTreeMap<u16, RoutineStats> {
+        let mut routines = BTreeMap::new();
+        for (stack, count) in &self.folded {
+            for (depth, addr) in stack.iter().enumerate() {
+                if stack[..depth].contains(addr) {
+                    continue;
+                }
+                routines
+                    .entry(*addr)
+                    .or_insert_with(RoutineStats::default)
+                    .total += count;
+            }
+            if let Some(addr) = stack.last() {
+                routines.get_mut(addr).unwrap().own += count;
+            }
+        }
+        for (addr, stats) in routines.iter_mut() {
+            stats.calls = self.calls.get(addr).copied().unwrap_or(0);
+        }
+        routines
+    }
+
+    /// Report of the hot spots, with addresses named by `symbols`
+    ///
+    /// Displaying the `Profile` itself gives the report without symbols.
+    pub fn report<'a>(&'a self, symbols: Option<&'a Symbols>) -> Report<'a> {
+        Report {
+            profile: self,
+            symbols,
+        }
+    }
+
+    /// Write the call stacks in folded format, sorted by stack, with subroutines named by
+    /// `symbols`
+    pub fn write_folded(&self, symbols: Option<&Symbols>, mut writer: impl Write) -> Result<()> {
+        let mut stacks: Vec<_> = self.folded.iter().collect();
+        stacks.sort_unstable();
+        for (stack, count) in stacks {
+            let mut line = String::from("main");
+            for addr in stack {
+                line.push_str(&format!(";{}", routine_name(*addr, symbols)));
+            }
+            writeln!(writer, "{} {}", line, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Name of the subroutine at `addr`, its label if it has one
+fn routine_name(addr: u16, symbols: Option<&Symbols>) -> String {
+    match symbols.and_then(|symbols| symbols.label(addr)) {
+        Some(label) => label.to_string(),
+        None => format!("sub_{:03X}", addr),
+    }
+}
+
+/// Share of `total` that `count` makes up, in percent
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.report(None).fmt(f)
+    }
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { profile, symbols } = *self;
+        writeln!(f, "Instructions executed: {}", profile.total)?;
+
+        let mut addrs: Vec<_> = profile.addrs.iter().collect();
+        addrs.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(b.0)));
+        writeln!(f, "Hottest addresses:")?;
+        for (addr, stats) in addrs.into_iter().take(TOP_ADDRESSES) {
+            write!(f, "    {:03X}  ", addr)?;
+            if let Some(symbols) = symbols {
+                write!(f, "{:<24}", symbols.describe(*addr))?;
+            }
+            writeln!(
+                f,
+                "{:>10} {:>6.2}%  {:>10} cycles  {}",
+                stats.count,
+                percent(stats.count, profile.total),
+                stats.cycles,
+                stats.inst.disassemble_with(symbols)
+            )?;
+        }
+
+        writeln!(f, "By mnemonic:")?;
+        for (mnemonic, count) in profile.mnemonics() {
+            writeln!(
+                f,
+                "    {:<6} {:>10} {:>6.2}%",
+                mnemonic,
+                count,
+                percent(count, profile.total)
+            )?;
+        }
+
+        let mut routines: Vec<_> = profile.routines().into_iter().collect();
+        if !routines.is_empty() {
+            routines.sort_by(|a, b| b.1.total.cmp(&a.1.total).then(a.0.cmp(&b.0)));
+            writeln!(f, "Subroutines (calls, total, self):")?;
+            for (addr, stats) in routines {
+                writeln!(
+                    f,
+                    "    {:<8} {:>8} {:>10} {:>6.2}% {:>10} {:>6.2}%",
+                    routine_name(addr, symbols),
+                    stats.calls,
+                    stats.total,
+                    percent(stats.total, profile.total),
+                    stats.own,
+                    percent(stats.own, profile.total)
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chip8, Emulator};
+
+    #[test]
+    fn call_stacks() {
+        let rom = [
+            0x22, 0x06, // 200: CALL 206
+            0x22, 0x0A, // 202: CALL 20A
+            0x12, 0x04, // 204: JP 204
+            0x22, 0x0A, // 206: CALL 20A
+            0x00, 0xEE, // 208: RET
+            0x70, 0x01, // 20A: ADD V0, 01
+            0x00, 0xEE, // 20C: RET
+        ];
+        let mut chip8 = Chip8::new();
+        chip8.set_profiling(true);
+        chip8.load_rom_bytes(&rom).unwrap();
+        for _ in 0..10 {
+            chip8.step().unwrap();
+        }
+
+        let profile = chip8.profile().unwrap();
+        assert_eq!(profile.total(), 10);
+        assert_eq!(profile.addrs()[&0x204].count, 2);
+        assert_eq!(profile.mnemonics()[0], ("CALL", 3));
+
+        let routines = profile.routines();
+        assert_eq!(
+            routines[&0x20A],
+            RoutineStats {
+                calls: 2,
+                total: 4,
+                own: 4
+            }
+        );
+        assert_eq!(
+            routines[&0x206],
+            RoutineStats {
+                calls: 1,
+                total: 4,
+                own: 2
+            }
+        );
+
+        let mut folded = Vec::new();
+        profile.write_folded(None, &mut folded).unwrap();
+        assert_eq!(
+            String::from_utf8(folded).unwrap(),
+            "main 4\nmain;sub_206 2\nmain;sub_206;sub_20A 2\nmain;sub_20A 2\n"
+        );
+
+        let mut symbols = Symbols::new();
+        symbols.insert_label(0x20A, "add_one");
+        let mut folded = Vec::new();
+        profile.write_folded(Some(&symbols), &mut folded).unwrap();
+        assert!(String::from_utf8(folded)
+            .unwrap()
+            .contains("main;sub_206;add_one 2\n"));
+        let report = profile.report(Some(&symbols)).to_string();
+        assert!(report.contains("add_one+0x2"));
+    }
+
+    #[test]
+    fn faults_not_counted() {
+        let mut chip8 = Chip8::new();
+        chip8.set_profiling(true);
+        // 00EE: RET with nothing to return to
+        chip8.load_rom_bytes(&[0x00, 0xEE]).unwrap();
+        assert!(chip8.step().is_err());
+        assert_eq!(chip8.profile().unwrap().total(), 0);
+    }
+}
@@ -0,0 +1,131 @@
+//! Chip-8 interpreter quirks.
+//!
+//! The various Chip-8 interpreters disagree on the behaviour of a handful of instructions, and
+//! ROMs tend to rely on the behaviour of the interpreter they were written for. `Quirks` selects
+//! which behaviour is used.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Behavioural differences between Chip-8 interpreters
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Quirks {
+    /// `8xy6`/`8xyE` shift `Vy` and store the result in `Vx`, instead of shifting `Vx` in place
+    pub shift_uses_vy: bool,
+    /// `Fx55`/`Fx65` leave `I` incremented past the last register stored or loaded
+    pub load_store_increments_i: bool,
+    /// `Bnnn` jumps to `nnn + Vx`, where `x` is the highest nibble of `nnn`, instead of `V0`
+    pub jump_uses_vx: bool,
+    /// `8xy1`/`8xy2`/`8xy3` reset `VF` to 0
+    pub vf_reset: bool,
+    /// Sprites drawn past the edge of the display are clipped instead of wrapping around
+    pub clip_sprites: bool,
+    /// `Dxyn` waits for the next display interrupt before drawing, ending the frame. VIP timing
+    /// always waits
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    /// Behaviour of the original COSMAC VIP interpreter
+    pub const fn chip8() -> Self {
+        Self {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            vf_reset: true,
+            clip_sprites: true,
+            display_wait: true,
+        }
+    }
+
+    /// Behaviour modern Chip-8 interpreters settled on, which many newer ROMs expect
+    pub const fn modern() -> Self {
+        Self {
+            shift_uses_vy: false,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+        }
+    }
+
+    /// Behaviour of the Super Chip-48 interpreter
+    pub const fn schip() -> Self {
+        Self {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+        }
+    }
+
+    /// Behaviour of the XO-CHIP interpreter (Octo)
+    pub const fn xochip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::chip8()
+    }
+}
+
+/// Chip-8 variants a ROM may be written for
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Platform {
+    /// The original COSMAC VIP Chip-8
+    #[default]
+    Chip8,
+    /// Super Chip-48
+    Schip,
+    /// XO-CHIP
+    XoChip,
+}
+
+impl Platform {
+    /// Default quirks for the platform
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Self::Chip8 => Quirks::chip8(),
+            Self::Schip => Quirks::schip(),
+            Self::XoChip => Quirks::xochip(),
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "chip8" | "chip-8" => Ok(Self::Chip8),
+            "schip" | "superchip" | "super-chip" => Ok(Self::Schip),
+            "xochip" | "xo-chip" => Ok(Self::XoChip),
+            _ => Err(format!(
+                "unknown platform `{}`, expected one of chip8, schip, xochip",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Chip8 => write!(f, "CHIP-8"),
+            Self::Schip => write!(f, "SCHIP"),
+            Self::XoChip => write!(f, "XO-CHIP"),
+        }
+    }
+}
@@ -28,8 +28,10 @@ use crate::types::Nibble;
 
 /// Memory address for program (ROM) start.
 pub const PROGRAM_START: u16 = 0x200;
+/// Memory address for program (ROM) start on the ETI 660.
+pub const ETI_660_PROGRAM_START: u16 = 0x600;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[repr(transparent)]
 pub struct Regs([u8; Self::NUM_GP_REGS]);
 
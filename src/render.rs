@@ -0,0 +1,262 @@
+//! Rendering of the Chip-8 display to RGB images.
+//!
+//! Pixels of the [`Display`] are coloured using a [`Palette`] of four colours, one for each
+//! combination of the two XO-CHIP bit planes. Displays with a single plane only use the first
+//! two, the background and foreground.
+//!
+//! [`Display`]: ../display/struct.Display.html
+//! [`Palette`]: struct.Palette.html
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::display::Display;
+
+/// A colour, as red, green and blue
+pub type Rgb = [u8; 3];
+
+/// Default number of screen pixels per Chip-8 pixel, in each direction
+pub const DEFAULT_SCALE: usize = 10;
+
+/// Colours used to draw the display
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Palette {
+    /// Colours by pixel value: background, plane 1, plane 2, and both planes
+    pub colors: [Rgb; 4],
+}
+
+/// Built-in palettes, by name
+pub const THEMES: &[(&str, Palette)] = &[
+    (
+        "classic",
+        Palette::new([0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF]),
+    ),
+    (
+        "inverted",
+        Palette::new([0xFF, 0xFF, 0xFF], [0x00, 0x00, 0x00]),
+    ),
+    (
+        "amber",
+        Palette::new([0x1A, 0x10, 0x00], [0xFF, 0xB0, 0x00]),
+    ),
+    (
+        "phosphor",
+        Palette::new([0x00, 0x1A, 0x00], [0x33, 0xFF, 0x33]),
+    ),
+    (
+        "lcd",
+        Palette::with_planes([
+            [0x9B, 0xBC, 0x0F],
+            [0x0F, 0x38, 0x0F],
+            [0x8B, 0xAC, 0x0F],
+            [0x30, 0x62, 0x30],
+        ]),
+    ),
+    (
+        "octo",
+        Palette::with_planes([
+            [0x99, 0x66, 0x00],
+            [0xFF, 0xCC, 0x00],
+            [0xFF, 0x66, 0x00],
+            [0x66, 0x22, 0x00],
+        ]),
+    ),
+    // yellow on black is the most legible combination for many users with low vision
+    (
+        "high-contrast",
+        Palette::new([0x00, 0x00, 0x00], [0xFF, 0xFF, 0x00]),
+    ),
+];
+
+impl Palette {
+    /// Create a two colour `Palette`, both planes are drawn in `foreground`
+    pub const fn new(background: Rgb, foreground: Rgb) -> Self {
+        Self {
+            colors: [background, foreground, foreground, foreground],
+        }
+    }
+
+    /// Create a four colour `Palette` for XO-CHIP, by pixel value
+    pub const fn with_planes(colors: [Rgb; 4]) -> Self {
+        Self { colors }
+    }
+
+    /// Look up a built-in palette by name
+    pub fn theme(name: &str) -> Option<Self> {
+        THEMES
+            .iter()
+            .find(|(theme, _)| theme.eq_ignore_ascii_case(name))
+            .map(|(_, palette)| *palette)
+    }
+
+    /// Create a `Palette` from two or four colours, as `#RRGGBB` strings
+    pub fn from_hex<S: AsRef<str>>(colors: &[S]) -> Result<Self, String> {
+        let colors = colors
+            .iter()
+            .map(|c| parse_rgb(c.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match colors[..] {
+            [background, foreground] => Ok(Self::new(background, foreground)),
+            [a, b, c, d] => Ok(Self::with_planes([a, b, c, d])),
+            _ => Err(format!(
+                "expected 2 or 4 colours in a palette, got {}",
+                colors.len()
+            )),
+        }
+    }
+
+    /// Colour of pixels that are off
+    pub fn background(&self) -> Rgb {
+        self.colors[0]
+    }
+
+    /// Colour of pixels that are on
+    pub fn foreground(&self) -> Rgb {
+        self.colors[1]
+    }
+
+    /// Get the colour for a pixel value of the [`Display`]
+    ///
+    /// [`Display`]: ../display/struct.Display.html
+    pub fn color(&self, pixel: u8) -> Rgb {
+        self.colors[(pixel & 0x3) as usize]
+    }
+
+    /// Colours of the palette, in pixel value order
+    pub fn colors(&self) -> Vec<Rgb> {
+        self.colors.to_vec()
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        THEMES[0].1
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    /// Parse either the name of a built-in theme, or a comma separated list of colours
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(palette) = Self::theme(s) {
+            return Ok(palette);
+        }
+        if !s.contains(',') {
+            let themes: Vec<_> = THEMES.iter().map(|(name, _)| *name).collect();
+            return Err(format!(
+                "unknown palette `{}`, expected a list of colours or one of {}",
+                s,
+                themes.join(", ")
+            ));
+        }
+
+        let colors: Vec<_> = s.split(',').map(str::trim).collect();
+        Self::from_hex(&colors)
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let colors: Vec<_> = self
+            .colors
+            .iter()
+            .map(|[r, g, b]| format!("#{:02X}{:02X}{:02X}", r, g, b))
+            .collect();
+        write!(f, "{}", colors.join(","))
+    }
+}
+
+/// Parse a colour given as `#RRGGBB` or `RRGGBB`
+pub fn parse_rgb(s: &str) -> Result<Rgb, String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(format!("invalid colour `{}`, expected `#RRGGBB`", s));
+    }
+
+    let mut rgb = [0; 3];
+    for (i, c) in rgb.iter_mut().enumerate() {
+        *c = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|_| format!("invalid colour `{}`, expected `#RRGGBB`", s))?;
+    }
+    Ok(rgb)
+}
+
+/// An RGB image of the display
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Image {
+    /// Width of the image, in pixels
+    pub width: usize,
+    /// Height of the image, in pixels
+    pub height: usize,
+    /// Three bytes per pixel, row by row
+    pub rgb: Vec<u8>,
+}
+
+/// Scale each pixel of `display` up to a `scale`x`scale` square, without colouring it
+///
+/// Returns the width and height of the scaled image with its pixel values, row by row.
+pub fn scale(display: &Display, scale: usize) -> (usize, usize, Vec<u8>) {
+    let scale = scale.max(1);
+    let (width, height) = (display.width() * scale, display.height() * scale);
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in display.pixels().chunks(display.width()) {
+        let line: Vec<u8> = row
+            .iter()
+            .flat_map(|p| std::iter::repeat_n(*p, scale))
+            .collect();
+        for _ in 0..scale {
+            pixels.extend_from_slice(&line);
+        }
+    }
+
+    (width, height, pixels)
+}
+
+/// Render `display` as an RGB image, with every pixel scaled up to a `scale`x`scale` square
+pub fn render(display: &Display, palette: &Palette, scale: usize) -> Image {
+    let (width, height, pixels) = self::scale(display, scale);
+    let rgb = pixels.iter().flat_map(|p| palette.color(*p)).collect();
+
+    Image { width, height, rgb }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_palette() {
+        assert_eq!(
+            "Amber".parse::<Palette>(),
+            Ok(Palette::theme("amber").unwrap())
+        );
+        assert_eq!(
+            "#000000, 00ff00".parse::<Palette>(),
+            Ok(Palette::new([0, 0, 0], [0, 0xFF, 0]))
+        );
+        assert!("#000000,#111111,#222222".parse::<Palette>().is_err());
+        assert!("#00000G,#111111".parse::<Palette>().is_err());
+        assert!("nope".parse::<Palette>().is_err());
+
+        let palette = Palette::theme("octo").unwrap();
+        assert_eq!(palette.to_string().parse::<Palette>(), Ok(palette));
+    }
+
+    #[test]
+    fn render_scaled() {
+        let mut display = Display::new();
+        display.draw_sprite(0, 0, &[0x80], false);
+        let palette = Palette::new([1, 2, 3], [4, 5, 6]);
+
+        let image = render(&display, &palette, 2);
+        assert_eq!((image.width, image.height), (128, 64));
+        assert_eq!(&image.rgb[..9], &[4, 5, 6, 4, 5, 6, 1, 2, 3]);
+        assert_eq!(
+            &image.rgb[128 * 3..128 * 3 + 9],
+            &[4, 5, 6, 4, 5, 6, 1, 2, 3]
+        );
+    }
+}
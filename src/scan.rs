@@ -0,0 +1,322 @@
+//! Static ROM scanning.
+//!
+//! Disassembles a ROM by following its control flow from the entry point, then uses the
+//! instructions found to guess the platform it was written for, and which [`Quirks`] may change
+//! how it behaves. Following control flow rather than decoding every word keeps sprite data from
+//! being mistaken for instructions.
+//!
+//! [`Quirks`]: ../quirks/struct.Quirks.html
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::{
+    opcode::OpCode,
+    optable,
+    quirks::{Platform, Quirks},
+};
+
+/// Number of instructions to look ahead for uses of a register or `I`
+const LOOKAHEAD: u16 = 8;
+
+/// Result of scanning a ROM
+#[derive(Debug)]
+pub struct Analysis {
+    /// Addresses of the reachable instructions
+    pub reachable: BTreeSet<u16>,
+    /// Extension instructions used, by pattern (e.g. `00FF`), with the number of uses
+    pub extensions: BTreeMap<&'static str, usize>,
+    /// Platform the ROM was most likely written for
+    pub platform: Platform,
+    /// Recommended quirks
+    pub quirks: Quirks,
+    /// Quirks the ROM is sensitive to, i.e. which may change how it behaves
+    pub sensitive: Vec<&'static str>,
+}
+
+/// Scan `rom`, loaded at and starting execution from `load_addr`
+pub fn scan(rom: &[u8], load_addr: u16) -> Analysis {
+    let rom = Rom {
+        bytes: rom,
+        load_addr,
+    };
+    let reachable = rom.trace();
+
+    let mut extensions = BTreeMap::new();
+    let mut platform = Platform::Chip8;
+    for addr in &reachable {
+        if let Some((pattern, ext)) = extension(rom.opcode(*addr)) {
+            *extensions.entry(pattern).or_insert(0) += 1;
+            if ext == Platform::XoChip || platform == Platform::Chip8 {
+                platform = ext;
+            }
+        }
+    }
+
+    let mut quirks = platform.quirks();
+    let mut sensitive = Vec::new();
+
+    // shifts naming two different registers depend on which one is shifted, programs written
+    // for the in-place shift usually left `y` as 0
+    let shifts: Vec<_> = reachable
+        .iter()
+        .map(|addr| rom.opcode(*addr).to_match_tuple())
+        .filter(|(op, x, y, n)| *op == 0x8 && (*n == 0x6 || *n == 0xE) && x != y)
+        .collect();
+    if !shifts.is_empty() {
+        sensitive.push("shift_uses_vy");
+        quirks.shift_uses_vy = !shifts.iter().all(|(_, _, y, _)| *y == 0);
+    }
+
+    let uses_i_after = |addr: u16| rom.uses_after(&reachable, addr, reads_i, sets_i);
+    if reachable
+        .iter()
+        .filter(|addr| {
+            matches!(
+                rom.opcode(**addr).to_match_tuple(),
+                (0xF, _, 0x5..=0x6, 0x5)
+            )
+        })
+        .any(|addr| uses_i_after(*addr))
+    {
+        sensitive.push("load_store_increments_i");
+    }
+
+    if reachable
+        .iter()
+        .any(|addr| rom.opcode(*addr).to_match_tuple().0 == 0xB)
+    {
+        sensitive.push("jump_uses_vx");
+    }
+
+    let reads_vf_after = |addr: u16| rom.uses_after(&reachable, addr, reads_vf, |_| false);
+    if reachable
+        .iter()
+        .filter(|addr| matches!(rom.opcode(**addr).to_match_tuple(), (0x8, _, _, 0x1..=0x3)))
+        .any(|addr| reads_vf_after(*addr))
+    {
+        sensitive.push("vf_reset");
+    }
+
+    Analysis {
+        reachable,
+        extensions,
+        platform,
+        quirks,
+        sensitive,
+    }
+}
+
+/// ROM image being scanned
+struct Rom<'a> {
+    bytes: &'a [u8],
+    load_addr: u16,
+}
+
+impl Rom<'_> {
+    /// Whether a whole instruction at `addr` lies inside the ROM
+    fn contains(&self, addr: u16) -> bool {
+        let end = self.load_addr as usize + self.bytes.len();
+        addr >= self.load_addr && (addr as usize) + 2 <= end
+    }
+
+    fn opcode(&self, addr: u16) -> OpCode {
+        let idx = (addr - self.load_addr) as usize;
+        OpCode::from((self.bytes[idx], self.bytes[idx + 1]))
+    }
+
+    /// Follow control flow from the load address, returning the addresses of the instructions
+    fn trace(&self) -> BTreeSet<u16> {
+        let mut reachable = BTreeSet::new();
+        let mut pending = vec![self.load_addr];
+
+        while let Some(mut addr) = pending.pop() {
+            while self.contains(addr) && reachable.insert(addr) {
+                let opcode = self.opcode(addr);
+                // an instruction at the very top of the address space has nothing after it
+                let next = match addr.checked_add(self.len(addr)) {
+                    Some(next) => next,
+                    None => break,
+                };
+                match opcode.to_match_tuple() {
+                    // return, exit, or an instruction we can't decode (probably data)
+                    (0x0, 0x0, 0xE, 0xE) | (0x0, 0x0, 0xF, 0xD) => break,
+                    _ if optable::lookup(opcode).is_none() => break,
+                    (0x1, ..) => {
+                        pending.push(opcode.addr());
+                        break;
+                    }
+                    (0x2, ..) => pending.push(opcode.addr()),
+                    // skips, which skip the whole of a following `F000 nnnn`
+                    (0x3, ..) | (0x4, ..) | (0x5, _, _, 0x0) | (0x9, _, _, 0x0) | (0xE, ..) => {
+                        let width = if self.contains(next) {
+                            self.len(next)
+                        } else {
+                            2
+                        };
+                        pending.extend(next.checked_add(width));
+                    }
+                    // the target depends on a register, so can't be followed statically
+                    (0xB, ..) => break,
+                    _ => (),
+                }
+                addr = next;
+            }
+        }
+
+        reachable
+    }
+
+    /// Length in bytes of the instruction at `addr`, 4 for `F000 nnnn`
+    fn len(&self, addr: u16) -> u16 {
+        optable::lookup(self.opcode(addr)).map_or(2, |def| def.len)
+    }
+
+    /// Whether an instruction shortly after `addr` satisfies `uses` before one satisfies `resets`
+    fn uses_after(
+        &self,
+        reachable: &BTreeSet<u16>,
+        addr: u16,
+        uses: impl Fn(OpCode) -> bool,
+        resets: impl Fn(OpCode) -> bool,
+    ) -> bool {
+        (1..=LOOKAHEAD)
+            .map_while(|i| addr.checked_add(2 * i))
+            .take_while(|addr| reachable.contains(addr))
+            .map(|addr| self.opcode(addr))
+            .take_while(|opcode| !resets(*opcode))
+            .any(uses)
+    }
+}
+
+/// Extension instruction pattern of `opcode`, and the platform that introduced it
+fn extension(opcode: OpCode) -> Option<(&'static str, Platform)> {
+    optable::lookup(opcode)
+        .filter(|def| def.is_extension())
+        .map(|def| (def.pattern, def.platforms[0]))
+}
+
+/// Whether `opcode` reads `I`
+fn reads_i(opcode: OpCode) -> bool {
+    matches!(
+        opcode.to_match_tuple(),
+        (0xD, ..) | (0xF, _, 0x1, 0xE) | (0xF, _, 0x3, 0x3) | (0xF, _, 0x5..=0x6, 0x5)
+    )
+}
+
+/// Whether `opcode` overwrites `I`
+fn sets_i(opcode: OpCode) -> bool {
+    matches!(
+        opcode.to_match_tuple(),
+        (0xA, ..) | (0xF, _, 0x2..=0x3, 0x0) | (0xF, _, 0x2, 0x9) | (0xF, 0x0, 0x0, 0x0)
+    )
+}
+
+/// Whether `opcode` reads `VF`
+fn reads_vf(opcode: OpCode) -> bool {
+    match opcode.to_match_tuple() {
+        (0x3, 0xF, ..) | (0x4, 0xF, ..) | (0x7, 0xF, ..) | (0xE, 0xF, ..) => true,
+        (0x5, x, y, _) | (0x8, x, y, _) | (0x9, x, y, _) => x == 0xF || y == 0xF,
+        (0xF, 0xF, ..) => true,
+        _ => false,
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Reachable instructions: {}", self.reachable.len())?;
+        writeln!(f, "Platform: {}", self.platform)?;
+
+        if !self.extensions.is_empty() {
+            writeln!(f, "Extension instructions:")?;
+            for (pattern, count) in &self.extensions {
+                writeln!(f, "    {:<10} x{}", pattern, count)?;
+            }
+        }
+
+        writeln!(f, "Recommended quirks:")?;
+        let quirks = [
+            ("shift_uses_vy", self.quirks.shift_uses_vy),
+            (
+                "load_store_increments_i",
+                self.quirks.load_store_increments_i,
+            ),
+            ("jump_uses_vx", self.quirks.jump_uses_vx),
+            ("vf_reset", self.quirks.vf_reset),
+            ("clip_sprites", self.quirks.clip_sprites),
+            ("display_wait", self.quirks.display_wait),
+        ];
+        for (name, enabled) in &quirks {
+            let note = if self.sensitive.contains(name) {
+                " (ROM is sensitive to this)"
+            } else {
+                ""
+            };
+            writeln!(f, "    {:<24} {}{}", name, enabled, note)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_control_flow() {
+        let rom = [
+            0x22, 0x06, // 200: CALL 206
+            0x12, 0x04, // 202: JP 204
+            0x12, 0x04, // 204: JP 204
+            0x00, 0xFF, // 206: HIGH
+            0x00, 0xEE, // 208: RET
+            0x00, 0xFE, // 20A: data, looks like LOW
+        ];
+        let analysis = scan(&rom, 0x200);
+
+        let reachable: Vec<_> = analysis.reachable.iter().copied().collect();
+        assert_eq!(reachable, vec![0x200, 0x202, 0x204, 0x206, 0x208]);
+        assert_eq!(analysis.platform, Platform::Schip);
+        assert_eq!(analysis.extensions.get("00FF"), Some(&1));
+        assert_eq!(analysis.extensions.get("00FE"), None);
+    }
+
+    #[test]
+    fn quirk_heuristics() {
+        let rom = [
+            0x81, 0x06, // 200: SHR V1 V0
+            0xF2, 0x55, // 202: LD [I], V2
+            0xF2, 0x65, // 204: LD V2, [I]
+            0x81, 0x21, // 206: OR V1 V2
+            0x3F, 0x00, // 208: SE VF 00
+            0xF0, 0x00, // 20A: LD I, long
+            0x02, 0x00, // 20C:     0200
+            0x12, 0x0E, // 20E: JP 20E
+        ];
+        let analysis = scan(&rom, 0x200);
+
+        assert_eq!(analysis.platform, Platform::XoChip);
+        assert!(!analysis.quirks.shift_uses_vy);
+        assert_eq!(
+            analysis.sensitive,
+            vec!["shift_uses_vy", "load_store_increments_i", "vf_reset"]
+        );
+        assert!(!analysis.reachable.contains(&0x20C));
+    }
+
+    #[test]
+    fn end_of_address_space() {
+        let rom = [
+            0x80, 0x16, // FFF8: SHR V0 V1
+            0x30, 0x00, // FFFA: SE V0 00
+            0xF0, 0x00, // FFFC: LD I, long
+            0x00, 0xE0, // FFFE:     00E0
+        ];
+        let analysis = scan(&rom, 0xFFF8);
+
+        let reachable: Vec<_> = analysis.reachable.iter().copied().collect();
+        assert_eq!(reachable, vec![0xFFF8, 0xFFFA, 0xFFFC]);
+        assert_eq!(scan(&rom[6..], 0xFFFE).reachable.len(), 1);
+    }
+}
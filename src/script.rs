@@ -0,0 +1,389 @@
+//! Scripting hooks.
+//!
+//! A [`Script`] drives a [`Chip8`] from a [Rhai](https://rhai.rs) script, so scenario tests and
+//! bots can be written without recompiling. The top level of the script runs once when it is
+//! loaded, after which these functions are called if the script defines them:
+//!
+//! * `on_frame()` - after each frame
+//! * `on_breakpoint(addr)` - before the instruction at a breakpoint executes
+//! * `on_instruction(addr)` - before every instruction executes, which slows emulation down a lot
+//!
+//! Functions can't see variables of the top level, state kept between calls lives in `this`,
+//! which starts out as an empty object map. The machine is inspected and changed with:
+//!
+//! * `reg(x)`, `set_reg(x, value)` - register `Vx`
+//! * `i()`, `set_i(value)`, `pc()`, `set_pc(addr)`, `dt()`, `set_dt(value)`, `st()`,
+//!   `set_st(value)` - the other registers
+//! * `peek(addr)`, `poke(addr, value)` - a byte of RAM
+//! * `pixel(x, y)`, `screen()` - a pixel of the display, or the whole display as text
+//! * `press(key)`, `release(key)`, `is_pressed(key)` - the keypad
+//! * `key_down(name)`, `key_up(name)` - a host key (e.g. `"W"`), pressing the keypad key the
+//!   [`Keymap`] maps it to
+//! * `frame()` - frames run so far
+//! * `break_at(addr)`, `clear_break(addr)` - set and clear breakpoints
+//! * `stop()` - end the run
+//!
+//! Output of `print()` is logged at the info level.
+//!
+//! For example, to hold down key 5 for a second and check where the game ends up:
+//!
+//! ```text
+//! press(5);
+//! break_at(0x2A6);
+//!
+//! fn on_frame() {
+//!     if frame() == 60 { release(5); }
+//!     if frame() == 600 { print(screen()); stop(); }
+//! }
+//!
+//! fn on_breakpoint(addr) {
+//!     this.hits = (this.hits ?? 0) + 1;
+//!     print(`draw_player called ${this.hits} times, V0 = ${reg(0)}`);
+//! }
+//! ```
+//!
+//! [`Script`]: struct.Script.html
+//! [`Chip8`]: ../struct.Chip8.html
+//! [`Keymap`]: ../keymap/struct.Keymap.html
+
+use std::cell::{Ref, RefCell};
+use std::collections::{BTreeSet, HashSet};
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST, INT};
+
+use super::{
+    error::{Chip8Error, Result},
+    keymap::Keymap,
+    memory::Ram,
+    Chip8,
+};
+
+/// Result of a function called by a script
+type ScriptResult<T> = std::result::Result<T, Box<EvalAltResult>>;
+
+/// State shared between the [`Script`] and the functions it registers
+///
+/// [`Script`]: struct.Script.html
+#[derive(Debug)]
+struct Host {
+    chip8: Chip8,
+    keymap: Keymap,
+    breakpoints: BTreeSet<u16>,
+    /// Frames run so far
+    frame: u64,
+    /// Set by `stop()`
+    stopped: bool,
+}
+
+/// A [`Chip8`] driven by a script, see the [module documentation](index.html)
+///
+/// [`Chip8`]: ../struct.Chip8.html
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    /// `this` of the hooks
+    this: Dynamic,
+    /// Names of the hooks the script defines
+    hooks: HashSet<String>,
+    host: Rc<RefCell<Host>>,
+    /// Address whose hooks already ran, so execution resumes from it
+    resume: Option<u16>,
+}
+
+impl Script {
+    /// Compile `source` and run its top level, driving `chip8`
+    pub fn new(source: &str, chip8: Chip8) -> Result<Self> {
+        Self::with_keymap(source, chip8, Keymap::default())
+    }
+
+    /// Like [`new`], with `keymap` translating the host keys of `key_down` and `key_up`
+    ///
+    /// [`new`]: #method.new
+    pub fn with_keymap(source: &str, chip8: Chip8, keymap: Keymap) -> Result<Self> {
+        let host = Rc::new(RefCell::new(Host {
+            chip8,
+            keymap,
+            breakpoints: BTreeSet::new(),
+            frame: 0,
+            stopped: false,
+        }));
+        let mut engine = Engine::new();
+        register(&mut engine, &host);
+        engine.on_print(|text| log::info!("{}", text));
+
+        let ast = engine
+            .compile(source)
+            .map_err(|e| Chip8Error::Script(e.to_string()))?;
+        let hooks = ast.iter_functions().map(|f| f.name.to_string()).collect();
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|e| Chip8Error::Script(e.to_string()))?;
+
+        Ok(Self {
+            engine,
+            ast,
+            scope,
+            this: Dynamic::from_map(Map::new()),
+            hooks,
+            host,
+            resume: None,
+        })
+    }
+
+    /// Read the script at `path` and run its top level, driving `chip8`
+    pub fn open(path: impl AsRef<Path>, chip8: Chip8) -> Result<Self> {
+        Self::new(&fs::read_to_string(path)?, chip8)
+    }
+
+    /// The machine being driven
+    pub fn chip8(&self) -> Ref<'_, Chip8> {
+        Ref::map(self.host.borrow(), |host| &host.chip8)
+    }
+
+    /// Stop driving the machine, handing it back
+    pub fn into_chip8(self) -> Chip8 {
+        drop(self.engine);
+        match Rc::try_unwrap(self.host) {
+            Ok(host) => host.into_inner().chip8,
+            Err(_) => unreachable!("the engine holding the other references was dropped"),
+        }
+    }
+
+    /// Whether the script called `stop()`
+    pub fn stopped(&self) -> bool {
+        self.host.borrow().stopped
+    }
+
+    /// Run a frame, calling the hooks along the way, returning `false` once the script stopped
+    pub fn run_frame(&mut self) -> Result<bool> {
+        let every_instruction = self.hooks.contains("on_instruction");
+        while !self.stopped() {
+            let mut resume = self.resume.take();
+            let stopped = {
+                let mut host = self.host.borrow_mut();
+                let Host {
+                    chip8, breakpoints, ..
+                } = &mut *host;
+                chip8.run_frame_until(|pc| {
+                    // the hooks for where execution resumes from have already run
+                    if resume.take() == Some(pc) {
+                        return false;
+                    }
+                    every_instruction || breakpoints.contains(&pc)
+                })?
+            };
+            if !stopped {
+                self.host.borrow_mut().frame += 1;
+                self.call("on_frame", ())?;
+                break;
+            }
+
+            let pc = self.host.borrow().chip8.state().pc();
+            self.resume = Some(pc);
+            if self.host.borrow().breakpoints.contains(&pc) {
+                self.call("on_breakpoint", (pc as INT,))?;
+            }
+            if every_instruction {
+                self.call("on_instruction", (pc as INT,))?;
+            }
+        }
+        Ok(!self.stopped())
+    }
+
+    /// Call the hook `name` if the script defines it
+    fn call(&mut self, name: &str, args: impl rhai::FuncArgs) -> Result<()> {
+        if !self.hooks.contains(name) {
+            return Ok(());
+        }
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut self.this);
+        self.engine
+            .call_fn_with_options::<Dynamic>(options, &mut self.scope, &self.ast, name, args)
+            .map(|_| ())
+            .map_err(|e| Chip8Error::Script(format!("in `{}`: {}", name, e)))
+    }
+}
+
+impl std::fmt::Debug for Script {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Script")
+            .field("hooks", &self.hooks)
+            .field("host", &self.host)
+            .finish()
+    }
+}
+
+/// Register the functions scripts use to reach the machine
+fn register(engine: &mut Engine, host: &Rc<RefCell<Host>>) {
+    // each function gets its own handle on the host
+    macro_rules! with_host {
+        ($name:expr, |$host:ident $(, $arg:ident: $ty:ty)*| $(-> $ret:ty)? $body:block) => {{
+            let handle = Rc::clone(host);
+            engine.register_fn($name, move |$($arg: $ty),*| $(-> $ret)? {
+                #[allow(unused_mut)]
+                let mut $host = handle.borrow_mut();
+                $body
+            });
+        }};
+        ($name:expr, |$host:ident $(, $arg:ident: $ty:ty)*| $body:expr) => {
+            with_host!($name, |$host $(, $arg: $ty)*| { $body })
+        };
+    }
+
+    with_host!("reg", |host, x: INT| -> ScriptResult<INT> {
+        Ok(host.chip8.state().reg(reg_index(x)?) as INT)
+    });
+    with_host!("set_reg", |host, x: INT, value: INT| -> ScriptResult<()> {
+        host.chip8.set_reg(reg_index(x)?, value as u8);
+        Ok(())
+    });
+    with_host!("i", |host| host.chip8.state().i() as INT);
+    with_host!("set_i", |host, value: INT| host.chip8.set_i(value as u16));
+    with_host!("pc", |host| host.chip8.state().pc() as INT);
+    with_host!("set_pc", |host, addr: INT| host.chip8.set_pc(addr as u16));
+    with_host!("dt", |host| host.chip8.state().dt() as INT);
+    with_host!("set_dt", |host, value: INT| host.chip8.set_dt(value as u8));
+    with_host!("st", |host| host.chip8.state().st() as INT);
+    with_host!("set_st", |host, value: INT| host.chip8.set_st(value as u8));
+
+    with_host!("peek", |host, addr: INT| -> ScriptResult<INT> {
+        Ok(host.chip8.state().memory()[ram_index(addr)?] as INT)
+    });
+    with_host!("poke", |host, addr: INT, value: INT| -> ScriptResult<()> {
+        let addr = ram_index(addr)? as u16;
+        host.chip8
+            .write_mem(addr, &[value as u8])
+            .map_err(|e| e.to_string().into())
+    });
+
+    with_host!("pixel", |host, x: INT, y: INT| {
+        let display = host.chip8.display();
+        let (w, h) = (display.width() as INT, display.height() as INT);
+        display.pixel(x.rem_euclid(w) as usize, y.rem_euclid(h) as usize) as INT
+    });
+    with_host!("screen", |host| {
+        let display = host.chip8.display();
+        let mut text = String::new();
+        for y in 0..display.height() {
+            for x in 0..display.width() {
+                text.push(if display.pixel(x, y) != 0 { '#' } else { '.' });
+            }
+            text.push('\n');
+        }
+        text
+    });
+
+    with_host!("press", |host, key: INT| host
+        .chip8
+        .keypad_mut()
+        .press(key as u8));
+    with_host!("release", |host, key: INT| host
+        .chip8
+        .keypad_mut()
+        .release(key as u8));
+    with_host!("is_pressed", |host, key: INT| host
+        .chip8
+        .keypad()
+        .is_pressed(key as u8));
+
+    with_host!("key_down", |host, name: &str| {
+        let Host { chip8, keymap, .. } = &mut *host;
+        keymap.key_down(name, chip8.keypad_mut());
+    });
+    with_host!("key_up", |host, name: &str| {
+        let Host { chip8, keymap, .. } = &mut *host;
+        keymap.key_up(name, chip8.keypad_mut());
+    });
+
+    with_host!("frame", |host| host.frame as INT);
+    with_host!("break_at", |host, addr: INT| {
+        host.breakpoints.insert(addr as u16);
+    });
+    with_host!("clear_break", |host, addr: INT| {
+        host.breakpoints.remove(&(addr as u16));
+    });
+    with_host!("stop", |host| host.stopped = true);
+}
+
+/// Check a register index given by a script
+fn reg_index(x: INT) -> ScriptResult<u8> {
+    match x {
+        0..=0xF => Ok(x as u8),
+        _ => Err(format!("no register V{}", x).into()),
+    }
+}
+
+/// Check an address given by a script
+fn ram_index(addr: INT) -> ScriptResult<usize> {
+    match usize::try_from(addr) {
+        Ok(addr) if addr < Ram::RAM_SIZE => Ok(addr),
+        _ => Err(format!("address {:#X} is outside of RAM", addr).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Emulator;
+
+    #[test]
+    fn hooks() {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load_rom_bytes(&[
+                0x70, 0x01, // 200: ADD V0, 01
+                0x12, 0x00, // 202: JP 200
+            ])
+            .unwrap();
+        let source = r#"
+            break_at(0x202);
+            poke(0x300, 0xAB);
+
+            fn on_breakpoint(addr) {
+                this.hits = (this.hits ?? 0) + 1;
+            }
+
+            fn on_frame() {
+                if frame() == 2 {
+                    set_reg(1, this.hits);
+                    stop();
+                }
+            }
+        "#;
+        let mut script = Script::new(source, chip8).unwrap();
+        assert!(script.run_frame().unwrap());
+        assert!(!script.run_frame().unwrap());
+
+        let chip8 = script.into_chip8();
+        // 10 instructions a frame, so V0 was incremented and the jump hit 5 times a frame
+        assert_eq!(chip8.regs[0x0], 10);
+        assert_eq!(chip8.regs[0x1], 10);
+        assert_eq!(chip8.ram[0x300], 0xAB);
+
+        let err = Script::new("reg(16)", Chip8::new()).unwrap_err();
+        assert!(err.to_string().contains("no register V16"));
+    }
+
+    #[test]
+    fn host_keys() {
+        let mut keymap = Keymap::default();
+        keymap.bind("Up", 0x5);
+        let source = r#"
+            key_down("w");
+            key_down("UP");
+            key_up("W");
+        "#;
+        let script = Script::with_keymap(source, Chip8::new(), keymap).unwrap();
+        assert!(script.chip8().keypad().is_pressed(0x5));
+
+        let script = Script::new(r#"key_down("Up");"#, Chip8::new()).unwrap();
+        assert!(!script.chip8().keypad().is_pressed(0x5));
+    }
+}
@@ -0,0 +1,270 @@
+//! Sprite viewer.
+//!
+//! [`SpriteSheet`] shows a range of memory as the sprites `DRW` would draw from it, so sprite
+//! data can be checked to be where the code thinks it is. Sprites are 8 pixels wide and 1 to 15
+//! rows high, or 16x16 from 32 bytes as SCHIP draws them for a height of 0. They are laid out in
+//! rows, each under its address, as text for the terminal:
+//!
+//! ```text
+//! 300       305
+//! ####....  ..#.....
+//! #..#....  .##.....
+//! #..#....  ..#.....
+//! #..#....  ..#.....
+//! ####....  .###....
+//! ```
+//!
+//! or as a PNG, with a grid between the sprites.
+//!
+//! [`SpriteSheet`]: struct.SpriteSheet.html
+
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use super::{
+    error::Result,
+    render::{Palette, Rgb},
+};
+
+/// Size of a sprite
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SpriteSize {
+    /// 8 pixels wide and 1 to 15 rows high, one byte a row
+    Small(u8),
+    /// 16x16, two bytes a row
+    Large,
+}
+
+impl SpriteSize {
+    /// Size of the sprites `DRW` draws with the height nibble `n`
+    pub fn from_nibble(n: u8) -> Self {
+        match n & 0xF {
+            0 => Self::Large,
+            n => Self::Small(n),
+        }
+    }
+
+    /// Width in pixels
+    pub fn width(self) -> usize {
+        match self {
+            Self::Small(_) => 8,
+            Self::Large => 16,
+        }
+    }
+
+    /// Height in pixels
+    pub fn height(self) -> usize {
+        match self {
+            Self::Small(height) => height as usize,
+            Self::Large => 16,
+        }
+    }
+
+    /// Bytes of memory a sprite takes up
+    pub fn bytes(self) -> usize {
+        self.width() / 8 * self.height()
+    }
+}
+
+impl Default for SpriteSize {
+    fn default() -> Self {
+        Self::Small(8)
+    }
+}
+
+impl FromStr for SpriteSize {
+    type Err = String;
+
+    /// Parse `8xN` for N from 1 to 15, or `16x16`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let err = || format!("invalid sprite size `{}`, expected `8xN` or `16x16`", s);
+        let (width, height) = s.split_once(['x', 'X']).ok_or_else(err)?;
+        match (width, height.parse()) {
+            ("8", Ok(height @ 1..=15)) => Ok(Self::Small(height)),
+            ("16", Ok(16)) => Ok(Self::Large),
+            _ => Err(err()),
+        }
+    }
+}
+
+impl fmt::Display for SpriteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width(), self.height())
+    }
+}
+
+/// Memory shown as sprites, see the [module documentation](index.html)
+#[derive(Debug, Clone)]
+pub struct SpriteSheet<'a> {
+    mem: &'a [u8],
+    addr: usize,
+    size: SpriteSize,
+    count: usize,
+    columns: usize,
+}
+
+impl<'a> SpriteSheet<'a> {
+    /// Create a `SpriteSheet` of a single sprite of `size` at `addr` of `mem`
+    pub fn new(mem: &'a [u8], addr: u16, size: SpriteSize) -> Self {
+        Self {
+            mem,
+            addr: addr as usize,
+            size,
+            count: 1,
+            columns: 8,
+        }
+    }
+
+    /// Show `count` consecutive sprites, as many as fit before the end of memory
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = count;
+        self
+    }
+
+    /// Lay out `columns` sprites a row, at least 1
+    pub fn columns(mut self, columns: usize) -> Self {
+        self.columns = columns.max(1);
+        self
+    }
+
+    /// Address and rows of each sprite shown, two bytes a row for 16x16 sprites
+    pub fn sprites(&self) -> impl Iterator<Item = (u16, &'a [u8])> + '_ {
+        let bytes = self.size.bytes();
+        let fit = self.mem.len().saturating_sub(self.addr) / bytes;
+        (0..self.count.min(fit)).map(move |idx| {
+            let start = self.addr + idx * bytes;
+            (start as u16, &self.mem[start..start + bytes])
+        })
+    }
+
+    /// Whether the pixel at `x`, `y` of `sprite` is set
+    fn pixel(&self, sprite: &[u8], x: usize, y: usize) -> bool {
+        let row_bytes = self.size.width() / 8;
+        sprite[y * row_bytes + x / 8] & (0x80 >> (x % 8)) != 0
+    }
+
+    /// Render the sprites as an RGB image, with every pixel scaled up to a `scale`x`scale`
+    /// square, and a one pixel grid in between
+    ///
+    /// Returns the width and height of the image with three bytes per pixel, row by row.
+    pub fn render(&self, palette: &Palette, scale: usize) -> (usize, usize, Vec<u8>) {
+        let scale = scale.max(1);
+        let sprites: Vec<_> = self.sprites().collect();
+        let columns = self.columns.min(sprites.len()).max(1);
+        let rows = sprites.len().div_ceil(columns).max(1);
+        let (cell_w, cell_h) = (
+            self.size.width() * scale + 1,
+            self.size.height() * scale + 1,
+        );
+        let (width, height) = (columns * cell_w + 1, rows * cell_h + 1);
+
+        let grid = blend(palette.background(), palette.foreground());
+        let mut rgb = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                let color = if x % cell_w == 0 || y % cell_h == 0 {
+                    grid
+                } else {
+                    let (cx, cy) = (x / cell_w, y / cell_h);
+                    let (px, py) = ((x % cell_w - 1) / scale, (y % cell_h - 1) / scale);
+                    match sprites.get(cy * columns + cx) {
+                        Some((_, sprite)) if self.pixel(sprite, px, py) => palette.foreground(),
+                        _ => palette.background(),
+                    }
+                };
+                rgb.extend_from_slice(&color);
+            }
+        }
+        (width, height, rgb)
+    }
+
+    /// Write the sprites as a PNG, see [`render`](#method.render)
+    pub fn write_png(&self, palette: &Palette, scale: usize, writer: impl Write) -> Result<()> {
+        let (width, height, rgb) = self.render(palette, scale);
+
+        let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&rgb)?;
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for SpriteSheet<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sprites: Vec<_> = self.sprites().collect();
+        let width = self.size.width();
+        for row in sprites.chunks(self.columns) {
+            let labels: Vec<_> = row
+                .iter()
+                .map(|(addr, _)| format!("{:<width$}", format!("{:03X}", addr), width = width))
+                .collect();
+            writeln!(f, "{}", labels.join("  ").trim_end())?;
+
+            for y in 0..self.size.height() {
+                let lines: Vec<String> = row
+                    .iter()
+                    .map(|(_, sprite)| {
+                        (0..width)
+                            .map(|x| if self.pixel(sprite, x, y) { '#' } else { '.' })
+                            .collect()
+                    })
+                    .collect();
+                writeln!(f, "{}", lines.join("  "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Colour halfway between `a` and `b`
+fn blend(a: Rgb, b: Rgb) -> Rgb {
+    [
+        ((a[0] as u16 + b[0] as u16) / 2) as u8,
+        ((a[1] as u16 + b[1] as u16) / 2) as u8,
+        ((a[2] as u16 + b[2] as u16) / 2) as u8,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size() {
+        assert_eq!("8x5".parse(), Ok(SpriteSize::Small(5)));
+        assert_eq!("16X16".parse(), Ok(SpriteSize::Large));
+        assert!("8x16".parse::<SpriteSize>().is_err());
+        assert!("16x8".parse::<SpriteSize>().is_err());
+        assert_eq!(SpriteSize::from_nibble(0), SpriteSize::Large);
+        assert_eq!(SpriteSize::Large.bytes(), 32);
+        assert_eq!(SpriteSize::Small(3).to_string(), "8x3");
+    }
+
+    #[test]
+    fn sheet() {
+        let mut mem = vec![0; 0x302];
+        mem[0x2FC..0x302].copy_from_slice(&[0xF0, 0x90, 0xF0, 0x20, 0x60, 0x20]);
+
+        let sheet = SpriteSheet::new(&mem, 0x2FC, SpriteSize::Small(3)).count(4);
+        assert_eq!(sheet.sprites().count(), 2);
+        assert_eq!(
+            sheet.to_string(),
+            "2FC       2FF\n####....  ..#.....\n#..#....  .##.....\n####....  ..#.....\n"
+        );
+
+        let palette = Palette::new([0, 0, 0], [0xFF, 0xFF, 0xFF]);
+        let (width, height, rgb) = sheet.render(&palette, 2);
+        assert_eq!((width, height), (2 * 17 + 1, 7 + 1));
+        // the grid, then the top left pixel of the first sprite
+        assert_eq!(&rgb[..3], &[0x7F, 0x7F, 0x7F]);
+        assert_eq!(&rgb[(width + 1) * 3..(width + 2) * 3], &[0xFF, 0xFF, 0xFF]);
+
+        let mut png = Vec::new();
+        sheet.write_png(&palette, 2, &mut png).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+    }
+}
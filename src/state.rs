@@ -0,0 +1,88 @@
+//! Public view of the machine state.
+//!
+//! [`MachineState`] reads the registers, stack, timers and memory of a [`Chip8`] without reaching
+//! into its internals, for frontends, debuggers and tests. Changes go through the setters of
+//! [`Chip8`]: [`set_reg`], [`set_i`], [`set_pc`], [`set_dt`], [`set_st`] and [`write_mem`].
+//!
+//! [`MachineState`]: struct.MachineState.html
+//! [`Chip8`]: ../struct.Chip8.html
+//! [`set_reg`]: ../struct.Chip8.html#method.set_reg
+//! [`set_i`]: ../struct.Chip8.html#method.set_i
+//! [`set_pc`]: ../struct.Chip8.html#method.set_pc
+//! [`set_dt`]: ../struct.Chip8.html#method.set_dt
+//! [`set_st`]: ../struct.Chip8.html#method.set_st
+//! [`write_mem`]: ../struct.Chip8.html#method.write_mem
+
+use super::{display::Display, keypad::Keypad, memory::Ram, Chip8};
+
+/// Read-only view of a [`Chip8`], see the [module documentation](index.html)
+///
+/// [`Chip8`]: ../struct.Chip8.html
+#[derive(Debug, Copy, Clone)]
+pub struct MachineState<'a> {
+    chip8: &'a Chip8,
+}
+
+impl<'a> MachineState<'a> {
+    pub(crate) fn new(chip8: &'a Chip8) -> Self {
+        Self { chip8 }
+    }
+
+    /// Get register `Vx`
+    ///
+    /// # Panics
+    ///
+    /// If `x` is above `0xF`.
+    pub fn reg(&self, x: u8) -> u8 {
+        self.chip8.regs[x]
+    }
+
+    /// Get the general purpose registers, `V0..VF`
+    pub fn regs(&self) -> [u8; 16] {
+        let mut regs = [0; 16];
+        for (x, reg) in regs.iter_mut().enumerate() {
+            *reg = self.chip8.regs[x as u8];
+        }
+        regs
+    }
+
+    /// Get the address register `I`
+    pub fn i(&self) -> u16 {
+        self.chip8.i
+    }
+
+    /// Get the program counter
+    pub fn pc(&self) -> u16 {
+        self.chip8.pc
+    }
+
+    /// Get the return addresses of the subroutines being executed, innermost last
+    pub fn stack(&self) -> &'a [u16] {
+        &self.chip8.stack[..self.chip8.sp as usize]
+    }
+
+    /// Get the delay timer
+    pub fn dt(&self) -> u8 {
+        self.chip8.dt
+    }
+
+    /// Get the sound timer
+    pub fn st(&self) -> u8 {
+        self.chip8.st
+    }
+
+    /// Get all of memory
+    pub fn memory(&self) -> &'a [u8] {
+        &self.chip8.ram[0..Ram::RAM_SIZE]
+    }
+
+    /// Get the display framebuffer
+    pub fn display(&self) -> &'a Display {
+        &self.chip8.display
+    }
+
+    /// Get the keypad
+    pub fn keypad(&self) -> &'a Keypad {
+        &self.chip8.keypad
+    }
+}
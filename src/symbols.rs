@@ -0,0 +1,253 @@
+//! Symbol files.
+//!
+//! A symbol file names addresses of a ROM and records which line of assembler source each
+//! instruction came from, so addresses can be shown as `draw_player+0x4` instead of `0x2A4` and
+//! a debugger can show source instead of disassembly. It is a text file with one entry per line,
+//! in any of the forms:
+//!
+//! ```text
+//! # labels, either way round
+//! 0x2A0      draw_player
+//! draw_enemy = 0x2C4
+//! # source lines
+//! 0x200      game.8o:12
+//! 0x202      game.8o:13
+//! ```
+//!
+//! Addresses are in hex, with or without a `0x` prefix. Blank lines and lines starting with `#`
+//! are ignored. Relative source paths are relative to the directory of the symbol file.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::error::{Chip8Error, Result};
+
+/// A line of source
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SourceLine {
+    /// Source file
+    pub path: PathBuf,
+    /// Line number, starting from 1
+    pub line: u32,
+}
+
+/// Source line of each instruction
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct LineMap {
+    lines: BTreeMap<u16, SourceLine>,
+}
+
+/// Labels and source lines of a ROM, see the [module documentation](index.html)
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Symbols {
+    /// Label of each address
+    labels: BTreeMap<u16, String>,
+    /// Address of each label
+    addrs: HashMap<String, u16>,
+    lines: LineMap,
+}
+
+impl LineMap {
+    /// Create an empty `LineMap`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that the instruction at `addr` came from `line` of `path`
+    pub fn insert(&mut self, addr: u16, path: impl Into<PathBuf>, line: u32) {
+        let path = path.into();
+        self.lines.insert(addr, SourceLine { path, line });
+    }
+
+    /// Whether the map is empty
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Get the source line of the instruction at `addr`
+    pub fn line(&self, addr: u16) -> Option<&SourceLine> {
+        self.lines.get(&addr)
+    }
+
+    /// Get the address of the first instruction generated from `line` of `path`
+    pub fn addr(&self, path: &Path, line: u32) -> Option<u16> {
+        self.lines
+            .iter()
+            .find(|(_, source)| source.line == line && same_file(&source.path, path))
+            .map(|(addr, _)| *addr)
+    }
+}
+
+impl Symbols {
+    /// Create an empty `Symbols`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a symbol file, resolving relative source paths against `base`
+    pub fn parse(text: &str, base: &Path) -> Result<Self> {
+        let mut symbols = Self::new();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |reason: &str| Chip8Error::Symbols {
+                line: idx + 1,
+                reason: reason.to_string(),
+            };
+
+            let fields: Vec<_> = line.split_whitespace().collect();
+            match fields[..] {
+                [label, "=", addr] => {
+                    let addr = parse_hex(addr).ok_or_else(|| err("invalid address"))?;
+                    symbols.insert_label(addr, label);
+                }
+                [addr, name] => {
+                    let addr = parse_hex(addr).ok_or_else(|| err("invalid address"))?;
+                    match name.rsplit_once(':') {
+                        Some((path, number)) => {
+                            let number = number.parse().map_err(|_| err("invalid line number"))?;
+                            symbols.lines.insert(addr, base.join(path), number);
+                        }
+                        None => symbols.insert_label(addr, name),
+                    }
+                }
+                _ => {
+                    return Err(err(
+                        "expected `ADDR LABEL`, `LABEL = ADDR` or `ADDR FILE:LINE`",
+                    ))
+                }
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// Read and parse the symbol file at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::parse(&fs::read_to_string(path)?, base)
+    }
+
+    /// Name `addr` `label`, replacing any label it had, and moving `label` if it named another
+    /// address
+    pub fn insert_label(&mut self, addr: u16, label: impl Into<String>) {
+        let label = label.into();
+        if let Some(old) = self.labels.insert(addr, label.clone()) {
+            self.addrs.remove(&old);
+        }
+        if let Some(old) = self.addrs.insert(label, addr) {
+            if old != addr {
+                self.labels.remove(&old);
+            }
+        }
+    }
+
+    /// Get the label of `addr`
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        self.labels.get(&addr).map(String::as_str)
+    }
+
+    /// Get the address of `label`
+    pub fn addr(&self, label: &str) -> Option<u16> {
+        self.addrs.get(label).copied()
+    }
+
+    /// Get the closest label at or before `addr`, and the offset of `addr` from it
+    pub fn locate(&self, addr: u16) -> Option<(&str, u16)> {
+        self.labels
+            .range(..=addr)
+            .next_back()
+            .map(|(start, label)| (label.as_str(), addr - start))
+    }
+
+    /// Describe `addr` by the closest label, e.g. `draw_player+0x4`, or in hex if no label
+    /// precedes it
+    pub fn describe(&self, addr: u16) -> String {
+        match self.locate(addr) {
+            Some((label, 0)) => label.to_string(),
+            Some((label, offset)) => format!("{}+{:#X}", label, offset),
+            None => format!("{:#05X}", addr),
+        }
+    }
+
+    /// Source lines of the instructions
+    pub fn lines(&self) -> &LineMap {
+        &self.lines
+    }
+
+    /// Source lines of the instructions, for editing
+    pub fn lines_mut(&mut self) -> &mut LineMap {
+        &mut self.lines
+    }
+}
+
+/// Parse a hex number, with or without a `0x` prefix
+fn parse_hex(s: &str) -> Option<u16> {
+    let hex = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u16::from_str_radix(hex, 16).ok()
+}
+
+/// Whether `a` and `b` refer to the same file
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_symbols() {
+        let text =
+            "# labels\n0x200 main\ndraw_player = 2A0\n\n0x200 game.8o:12\n202 lib/util.8o:3\n";
+        let symbols = Symbols::parse(text, Path::new("/src")).unwrap();
+
+        assert_eq!(symbols.label(0x2A0), Some("draw_player"));
+        assert_eq!(symbols.addr("main"), Some(0x200));
+        assert_eq!(symbols.describe(0x2A4), "draw_player+0x4");
+        assert_eq!(symbols.describe(0x200), "main");
+        assert_eq!(symbols.describe(0x1FE), "0x1FE");
+
+        let lines = symbols.lines();
+        assert_eq!(
+            lines.line(0x202),
+            Some(&SourceLine {
+                path: PathBuf::from("/src/lib/util.8o"),
+                line: 3
+            })
+        );
+        assert_eq!(lines.addr(Path::new("/src/game.8o"), 12), Some(0x200));
+        assert_eq!(lines.addr(Path::new("/src/game.8o"), 13), None);
+
+        assert!(matches!(
+            Symbols::parse("0x200 game.8o:x", Path::new("")),
+            Err(Chip8Error::Symbols { line: 1, .. })
+        ));
+        assert!(matches!(
+            Symbols::parse("main\n0x200", Path::new("")),
+            Err(Chip8Error::Symbols { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn move_label() {
+        let mut symbols = Symbols::new();
+        symbols.insert_label(0x200, "main");
+        symbols.insert_label(0x200, "start");
+        assert_eq!(symbols.addr("main"), None);
+
+        symbols.insert_label(0x300, "start");
+        assert_eq!(symbols.addr("start"), Some(0x300));
+        assert_eq!(symbols.label(0x200), None);
+        assert_eq!(symbols.describe(0x302), "start+0x2");
+    }
+}
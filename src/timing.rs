@@ -0,0 +1,86 @@
+//! Instruction timing models.
+//!
+//! By default every instruction takes the same time, the clock speed deciding how many run per
+//! frame. The COSMAC VIP's interpreter didn't work that way: instructions took anything from a
+//! handful to thousands of machine cycles, the display interrupt used part of every frame, and
+//! `DRW` waited for the interrupt before drawing. Some old games rely on that for their speed, so
+//! [`Timing::Vip`] charges each instruction its cost from the [`optable`] instead.
+//!
+//! The costs are approximations taken from analyses of the VIP interpreter, not measurements, and
+//! conditional extra cycles (e.g. a skip being taken) aren't modelled.
+//!
+//! [`Timing::Vip`]: enum.Timing.html#variant.Vip
+//! [`optable`]: ../optable/index.html
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::instruction::Instruction;
+
+/// Machine cycles per frame on the VIP, a 1.76MHz CPU taking 8 clock cycles per machine cycle
+pub const VIP_FRAME_CYCLES: u32 = 3668;
+/// Machine cycles of each frame taken by the display interrupt and DMA, approximately
+pub const VIP_INTERRUPT_CYCLES: u32 = 1100;
+/// Machine cycles the interpreter takes to fetch and decode an instruction
+const VIP_FETCH_CYCLES: u32 = 40;
+/// Machine cycles to copy a single register to or from memory in `Fx55` and `Fx65`
+const VIP_REG_COPY_CYCLES: u32 = 14;
+
+/// How long instructions take to execute
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Timing {
+    /// Every instruction takes the same time, set by the clock speed
+    #[default]
+    Fixed,
+    /// Each instruction takes as many machine cycles as on the COSMAC VIP, and `DRW` waits for
+    /// the next frame
+    Vip,
+}
+
+/// Machine cycles `inst` takes on the VIP, including fetch and decode
+pub fn vip_cycles(inst: &Instruction) -> u32 {
+    let base = VIP_FETCH_CYCLES + inst.def().cycles;
+    match inst.opcode().to_match_tuple() {
+        // one more register each than `x`
+        (0xF, x, 0x5..=0x6, 0x5) => base + VIP_REG_COPY_CYCLES * x as u32,
+        _ => base,
+    }
+}
+
+impl FromStr for Timing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fixed" => Ok(Self::Fixed),
+            "vip" | "cosmac-vip" => Ok(Self::Vip),
+            _ => Err(format!(
+                "unknown timing `{}`, expected one of fixed, vip",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fixed => write!(f, "fixed"),
+            Self::Vip => write!(f, "vip"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_round_trip() {
+        for timing in [Timing::Fixed, Timing::Vip] {
+            assert_eq!(timing.to_string().parse(), Ok(timing));
+        }
+        assert_eq!("COSMAC-VIP".parse(), Ok(Timing::Vip));
+        assert!("cosmac vip".parse::<Timing>().is_err());
+    }
+}
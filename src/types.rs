@@ -130,7 +130,7 @@ mod tests {
     fn addr_from() {
         let addr = Addr(0xEEF);
         assert_eq!(Addr::from(0xBEEF), addr);
-        assert_eq!(usize::from(addr), 0xEEF as usize);
+        assert_eq!(usize::from(addr), 0xEEF);
     }
 
     #[test]
@@ -143,7 +143,7 @@ mod tests {
     fn nibble_from() {
         let nib = Nibble(0x0F);
         assert_eq!(Nibble::from(0xAF), nib);
-        assert_eq!(usize::from(nib), 0x0F as usize);
+        assert_eq!(usize::from(nib), 0x0F);
     }
 
     #[test]